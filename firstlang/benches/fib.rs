@@ -0,0 +1,47 @@
+//! Compare the tree-walking interpreter with the bytecode VM on `fib(25)`
+//!
+//! Run with: cargo bench -p firstlang
+
+use std::time::{Duration, Instant};
+
+use firstlang::{parse, vm, Interpreter, Value};
+
+const SOURCE: &str = r#"
+def fib(n) {
+    if (n < 2) {
+        return n
+    } else {
+        return fib(n - 1) + fib(n - 2)
+    }
+}
+fib(25)
+"#;
+
+const ITERATIONS: u32 = 5;
+
+/// Run `f` a few times and return the fastest wall-clock time
+fn best_of(mut f: impl FnMut() -> Value) -> Duration {
+    (0..ITERATIONS)
+        .map(|_| {
+            let start = Instant::now();
+            assert_eq!(f(), Value::Int(75025));
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let program = parse(SOURCE).unwrap();
+
+    let interpreter = best_of(|| Interpreter::new().run(&program).unwrap());
+    let bytecode = vm::compile(&program).unwrap();
+    let vm = best_of(|| vm::VM::new(bytecode.clone()).run().unwrap());
+
+    println!("fib(25) tree-walking interpreter: {:?}", interpreter);
+    println!("fib(25) bytecode VM:              {:?}", vm);
+    println!(
+        "speedup: {:.1}x",
+        interpreter.as_secs_f64() / vm.as_secs_f64()
+    );
+}
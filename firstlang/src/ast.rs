@@ -4,12 +4,40 @@
 
 use std::fmt;
 
+use num_bigint::BigInt;
+
 /// A program is a list of statements
 pub type Program = Vec<Stmt>;
 
+/// A statement and the source lines it spans
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    /// 1-based line number, used by the debugger
+    pub line: usize,
+    /// Line the statement ends on, used by the formatter to place comments
+    pub end_line: usize,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, line: usize) -> Self {
+        Stmt {
+            kind,
+            line,
+            end_line: line,
+        }
+    }
+
+    /// Set the line the statement ends on
+    pub fn ending_at(mut self, end_line: usize) -> Self {
+        self.end_line = end_line;
+        self
+    }
+}
+
 /// Statements in our language
 #[derive(Debug, Clone, PartialEq)]
-pub enum Stmt {
+pub enum StmtKind {
     /// Function definition: def name(params) { body }
     Function {
         name: String,
@@ -18,8 +46,31 @@ pub enum Stmt {
     },
     /// Return statement: return expr
     Return(Expr),
+    /// Raise an exception: raise expr
+    Raise(Expr),
     /// Assignment: name = expr
-    Assignment { name: String, value: Expr },
+    Assignment {
+        name: String,
+        binding: Binding,
+        value: Expr,
+    },
+    /// Field update: name.field = expr (`fields` has one name per `.`)
+    FieldAssignment {
+        name: String,
+        binding: Binding,
+        fields: Vec<String>,
+        value: Expr,
+    },
+    /// Record declaration: record Name(fields), defining a constructor `Name`
+    Record { name: String, fields: Vec<String> },
+    /// Global declaration: global name, ...
+    Global(Vec<String>),
+    /// Import: `import "path.fl"`, or `from module import name, ...` when
+    /// `names` is not empty
+    Import {
+        path: String,
+        names: Vec<ImportName>,
+    },
     /// Expression statement (for side effects or final value)
     Expr(Expr),
 }
@@ -29,10 +80,14 @@ pub enum Stmt {
 pub enum Expr {
     /// Integer literal
     Int(i64),
+    /// Integer literal too large for 64 bits
+    BigInt(BigInt),
     /// Boolean literal
     Bool(bool),
+    /// The `None` literal
+    None,
     /// Variable reference
-    Var(String),
+    Var { name: String, binding: Binding },
     /// Unary operation: -x, !x
     Unary { op: UnaryOp, expr: Box<Expr> },
     /// Binary operation: x + y, x < y, etc.
@@ -42,7 +97,11 @@ pub enum Expr {
         right: Box<Expr>,
     },
     /// Function call: name(args)
-    Call { name: String, args: Vec<Expr> },
+    Call {
+        name: String,
+        binding: Binding,
+        args: Vec<Expr>,
+    },
     /// Conditional: if (cond) { then } else { else }
     If {
         cond: Box<Expr>,
@@ -53,6 +112,66 @@ pub enum Expr {
     While { cond: Box<Expr>, body: Vec<Stmt> },
     /// Block expression (returns last expression's value)
     Block(Vec<Stmt>),
+    /// Exception handler: try { body } catch (name) { handler }
+    Try {
+        body: Vec<Stmt>,
+        name: String,
+        binding: Binding,
+        handler: Vec<Stmt>,
+    },
+    /// Field access: expr.field
+    Field { expr: Box<Expr>, field: String },
+    /// A new record, made by the constructor of a `record` declaration
+    Record {
+        name: String,
+        fields: Vec<(String, Expr)>,
+    },
+}
+
+/// Body of the constructor function of `record name(fields)` declared on
+/// `line`, whose parameters are the fields
+pub fn constructor_body(name: &str, fields: &[String], line: usize) -> Vec<Stmt> {
+    let fields = fields
+        .iter()
+        .enumerate()
+        .map(|(slot, field)| {
+            let value = Expr::Var {
+                name: field.clone(),
+                binding: Binding::Local(slot),
+            };
+            (field.clone(), value)
+        })
+        .collect();
+    let record = Expr::Record {
+        name: name.to_string(),
+        fields,
+    };
+    vec![Stmt::new(StmtKind::Return(record), line)]
+}
+
+/// Where a variable lives at runtime, filled in by the resolver
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Binding {
+    /// Not resolved yet: looked up by name
+    #[default]
+    Unresolved,
+    /// Slot in the current frame (a function or block-local variable)
+    Local(usize),
+    /// Top-level variable: looked up by name
+    Global,
+    /// Function defined with `def`: looked up by name
+    Function,
+}
+
+/// A name brought into scope by `from module import name`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportName {
+    /// Name of the function or top-level variable in the imported module
+    pub name: String,
+    /// Name it is bound to in the importing file
+    ///
+    /// Same as `name`, except in modules, where the loader qualifies it.
+    pub alias: String,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -76,23 +195,50 @@ pub enum BinaryOp {
     Ge,
     Eq,
     Ne,
+    // `x is None`, `x is not None`
+    Is,
+    IsNot,
 }
 
-// Display implementations for pretty printing
+// Display implementations for one-line summaries (see `formatter` for full source)
 
 impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl fmt::Display for StmtKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Stmt::Function { name, params, body } => {
+            StmtKind::Function { name, params, body } => {
                 write!(f, "def {}({}) {{ ", name, params.join(", "))?;
                 for stmt in body {
                     write!(f, "{} ", stmt)?;
                 }
                 write!(f, "}}")
             }
-            Stmt::Return(expr) => write!(f, "return {}", expr),
-            Stmt::Assignment { name, value } => write!(f, "{} = {}", name, value),
-            Stmt::Expr(expr) => write!(f, "{}", expr),
+            StmtKind::Return(expr) => write!(f, "return {}", expr),
+            StmtKind::Raise(expr) => write!(f, "raise {}", expr),
+            StmtKind::Global(names) => write!(f, "global {}", names.join(", ")),
+            StmtKind::Import { path, names } if names.is_empty() => {
+                write!(f, "import \"{}\"", path)
+            }
+            StmtKind::Import { path, names } => {
+                let names: Vec<_> = names.iter().map(|n| n.name.as_str()).collect();
+                write!(f, "from \"{}\" import {}", path, names.join(", "))
+            }
+            StmtKind::Assignment { name, value, .. } => write!(f, "{} = {}", name, value),
+            StmtKind::FieldAssignment {
+                name,
+                fields,
+                value,
+                ..
+            } => write!(f, "{}.{} = {}", name, fields.join("."), value),
+            StmtKind::Record { name, fields } => {
+                write!(f, "record {}({})", name, fields.join(", "))
+            }
+            StmtKind::Expr(expr) => write!(f, "{}", expr),
         }
     }
 }
@@ -101,11 +247,13 @@ impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Expr::Int(n) => write!(f, "{}", n),
+            Expr::BigInt(n) => write!(f, "{}", n),
             Expr::Bool(b) => write!(f, "{}", b),
-            Expr::Var(name) => write!(f, "{}", name),
+            Expr::None => write!(f, "None"),
+            Expr::Var { name, .. } => write!(f, "{}", name),
             Expr::Unary { op, expr } => write!(f, "({}{})", op, expr),
             Expr::Binary { op, left, right } => write!(f, "({} {} {})", left, op, right),
-            Expr::Call { name, args } => {
+            Expr::Call { name, args, .. } => {
                 let args_str: Vec<_> = args.iter().map(|a| a.to_string()).collect();
                 write!(f, "{}({})", name, args_str.join(", "))
             }
@@ -116,6 +264,15 @@ impl fmt::Display for Expr {
                 write!(f, "while ({}) {{ ... }}", cond)
             }
             Expr::Block(_) => write!(f, "{{ ... }}"),
+            Expr::Try { name, .. } => write!(f, "try {{ ... }} catch ({}) {{ ... }}", name),
+            Expr::Field { expr, field } => write!(f, "{}.{}", expr, field),
+            Expr::Record { name, fields } => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(field, value)| format!("{}={}", field, value))
+                    .collect();
+                write!(f, "{}({})", name, fields.join(", "))
+            }
         }
     }
 }
@@ -143,6 +300,8 @@ impl fmt::Display for BinaryOp {
             BinaryOp::Ge => write!(f, ">="),
             BinaryOp::Eq => write!(f, "=="),
             BinaryOp::Ne => write!(f, "!="),
+            BinaryOp::Is => write!(f, "is"),
+            BinaryOp::IsNot => write!(f, "is not"),
         }
     }
 }
@@ -0,0 +1,364 @@
+//! Interactive step debugger for Firstlang
+//!
+//! A `Hook` that pauses the interpreter before a statement when a breakpoint
+//! is hit or a step finishes, and reads commands until told to go on:
+//!
+//! ```text
+//! s, step            Run to the next statement, entering calls
+//! n, next            Run to the next statement in this function (step over calls)
+//! o, out             Run until the current function returns
+//! c, continue        Run until the next breakpoint
+//! b, break <where>   Set a breakpoint at a line number or function name
+//! d, delete <where>  Remove a breakpoint
+//! l, locals [frame]  Print the variables of a frame (0 = innermost)
+//! p, print <name>    Print a variable of the innermost frame
+//! bt, backtrace      Print the call stack
+//! q, quit            Stop the program
+//! ```
+//!
+//! The debugger starts paused before the first statement.
+
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+use crate::ast::{Stmt, StmtKind};
+use crate::interpreter::{Hook, Interpreter};
+
+const HELP: &str = "\
+s, step            Run to the next statement, entering calls
+n, next            Run to the next statement in this function (step over calls)
+o, out             Run until the current function returns
+c, continue        Run until the next breakpoint
+b, break <where>   Set a breakpoint at a line number or function name
+d, delete <where>  Remove a breakpoint
+l, locals [frame]  Print the variables of a frame (0 = innermost)
+p, print <name>    Print a variable of the innermost frame
+bt, backtrace      Print the call stack
+q, quit            Stop the program";
+
+/// When to pause next, besides breakpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Before the next statement
+    Step,
+    /// Before the next statement at this call depth or shallower
+    Next(usize),
+    /// Before the next statement shallower than this call depth
+    Out(usize),
+    /// Only at breakpoints
+    Continue,
+    /// Never again (input ended)
+    Detached,
+}
+
+/// Step debugger reading commands from `input` and writing to `output`
+pub struct Debugger<R, W> {
+    input: R,
+    output: W,
+    mode: Mode,
+    lines: BTreeSet<usize>,
+    functions: BTreeSet<String>,
+    /// Function called since the previous statement, to notice function
+    /// entry even through tail calls, which keep the call depth
+    entered: Option<String>,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Debugger {
+            input,
+            output,
+            mode: Mode::Step,
+            lines: BTreeSet::new(),
+            functions: BTreeSet::new(),
+            entered: None,
+        }
+    }
+
+    fn should_pause(&self, interpreter: &Interpreter, stmt: &Stmt, entered: Option<&str>) -> bool {
+        let depth = interpreter.call_depth();
+        let stepped = match self.mode {
+            Mode::Step => true,
+            Mode::Next(from) => depth <= from,
+            Mode::Out(from) => depth < from,
+            Mode::Continue => false,
+            Mode::Detached => return false,
+        };
+        let at_function = entered.is_some_and(|function| self.functions.contains(function));
+        stepped || at_function || self.lines.contains(&stmt.line)
+    }
+
+    /// Read and run commands until one resumes the program
+    fn pause(&mut self, interpreter: &Interpreter, stmt: &Stmt) -> Result<(), String> {
+        let function = interpreter.current_function().unwrap_or("<main>");
+        self.say(format!("{}:{}: {}", function, stmt.line, summary(stmt)));
+
+        loop {
+            self.prompt();
+            let mut line = String::new();
+            match self.input.read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    // No more commands: let the program finish
+                    self.mode = Mode::Detached;
+                    return Ok(());
+                }
+                Ok(_) => {}
+            }
+
+            let line = line.trim();
+            let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+            let arg = arg.trim();
+            let depth = interpreter.call_depth();
+
+            match command {
+                "s" | "step" => self.mode = Mode::Step,
+                "n" | "next" => self.mode = Mode::Next(depth),
+                "o" | "out" => self.mode = Mode::Out(depth),
+                "c" | "continue" => self.mode = Mode::Continue,
+                "q" | "quit" => return Err("Stopped by debugger".to_string()),
+                "b" | "break" if arg.is_empty() => self.list_breakpoints(),
+                "b" | "break" => {
+                    match arg.parse::<usize>() {
+                        Ok(line) => self.lines.insert(line),
+                        Err(_) => self.functions.insert(arg.to_string()),
+                    };
+                    self.say(format!("Breakpoint at {}", arg));
+                }
+                "d" | "delete" => {
+                    let removed = match arg.parse::<usize>() {
+                        Ok(line) => self.lines.remove(&line),
+                        Err(_) => self.functions.remove(arg),
+                    };
+                    if !removed {
+                        self.say(format!("No breakpoint at {}", arg));
+                    }
+                }
+                "l" | "locals" => self.print_locals(interpreter, arg),
+                "p" | "print" => {
+                    let frames = interpreter.frames();
+                    // The innermost frame first, then the top-level variables
+                    let found = frames[0]
+                        .locals
+                        .iter()
+                        .chain(&frames[frames.len() - 1].locals)
+                        .find(|(name, _)| *name == arg);
+                    match found {
+                        Some((_, value)) => self.say(value.to_string()),
+                        None => self.say(format!("No variable named '{}'", arg)),
+                    }
+                }
+                "bt" | "backtrace" => {
+                    let frames: Vec<_> = interpreter
+                        .frames()
+                        .iter()
+                        .enumerate()
+                        .map(|(i, frame)| format!("#{} {}", i, frame.function.unwrap_or("<main>")))
+                        .collect();
+                    self.say(frames.join("\n"));
+                }
+                "h" | "help" => self.say(HELP.to_string()),
+                "" => {}
+                _ => self.say(format!("Unknown command '{}' (try help)", command)),
+            }
+
+            if matches!(
+                command,
+                "s" | "step" | "n" | "next" | "o" | "out" | "c" | "continue"
+            ) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn print_locals(&mut self, interpreter: &Interpreter, arg: &str) {
+        let frames = interpreter.frames();
+        let index = if arg.is_empty() { Ok(0) } else { arg.parse() };
+        match index.ok().and_then(|i: usize| frames.get(i)) {
+            Some(frame) if frame.locals.is_empty() => self.say("No variables".to_string()),
+            Some(frame) => {
+                let locals: Vec<_> = frame
+                    .locals
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect();
+                self.say(locals.join("\n"));
+            }
+            None => self.say(format!("No frame {}", arg)),
+        }
+    }
+
+    fn list_breakpoints(&mut self) {
+        let breakpoints: Vec<_> = self
+            .lines
+            .iter()
+            .map(|line| format!("line {}", line))
+            .chain(self.functions.iter().map(|f| format!("function {}", f)))
+            .collect();
+        if breakpoints.is_empty() {
+            self.say("No breakpoints".to_string());
+        } else {
+            self.say(breakpoints.join("\n"));
+        }
+    }
+
+    fn prompt(&mut self) {
+        // The debugger has nowhere to report its own output errors
+        let _ = write!(self.output, "(debug) ");
+        let _ = self.output.flush();
+    }
+
+    fn say(&mut self, text: String) {
+        let _ = writeln!(self.output, "{}", text);
+    }
+}
+
+impl<R: BufRead, W: Write> Hook for Debugger<R, W> {
+    fn before_stmt(&mut self, interpreter: &Interpreter, stmt: &Stmt) -> Result<(), String> {
+        let entered = self.entered.take();
+        if self.should_pause(interpreter, stmt, entered.as_deref()) {
+            self.pause(interpreter, stmt)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn enter_call(&mut self, function: &str) {
+        self.entered = Some(function.to_string());
+    }
+
+    fn exit_call(&mut self, _function: &str) {
+        // A function with an empty body was entered and left already
+        self.entered = None;
+    }
+}
+
+/// One-line description of a statement: a `def` without its body
+fn summary(stmt: &Stmt) -> String {
+    match &stmt.kind {
+        StmtKind::Function { name, params, .. } => {
+            format!("def {}({})", name, params.join(", "))
+        }
+        kind => kind.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Value;
+    use crate::parser::parse;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    /// Output shared with the test after the debugger is moved into the interpreter
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const SOURCE: &str = "\
+def square(n) {
+    result = n * n
+    return result
+}
+a = square(3)
+b = a + 1
+b";
+
+    /// Run `SOURCE` under the debugger with `commands`, returning the result
+    /// and everything the debugger printed (prompts removed)
+    fn debug(commands: &str) -> (Result<Value, String>, String) {
+        debug_source(SOURCE, commands)
+    }
+
+    fn debug_source(source: &str, commands: &str) -> (Result<Value, String>, String) {
+        let output = Output::default();
+        let mut interpreter = Interpreter::without_prelude();
+        let input = Cursor::new(commands.as_bytes().to_vec());
+        interpreter.set_hook(Box::new(Debugger::new(input, output.clone())));
+        let result = interpreter.run(&parse(source).unwrap());
+        let text = String::from_utf8(output.0.borrow().clone()).unwrap();
+        (result, text.replace("(debug) ", ""))
+    }
+
+    #[test]
+    fn test_step_into_call() {
+        let (result, output) = debug("s\ns\ns\nl\nc\n");
+        assert_eq!(result.unwrap(), Value::Int(10));
+        assert_eq!(
+            output,
+            "<main>:1: def square(n)\n\
+             <main>:5: a = square(3)\n\
+             square:2: result = (n * n)\n\
+             square:3: return result\n\
+             n = 3\nresult = 9\n"
+        );
+    }
+
+    #[test]
+    fn test_step_over_call() {
+        let (result, output) = debug("n\nn\nn\np a\nc\n");
+        assert_eq!(result.unwrap(), Value::Int(10));
+        assert_eq!(
+            output,
+            "<main>:1: def square(n)\n\
+             <main>:5: a = square(3)\n\
+             <main>:6: b = (a + 1)\n\
+             <main>:7: b\n\
+             9\n"
+        );
+    }
+
+    #[test]
+    fn test_step_out() {
+        let (_, output) = debug("b square\nc\no\n");
+        assert!(output.ends_with("square:2: result = (n * n)\n<main>:6: b = (a + 1)\n"));
+    }
+
+    #[test]
+    fn test_function_breakpoint_on_tail_call() {
+        let source = "\
+def finish(n) {
+    return n
+}
+def count(n) {
+    if (n == 0) {
+        return finish(n)
+    } else {
+        return count(n - 1)
+    }
+}
+count(2)";
+        let (result, output) = debug_source(source, "b count\nb finish\nc\nc\nc\nc\nc\n");
+        assert_eq!(result.unwrap(), Value::Int(0));
+        assert_eq!(output.matches("count:5: ").count(), 3);
+        assert!(output.ends_with("finish:2: return n\n"));
+    }
+
+    #[test]
+    fn test_line_breakpoint_and_backtrace() {
+        let (_, output) = debug("b 3\nc\nbt\nl 1\nc\n");
+        assert!(output.contains("square:3: return result\n#0 square\n#1 <main>\nNo variables\n"));
+    }
+
+    #[test]
+    fn test_quit_stops_program() {
+        let (result, _) = debug("q\n");
+        assert_eq!(result.unwrap_err(), "Stopped by debugger");
+    }
+
+    #[test]
+    fn test_end_of_input_lets_program_finish() {
+        let (result, _) = debug("");
+        assert_eq!(result.unwrap(), Value::Int(10));
+    }
+}
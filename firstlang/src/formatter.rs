@@ -0,0 +1,495 @@
+//! Source formatter for Firstlang
+//!
+//! Prints a parsed program back as canonical source: four-space indentation,
+//! one statement per line, spaces around binary operators and only the
+//! parentheses the precedence rules need. Comments are discarded by the
+//! grammar, so they are collected from the source text separately and put
+//! back by line number: a comment on its own line goes before the next
+//! statement, and a comment after code stays at the end of that line.
+//! Runs of blank lines between statements are kept as a single blank line.
+
+use crate::ast::{BinaryOp, Expr, Stmt, StmtKind};
+use crate::parser::parse;
+
+/// Names that cannot be written as a bare module name in an import
+const KEYWORDS: &[&str] = &[
+    "def", "if", "else", "while", "true", "false", "return", "global", "import", "from", "record",
+    "try", "catch", "raise", "None", "is",
+];
+
+/// Precedence of postfix expressions (calls, field access) and atoms
+const POSTFIX: u8 = 5;
+/// Precedence of `-x` and `!x`
+const UNARY: u8 = 4;
+
+/// Format Firstlang source code
+pub fn format(source: &str) -> Result<String, String> {
+    let program = parse(source)?;
+    Ok(format_program(&program, source))
+}
+
+/// Format a parsed program, taking comments and blank lines from `source`
+pub fn format_program(program: &[Stmt], source: &str) -> String {
+    let mut formatter = Formatter::new(source);
+    for stmt in program {
+        formatter.stmt(stmt, 0);
+    }
+    formatter.comments_before(usize::MAX, 0);
+    if !formatter.out.is_empty() {
+        formatter.out.push('\n');
+        // Keep one blank line at the end if the source had any
+        if source[source.trim_end().len()..].matches('\n').count() > 1 {
+            formatter.out.push('\n');
+        }
+    }
+    formatter.out
+}
+
+/// A `#` comment and the line it is on
+struct Comment {
+    line: usize,
+    text: String,
+}
+
+struct Formatter {
+    /// Comments in source order; the ones before `next` are printed
+    comments: Vec<Comment>,
+    next: usize,
+    /// Each source line with its comment removed
+    code: Vec<String>,
+    out: String,
+    /// Last source line printed, to tell where blank lines were
+    last_line: usize,
+    /// Nothing printed yet in the current block, so no blank line is needed
+    block_start: bool,
+    /// Lines of the statement being printed, bounding the blocks inside it
+    line: usize,
+    end_line: usize,
+}
+
+impl Formatter {
+    fn new(source: &str) -> Self {
+        let mut comments = Vec::new();
+        let mut code = Vec::new();
+        for (index, text) in source.lines().enumerate() {
+            // A `#` inside a module path string does not start a comment
+            let mut in_string = false;
+            let mut end = text.len();
+            for (pos, c) in text.char_indices() {
+                match c {
+                    '"' => in_string = !in_string,
+                    '#' if !in_string => {
+                        comments.push(Comment {
+                            line: index + 1,
+                            text: text[pos..].trim_end().to_string(),
+                        });
+                        end = pos;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            code.push(text[..end].to_string());
+        }
+
+        Formatter {
+            comments,
+            next: 0,
+            code,
+            out: String::new(),
+            last_line: 0,
+            block_start: true,
+            line: 0,
+            end_line: 0,
+        }
+    }
+
+    /// Start a new output line
+    fn newline(&mut self, indent: usize) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.out.push_str(&"    ".repeat(indent));
+        self.block_start = false;
+    }
+
+    /// Keep one blank line if the source had any between the last printed
+    /// line and `line`
+    fn blank_before(&mut self, line: usize) {
+        if self.block_start {
+            return;
+        }
+        let blank = (self.last_line + 1..line).any(|l| {
+            l >= 1
+                && self
+                    .code
+                    .get(l - 1)
+                    .is_some_and(|code| code.trim().is_empty())
+                && !self.comments.iter().any(|c| c.line == l)
+        });
+        if blank {
+            self.out.push('\n');
+        }
+    }
+
+    /// Print the comments on lines before `line` on lines of their own
+    fn comments_before(&mut self, line: usize, indent: usize) {
+        while self.next < self.comments.len() && self.comments[self.next].line < line {
+            let comment_line = self.comments[self.next].line;
+            self.blank_before(comment_line);
+            self.newline(indent);
+            let text = std::mem::take(&mut self.comments[self.next].text);
+            self.out.push_str(&text);
+            self.last_line = self.last_line.max(comment_line);
+            self.next += 1;
+        }
+    }
+
+    /// Take the comment at the end of `line`, if the next one is there
+    fn comment_on(&mut self, line: usize) -> Option<String> {
+        let comment = self.comments.get_mut(self.next)?;
+        if comment.line != line {
+            return None;
+        }
+        self.next += 1;
+        Some(std::mem::take(&mut comment.text))
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, indent: usize) {
+        self.comments_before(stmt.line, indent);
+        self.blank_before(stmt.line);
+        self.newline(indent);
+
+        let start = self.out.len();
+        let trailing = self.comment_on(stmt.line);
+        let outer = (self.line, self.end_line);
+        (self.line, self.end_line) = (stmt.line, stmt.end_line);
+
+        match &stmt.kind {
+            StmtKind::Function { name, params, body } => {
+                self.out
+                    .push_str(&format!("def {}({}) ", name, params.join(", ")));
+                self.block(body, indent);
+            }
+            StmtKind::Return(expr) => {
+                self.out.push_str("return ");
+                self.expr(expr, indent, 0);
+            }
+            StmtKind::Raise(expr) => {
+                self.out.push_str("raise ");
+                self.expr(expr, indent, 0);
+            }
+            StmtKind::Assignment { name, value, .. } => {
+                self.out.push_str(&format!("{} = ", name));
+                self.expr(value, indent, 0);
+            }
+            StmtKind::FieldAssignment {
+                name,
+                fields,
+                value,
+                ..
+            } => {
+                self.out
+                    .push_str(&format!("{}.{} = ", name, fields.join(".")));
+                self.expr(value, indent, 0);
+            }
+            StmtKind::Record { name, fields } => {
+                self.out
+                    .push_str(&format!("record {}({})", name, fields.join(", ")));
+            }
+            StmtKind::Global(names) => {
+                self.out.push_str(&format!("global {}", names.join(", ")));
+            }
+            StmtKind::Import { path, names } if names.is_empty() => {
+                self.out.push_str(&format!("import \"{}\"", path));
+            }
+            StmtKind::Import { path, names } => {
+                let names: Vec<_> = names.iter().map(|n| n.name.as_str()).collect();
+                self.out.push_str(&format!(
+                    "from {} import {}",
+                    module_name(path),
+                    names.join(", ")
+                ));
+            }
+            StmtKind::Expr(expr) => self.expr(expr, indent, 0),
+        }
+
+        // The comment after the first line of a multi-line statement
+        if let Some(comment) = trailing {
+            let end = match self.out[start..].find('\n') {
+                Some(pos) => start + pos,
+                None => self.out.len(),
+            };
+            self.out.insert_str(end, &format!("  {}", comment));
+        }
+        // ... and the one after its closing brace
+        if stmt.end_line > stmt.line {
+            if let Some(comment) = self.comment_on(stmt.end_line) {
+                self.out.push_str(&format!("  {}", comment));
+            }
+        }
+
+        (self.line, self.end_line) = outer;
+        self.last_line = self.last_line.max(stmt.end_line);
+    }
+
+    /// Print `{ stmts }` with the statements indented one level deeper
+    fn block(&mut self, stmts: &[Stmt], indent: usize) {
+        let close = self.closing_line(stmts);
+        self.out.push('{');
+        self.block_start = true;
+        let start = self.out.len();
+        for stmt in stmts {
+            self.stmt(stmt, indent + 1);
+        }
+        // Comments after the last statement of the block stay inside it
+        self.comments_before(close, indent + 1);
+        if self.out.len() > start {
+            self.newline(indent);
+        }
+        self.out.push('}');
+        self.block_start = false;
+        self.last_line = self.last_line.max(close);
+    }
+
+    /// Line of the `}` closing a block: the first one after its statements
+    fn closing_line(&self, stmts: &[Stmt]) -> usize {
+        let from = match stmts.last() {
+            Some(stmt) => stmt.end_line + 1,
+            None => self.last_line.max(self.line),
+        };
+        (from.max(1)..=self.end_line)
+            .find(|&l| self.code.get(l - 1).is_some_and(|code| code.contains('}')))
+            .unwrap_or(self.end_line)
+    }
+
+    /// Print an expression, in parentheses if it binds looser than `min`
+    fn expr(&mut self, expr: &Expr, indent: usize, min: u8) {
+        let prec = precedence(expr);
+        if prec < min {
+            self.out.push('(');
+        }
+
+        match expr {
+            Expr::Int(n) => self.out.push_str(&n.to_string()),
+            Expr::BigInt(n) => self.out.push_str(&n.to_string()),
+            Expr::Bool(b) => self.out.push_str(&b.to_string()),
+            Expr::None => self.out.push_str("None"),
+            Expr::Var { name, .. } => self.out.push_str(name),
+            Expr::Unary { op, expr } => {
+                self.out.push_str(&op.to_string());
+                self.expr(expr, indent, UNARY);
+            }
+            Expr::Binary { op, left, right } => {
+                // Operators are left-associative: `a - (b - c)` keeps its parentheses
+                self.expr(left, indent, prec);
+                self.out.push_str(&format!(" {} ", op));
+                self.expr(right, indent, prec + 1);
+            }
+            Expr::Call { name, args, .. } => {
+                self.out.push_str(name);
+                self.args(args.iter(), indent);
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.out.push_str("if (");
+                self.expr(cond, indent, 0);
+                self.out.push_str(") ");
+                self.block(then_branch, indent);
+                self.out.push_str(" else ");
+                self.block(else_branch, indent);
+            }
+            Expr::While { cond, body } => {
+                self.out.push_str("while (");
+                self.expr(cond, indent, 0);
+                self.out.push_str(") ");
+                self.block(body, indent);
+            }
+            Expr::Block(stmts) => self.block(stmts, indent),
+            Expr::Try {
+                body,
+                name,
+                handler,
+                ..
+            } => {
+                self.out.push_str("try ");
+                self.block(body, indent);
+                self.out.push_str(&format!(" catch ({}) ", name));
+                self.block(handler, indent);
+            }
+            Expr::Field { expr, field } => {
+                self.expr(expr, indent, POSTFIX);
+                self.out.push_str(&format!(".{}", field));
+            }
+            // Only made by constructors, so written as a constructor call
+            Expr::Record { name, fields } => {
+                self.out.push_str(name);
+                self.args(fields.iter().map(|(_, value)| value), indent);
+            }
+        }
+
+        if prec < min {
+            self.out.push(')');
+        }
+    }
+
+    fn args<'a>(&mut self, args: impl Iterator<Item = &'a Expr>, indent: usize) {
+        self.out.push('(');
+        for (i, arg) in args.enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expr(arg, indent, 0);
+        }
+        self.out.push(')');
+    }
+}
+
+/// How tightly an expression binds, from 0 (`if`, `while`, `try`) to `POSTFIX`
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::If { .. } | Expr::While { .. } | Expr::Try { .. } | Expr::Block(_) => 0,
+        Expr::Binary { op, .. } => match op {
+            BinaryOp::Lt
+            | BinaryOp::Gt
+            | BinaryOp::Le
+            | BinaryOp::Ge
+            | BinaryOp::Eq
+            | BinaryOp::Ne
+            | BinaryOp::Is
+            | BinaryOp::IsNot => 1,
+            BinaryOp::Add | BinaryOp::Sub => 2,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 3,
+        },
+        Expr::Unary { .. } => UNARY,
+        _ => POSTFIX,
+    }
+}
+
+/// How to write the module of a `from` import: `math` for the file `math.fl`
+/// next to the importer, the quoted path otherwise
+fn module_name(path: &str) -> String {
+    let bare = path.strip_suffix(".fl").filter(|name| {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !KEYWORDS.contains(name)
+    });
+    match bare {
+        Some(name) => name.to_string(),
+        None => format!("\"{}\"", path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_indents_blocks() {
+        let source = "def f(n){if(n<2){return n}else{return f(n-1)+f(n-2)}}\nf(10)";
+        let expected = "\
+def f(n) {
+    if (n < 2) {
+        return n
+    } else {
+        return f(n - 1) + f(n - 2)
+    }
+}
+f(10)
+";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_format_keeps_needed_parentheses_only() {
+        assert_eq!(format("x = (1 + 2) * 3").unwrap(), "x = (1 + 2) * 3\n");
+        assert_eq!(format("x = 1 + (2 * 3)").unwrap(), "x = 1 + 2 * 3\n");
+        assert_eq!(format("x = 1 - (2 - 3)").unwrap(), "x = 1 - (2 - 3)\n");
+        assert_eq!(format("x = -(a + b)").unwrap(), "x = -(a + b)\n");
+        assert_eq!(format("x = (f(1)).y").unwrap(), "x = f(1).y\n");
+        assert_eq!(
+            format("x = (a is   not None)").unwrap(),
+            "x = a is not None\n"
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_comments() {
+        let source = "\
+# Header
+
+x = 1   # one
+def f() {
+    # inside
+    return x
+    # at the end
+}  # after f
+
+
+y = 2
+";
+        let expected = "\
+# Header
+
+x = 1  # one
+def f() {
+    # inside
+    return x
+    # at the end
+}  # after f
+
+y = 2
+";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_format_else_comments_stay_in_else() {
+        let source = "if (c) {\n1\n# then\n} else {\n# else\n2\n}\n";
+        let expected = "if (c) {\n    1\n    # then\n} else {\n    # else\n    2\n}\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_format_new_constructs() {
+        let source = "\
+record P(x,y)
+import \"lib/math.fl\"
+from utils import a,b
+p=P(1,2)
+p.x=try{raise p.y}catch(e){e}
+";
+        let expected = "\
+record P(x, y)
+import \"lib/math.fl\"
+from utils import a, b
+p = P(1, 2)
+p.x = try {
+    raise p.y
+} catch (e) {
+    e
+}
+";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_format_keeps_import_paths_and_final_blank_line() {
+        let source = "import \"math.fl\"\nfrom \"math.fl\" import a\nimport b\n\n\n";
+        let expected = "import \"math.fl\"\nfrom math import a\nimport \"b.fl\"\n\n";
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_format_empty_blocks() {
+        assert_eq!(format("while (false) {}").unwrap(), "while (false) {}\n");
+        assert_eq!(format("").unwrap(), "");
+    }
+}
@@ -4,57 +4,240 @@
 //! - Variables and assignment
 //! - Functions with parameters
 //! - Recursion (via proper call stack)
+//! - Integers of any size: `Int` is promoted to `BigInt` on overflow
+//! - Records: `record Point(x, y)`, `p.x`, `p.x = 1`
+//! - Tail calls (`return f(...)`) in constant stack space
 //! - Control flow (if/else, while)
+//! - Exceptions (`raise`, `try`/`catch`), which unwind the call stack
 
 use std::collections::HashMap;
+use std::rc::Rc;
 
-use crate::ast::{BinaryOp, Expr, Program, Stmt, UnaryOp};
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+
+use crate::ast::{constructor_body, BinaryOp, Binding, Expr, Program, Stmt, StmtKind, UnaryOp};
+use crate::module::module_name;
+use crate::prelude::prelude;
+use crate::resolver::Resolver;
 
 /// Runtime values in our language
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Int(i64),
+    /// An integer outside the 64-bit range; smaller results become `Int` again
+    BigInt(BigInt),
     Bool(bool),
     /// A function value stores its parameter names and body
     Function {
         params: Vec<String>,
         body: Vec<Stmt>,
     },
-    /// Unit value (returned from statements with no value)
-    Unit,
+    /// A function value of the VM, which refers to the compiled function by
+    /// its index in `Bytecode::functions`
+    CompiledFunction {
+        index: usize,
+        params: Rc<[String]>,
+    },
+    /// A record made by the constructor of `record name(fields)`
+    ///
+    /// Records are values like integers: assigning one to another variable
+    /// copies it, and `==` compares the fields.
+    Record {
+        name: String,
+        fields: Vec<(String, Value)>,
+    },
+    /// A runtime error, such as division by zero, caught by `catch`
+    Error(String),
+    /// No value: the `None` literal, and the value of statements like
+    /// assignments
+    ///
+    /// When it is the result of a call, `returned_by` names the function,
+    /// so errors can say where a missing value came from.
+    None {
+        returned_by: Option<String>,
+    },
+}
+
+/// Values are equal when they hold the same data: which function returned
+/// a `None` does not matter
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::BigInt(a), Value::BigInt(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (
+                Value::Function { params, body },
+                Value::Function {
+                    params: other_params,
+                    body: other_body,
+                },
+            ) => params == other_params && body == other_body,
+            (
+                Value::CompiledFunction { index, .. },
+                Value::CompiledFunction {
+                    index: other_index, ..
+                },
+            ) => index == other_index,
+            (
+                Value::Record { name, fields },
+                Value::Record {
+                    name: other_name,
+                    fields: other_fields,
+                },
+            ) => name == other_name && fields == other_fields,
+            (Value::Error(a), Value::Error(b)) => a == b,
+            (Value::None { .. }, Value::None { .. }) => true,
+            _ => false,
+        }
+    }
 }
 
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Int(n) => write!(f, "{}", n),
+            Value::BigInt(n) => write!(f, "{}", n),
             Value::Bool(b) => write!(f, "{}", b),
             Value::Function { params, .. } => write!(f, "<function({})>", params.join(", ")),
-            Value::Unit => write!(f, "()"),
+            Value::CompiledFunction { params, .. } => {
+                write!(f, "<function({})>", params.join(", "))
+            }
+            Value::Record { name, fields } => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(field, value)| format!("{}={}", field, value))
+                    .collect();
+                write!(f, "{}({})", name, fields.join(", "))
+            }
+            Value::Error(message) => write!(f, "Error({:?})", message),
+            Value::None { .. } => write!(f, "None"),
+        }
+    }
+}
+
+impl Value {
+    /// `None`, not returned by any function
+    pub const NONE: Value = Value::None { returned_by: None };
+
+    /// An integer value, as `Int` whenever it fits in 64 bits
+    pub fn int(n: BigInt) -> Value {
+        match n.to_i64() {
+            Some(n) => Value::Int(n),
+            None => Value::BigInt(n),
+        }
+    }
+
+    fn is_none(&self) -> bool {
+        matches!(self, Value::None { .. })
+    }
+
+    /// The value a call to `function` returns: a `None` remembers where it
+    /// came from, unless another function returned it first
+    pub(crate) fn returned_by(self, function: &str) -> Value {
+        match self {
+            Value::None { returned_by: None } => Value::None {
+                returned_by: Some(function.to_string()),
+            },
+            value => value,
+        }
+    }
+
+    fn to_bigint(&self) -> BigInt {
+        match self {
+            Value::Int(n) => BigInt::from(*n),
+            Value::BigInt(n) => n.clone(),
+            _ => unreachable!("Only called on integers"),
         }
     }
 }
 
 /// An environment frame (for local variables in a function call)
+///
+/// Top-level variables are stored by name in the bottom frame's `locals`;
+/// every other variable has been resolved to a slot.
 #[derive(Debug, Clone)]
 struct Frame {
+    /// Function being called, `None` for the top level
+    function: Option<String>,
     locals: HashMap<String, Value>,
+    /// Function and block-local variables by slot, `None` until first assigned
+    slots: Vec<Option<Value>>,
+    /// Name of each slot assigned so far, for debugging
+    names: Vec<String>,
 }
 
 impl Frame {
     fn new() -> Self {
         Frame {
+            function: None,
             locals: HashMap::new(),
+            slots: Vec::new(),
+            names: Vec::new(),
         }
     }
 }
 
+/// A call frame as seen by a `Hook`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameInfo<'a> {
+    /// Function being called, `None` for the top level
+    pub function: Option<&'a str>,
+    /// Variables assigned so far and their values
+    pub locals: Vec<(&'a str, &'a Value)>,
+}
+
+/// Called by the interpreter before it executes each statement, and around
+/// every function call
+///
+/// This is what the debugger and the profiler are built on. Returning an
+/// error from `before_stmt` stops the program with that error.
+pub trait Hook {
+    fn before_stmt(&mut self, interpreter: &Interpreter, stmt: &Stmt) -> Result<(), String>;
+
+    /// A call to `function` starts, its arguments evaluated
+    fn enter_call(&mut self, _function: &str) {}
+
+    /// The innermost call returned, raised an exception or was replaced by
+    /// a tail call
+    fn exit_call(&mut self, _function: &str) {}
+}
+
 /// The interpreter state
 pub struct Interpreter {
-    /// Global environment (for functions and global variables)
+    /// Global environment (for functions)
     globals: HashMap<String, Value>,
     /// Call stack of local environments (for recursion support)
     call_stack: Vec<Frame>,
+    hook: Option<Box<dyn Hook>>,
+}
+
+/// Why evaluation stopped before finishing
+#[derive(Debug)]
+pub(crate) enum Unwind {
+    /// A value given to `raise`, or a runtime error as `Value::Error`,
+    /// travelling up to the nearest `catch`
+    Exception(Value),
+    /// The hook stopped the program: no `catch` sees this
+    Abort(String),
+}
+
+impl From<String> for Unwind {
+    fn from(message: String) -> Self {
+        Unwind::Exception(Value::Error(message))
+    }
+}
+
+/// The error reported when nothing catches an exception: a runtime error
+/// keeps its message
+impl From<Unwind> for String {
+    fn from(unwind: Unwind) -> Self {
+        match unwind {
+            Unwind::Exception(Value::Error(message)) | Unwind::Abort(message) => message,
+            Unwind::Exception(value) => format!("Uncaught exception: {}", value),
+        }
+    }
 }
 
 /// Control flow signals for the interpreter
@@ -63,32 +246,123 @@ enum ControlFlow {
     Continue(Value),
     /// Return statement encountered
     Return(Value),
+    /// `return f(...)`: the caller runs the call in place of the current one
+    TailCall(Call),
+}
+
+/// A function call whose arguments have been evaluated
+struct Call {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Stmt>,
+    args: Vec<Value>,
 }
 
 impl Interpreter {
+    /// Create an interpreter with the prelude's functions already defined
     pub fn new() -> Self {
+        let mut interpreter = Self::without_prelude();
+        interpreter.run(&prelude()).expect("The prelude should run");
+        interpreter
+    }
+
+    /// Create an interpreter with no functions defined
+    pub fn without_prelude() -> Self {
         Interpreter {
             globals: HashMap::new(),
             call_stack: vec![Frame::new()], // Start with one global frame
+            hook: None,
         }
     }
 
+    /// Call `hook` before every statement from now on
+    pub fn set_hook(&mut self, hook: Box<dyn Hook>) {
+        self.hook = Some(hook);
+    }
+
+    /// Number of active frames, 1 at the top level
+    pub fn call_depth(&self) -> usize {
+        self.call_stack.len()
+    }
+
+    /// Function of the innermost frame, `None` at the top level
+    pub fn current_function(&self) -> Option<&str> {
+        self.current_frame().function.as_deref()
+    }
+
+    /// The call stack, innermost frame first
+    pub fn frames(&self) -> Vec<FrameInfo<'_>> {
+        self.call_stack
+            .iter()
+            .rev()
+            .map(|frame| {
+                let mut locals: Vec<_> = frame
+                    .locals
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value))
+                    .collect();
+                locals.sort_by_key(|(name, _)| *name);
+                locals.extend(
+                    frame
+                        .names
+                        .iter()
+                        .zip(&frame.slots)
+                        .filter_map(|(name, value)| Some((name.as_str(), value.as_ref()?))),
+                );
+                FrameInfo {
+                    function: frame.function.as_deref(),
+                    locals,
+                }
+            })
+            .collect()
+    }
+
+    /// Top-level variables defined so far
+    pub fn variables(&self) -> &HashMap<String, Value> {
+        &self.call_stack[0].locals
+    }
+
+    /// Functions defined so far, including the prelude's
+    pub fn functions(&self) -> &HashMap<String, Value> {
+        &self.globals
+    }
+
     /// Run a complete program
+    ///
+    /// The program is resolved first, so undefined names are reported before
+    /// anything runs. Names defined by earlier calls to `run` stay visible.
     pub fn run(&mut self, program: &Program) -> Result<Value, String> {
-        let mut result = Value::Unit;
+        let mut program = program.clone();
+        Resolver::new()
+            .with_functions(self.globals.keys().cloned())
+            .with_variables(self.call_stack[0].locals.keys().cloned())
+            .resolve(&mut program)?;
+
+        self.exec_program(&program).map_err(String::from)
+    }
+
+    fn exec_program(&mut self, program: &Program) -> Result<Value, Unwind> {
+        let mut result = Value::NONE;
         for stmt in program {
             match self.exec_stmt(stmt)? {
                 ControlFlow::Continue(v) => result = v,
                 ControlFlow::Return(v) => return Ok(v),
+                ControlFlow::TailCall(call) => return self.call(call),
             }
         }
         Ok(result)
     }
 
     /// Execute a single statement
-    fn exec_stmt(&mut self, stmt: &Stmt) -> Result<ControlFlow, String> {
-        match stmt {
-            Stmt::Function { name, params, body } => {
+    fn exec_stmt(&mut self, stmt: &Stmt) -> Result<ControlFlow, Unwind> {
+        if let Some(mut hook) = self.hook.take() {
+            let result = hook.before_stmt(self, stmt);
+            self.hook = Some(hook);
+            result.map_err(Unwind::Abort)?;
+        }
+
+        match &stmt.kind {
+            StmtKind::Function { name, params, body } => {
                 // Store the function in globals
                 self.globals.insert(
                     name.clone(),
@@ -97,103 +371,96 @@ impl Interpreter {
                         body: body.clone(),
                     },
                 );
-                Ok(ControlFlow::Continue(Value::Unit))
+                Ok(ControlFlow::Continue(Value::NONE))
             }
 
-            Stmt::Return(expr) => {
+            // Leave the call to the caller so the current frame is gone first
+            StmtKind::Return(Expr::Call {
+                name,
+                binding,
+                args,
+            }) => Ok(ControlFlow::TailCall(
+                self.prepare_call(name, *binding, args)?,
+            )),
+
+            StmtKind::Return(expr) => {
                 let value = self.eval_expr(expr)?;
                 Ok(ControlFlow::Return(value))
             }
 
-            Stmt::Assignment { name, value } => {
-                let val = self.eval_expr(value)?;
-                // Assign to the current frame (local scope)
-                self.current_frame_mut().locals.insert(name.clone(), val);
-                Ok(ControlFlow::Continue(Value::Unit))
-            }
+            StmtKind::Raise(expr) => Err(Unwind::Exception(self.eval_expr(expr)?)),
 
-            Stmt::Expr(expr) => {
-                let value = self.eval_expr(expr)?;
-                Ok(ControlFlow::Continue(value))
+            StmtKind::Assignment {
+                name,
+                binding,
+                value,
+            } => {
+                let val = self.eval_expr(value)?;
+                self.assign(name, *binding, val);
+                Ok(ControlFlow::Continue(Value::NONE))
             }
-        }
-    }
 
-    /// Evaluate an expression
-    fn eval_expr(&mut self, expr: &Expr) -> Result<Value, String> {
-        match expr {
-            Expr::Int(n) => Ok(Value::Int(*n)),
-
-            Expr::Bool(b) => Ok(Value::Bool(*b)),
-
-            Expr::Var(name) => self.lookup_var(name),
-
-            Expr::Unary { op, expr } => {
-                let val = self.eval_expr(expr)?;
-                match (op, val) {
-                    (UnaryOp::Neg, Value::Int(n)) => Ok(Value::Int(-n)),
-                    (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
-                    (op, val) => Err(format!("Cannot apply {:?} to {:?}", op, val)),
+            // Rebuild the records along the path with the new field value
+            StmtKind::FieldAssignment {
+                name,
+                binding,
+                fields,
+                value,
+            } => {
+                let mut records = vec![self.lookup_var(name, *binding)?];
+                for field in &fields[..fields.len() - 1] {
+                    let inner = get_field(records.last().unwrap(), field)?;
+                    records.push(inner);
+                }
+                let mut val = self.eval_expr(value)?;
+                for (record, field) in records.into_iter().zip(fields).rev() {
+                    val = set_field(record, field, val)?;
                 }
+                self.assign(name, *binding, val);
+                Ok(ControlFlow::Continue(Value::NONE))
             }
 
-            Expr::Binary { op, left, right } => {
-                let l = self.eval_expr(left)?;
-                let r = self.eval_expr(right)?;
-                self.eval_binary_op(*op, l, r)
+            StmtKind::Record { name, fields } => {
+                self.globals.insert(
+                    name.clone(),
+                    Value::Function {
+                        params: fields.clone(),
+                        body: constructor_body(name, fields, stmt.line),
+                    },
+                );
+                Ok(ControlFlow::Continue(Value::NONE))
             }
 
-            Expr::Call { name, args } => {
-                // Look up the function
-                let func = self.lookup_var(name)?;
-
-                if let Value::Function { params, body } = func {
-                    // Evaluate arguments
-                    let arg_values: Vec<Value> = args
-                        .iter()
-                        .map(|a| self.eval_expr(a))
-                        .collect::<Result<_, _>>()?;
-
-                    // Check arity
-                    if params.len() != arg_values.len() {
-                        return Err(format!(
-                            "Function {} expects {} arguments, got {}",
-                            name,
-                            params.len(),
-                            arg_values.len()
-                        ));
-                    }
-
-                    // Create new frame for this call
-                    let mut frame = Frame::new();
-                    for (param, arg) in params.iter().zip(arg_values) {
-                        frame.locals.insert(param.clone(), arg);
-                    }
+            // Only affects name resolution
+            StmtKind::Global(_) => Ok(ControlFlow::Continue(Value::NONE)),
 
-                    // Push the new frame onto the call stack
-                    self.call_stack.push(frame);
-
-                    // Execute the function body
-                    let mut result = Value::Unit;
-                    for stmt in &body {
-                        match self.exec_stmt(stmt)? {
-                            ControlFlow::Continue(v) => result = v,
-                            ControlFlow::Return(v) => {
-                                // Pop the frame before returning
-                                self.call_stack.pop();
-                                return Ok(v);
-                            }
-                        }
+            // The module itself was loaded ahead of time; bind the imported names
+            StmtKind::Import { path, names } => {
+                let module = module_name(path);
+                for import in names {
+                    let qualified = format!("{}.{}", module, import.name);
+                    if let Some(function) = self.globals.get(&qualified).cloned() {
+                        self.globals.insert(import.alias.clone(), function);
+                    } else {
+                        let value = self.lookup_var(&qualified, Binding::Global)?;
+                        self.call_stack[0]
+                            .locals
+                            .insert(import.alias.clone(), value);
                     }
-
-                    // Pop the frame after normal completion
-                    self.call_stack.pop();
-                    Ok(result)
-                } else {
-                    Err(format!("{} is not a function", name))
                 }
+                Ok(ControlFlow::Continue(Value::NONE))
             }
 
+            StmtKind::Expr(expr) => self.exec_expr(expr),
+        }
+    }
+
+    /// Execute an expression in statement position
+    ///
+    /// Unlike `eval_expr`, a `return` inside an `if`, `while` or block body
+    /// is propagated so that it leaves the enclosing function.
+    fn exec_expr(&mut self, expr: &Expr) -> Result<ControlFlow, Unwind> {
+        match expr {
             Expr::If {
                 cond,
                 then_branch,
@@ -201,17 +468,9 @@ impl Interpreter {
             } => {
                 let cond_val = self.eval_expr(cond)?;
                 if let Value::Bool(b) = cond_val {
-                    let branch = if b { then_branch } else { else_branch };
-                    let mut result = Value::Unit;
-                    for stmt in branch {
-                        match self.exec_stmt(stmt)? {
-                            ControlFlow::Continue(v) => result = v,
-                            ControlFlow::Return(v) => return Ok(v),
-                        }
-                    }
-                    Ok(result)
+                    self.exec_block(if b { then_branch } else { else_branch })
                 } else {
-                    Err(format!("Condition must be boolean, got {:?}", cond_val))
+                    Err(format!("Condition must be boolean, got {}", describe(&cond_val)).into())
                 }
             }
 
@@ -222,48 +481,237 @@ impl Interpreter {
                         if !b {
                             break;
                         }
-                        for stmt in body {
-                            match self.exec_stmt(stmt)? {
-                                ControlFlow::Continue(_) => {}
-                                ControlFlow::Return(v) => return Ok(v),
-                            }
+                        match self.exec_block(body)? {
+                            ControlFlow::Continue(_) => {}
+                            flow => return Ok(flow),
                         }
                     } else {
                         return Err(format!(
-                            "While condition must be boolean, got {:?}",
-                            cond_val
-                        ));
+                            "While condition must be boolean, got {}",
+                            describe(&cond_val)
+                        )
+                        .into());
                     }
                 }
-                Ok(Value::Unit)
+                Ok(ControlFlow::Continue(Value::NONE))
             }
 
-            Expr::Block(stmts) => {
-                let mut result = Value::Unit;
-                for stmt in stmts {
-                    match self.exec_stmt(stmt)? {
-                        ControlFlow::Continue(v) => result = v,
-                        ControlFlow::Return(v) => return Ok(v),
+            Expr::Block(stmts) => self.exec_block(stmts),
+
+            Expr::Try {
+                body,
+                name,
+                binding,
+                handler,
+            } => {
+                let result = match self.exec_block(body) {
+                    // Run a `return f(...)` here, or `f` would run outside the `try`
+                    Ok(ControlFlow::TailCall(call)) => self.call(call).map(ControlFlow::Return),
+                    result => result,
+                };
+                // Frames of the calls that raised are already popped
+                match result {
+                    Err(Unwind::Exception(value)) => {
+                        self.assign(name, *binding, value);
+                        self.exec_block(handler)
                     }
+                    result => result,
                 }
-                Ok(result)
             }
+
+            _ => Ok(ControlFlow::Continue(self.eval_expr(expr)?)),
         }
     }
 
-    /// Look up a variable (check local frames first, then globals)
-    fn lookup_var(&self, name: &str) -> Result<Value, String> {
-        // Check the current frame first (local variables)
-        if let Some(val) = self.current_frame().locals.get(name) {
-            return Ok(val.clone());
+    /// Execute a list of statements, stopping early at a `return`
+    fn exec_block(&mut self, stmts: &[Stmt]) -> Result<ControlFlow, Unwind> {
+        let mut result = Value::NONE;
+        for stmt in stmts {
+            match self.exec_stmt(stmt)? {
+                ControlFlow::Continue(v) => result = v,
+                flow => return Ok(flow),
+            }
         }
+        Ok(ControlFlow::Continue(result))
+    }
+
+    /// Evaluate an expression
+    fn eval_expr(&mut self, expr: &Expr) -> Result<Value, Unwind> {
+        match expr {
+            Expr::Int(n) => Ok(Value::Int(*n)),
 
-        // Check globals (functions and global variables)
-        if let Some(val) = self.globals.get(name) {
-            return Ok(val.clone());
+            Expr::BigInt(n) => Ok(Value::BigInt(n.clone())),
+
+            Expr::Field { expr, field } => {
+                let record = self.eval_expr(expr)?;
+                Ok(get_field(&record, field)?)
+            }
+
+            Expr::Record { name, fields } => {
+                let fields = fields
+                    .iter()
+                    .map(|(field, value)| Ok((field.clone(), self.eval_expr(value)?)))
+                    .collect::<Result<_, String>>()?;
+                Ok(Value::Record {
+                    name: name.clone(),
+                    fields,
+                })
+            }
+
+            Expr::Bool(b) => Ok(Value::Bool(*b)),
+
+            Expr::None => Ok(Value::NONE),
+
+            Expr::Var { name, binding } => Ok(self.lookup_var(name, *binding)?),
+
+            Expr::Unary { op, expr } => {
+                let val = self.eval_expr(expr)?;
+                Ok(eval_unary_op(*op, val)?)
+            }
+
+            Expr::Binary { op, left, right } => {
+                let l = self.eval_expr(left)?;
+                let r = self.eval_expr(right)?;
+                Ok(eval_binary_op(*op, l, r)?)
+            }
+
+            Expr::Call {
+                name,
+                binding,
+                args,
+            } => {
+                let call = self.prepare_call(name, *binding, args)?;
+                self.call(call)
+            }
+
+            // In expression position a `return` inside the body becomes the
+            // value of the whole expression
+            Expr::If { .. } | Expr::While { .. } | Expr::Block(_) | Expr::Try { .. } => {
+                match self.exec_expr(expr)? {
+                    ControlFlow::Continue(v) | ControlFlow::Return(v) => Ok(v),
+                    ControlFlow::TailCall(call) => self.call(call),
+                }
+            }
+        }
+    }
+
+    /// Look up the function called `name` and evaluate the arguments
+    fn prepare_call(
+        &mut self,
+        name: &str,
+        binding: Binding,
+        args: &[Expr],
+    ) -> Result<Call, Unwind> {
+        let func = self.lookup_var(name, binding)?;
+
+        if let Value::Function { params, body } = func {
+            let args = args
+                .iter()
+                .map(|a| self.eval_expr(a))
+                .collect::<Result<_, _>>()?;
+            Ok(Call {
+                name: name.to_string(),
+                params,
+                body,
+                args,
+            })
+        } else {
+            Err(format!("{} is not a function", name).into())
+        }
+    }
+
+    /// Call a function, then every function it tail-calls, reusing one
+    /// frame of the call stack (and of the Rust stack) for all of them
+    fn call(&mut self, mut call: Call) -> Result<Value, Unwind> {
+        loop {
+            // Check arity
+            if call.params.len() != call.args.len() {
+                return Err(format!(
+                    "Function {} expects {} arguments, got {}",
+                    call.name,
+                    call.params.len(),
+                    call.args.len()
+                )
+                .into());
+            }
+
+            if let Some(hook) = self.hook.as_mut() {
+                hook.enter_call(&call.name);
+            }
+
+            // Create new frame for this call; parameters take the first slots
+            let mut frame = Frame::new();
+            frame.function = Some(call.name);
+            frame.slots = call.args.into_iter().map(Some).collect();
+            frame.names = call.params;
+
+            // Push the new frame onto the call stack
+            self.call_stack.push(frame);
+
+            // Execute the function body, popping the frame whether
+            // it returned, fell off the end or failed
+            let result = self.exec_block(&call.body);
+            let function = self
+                .call_stack
+                .pop()
+                .and_then(|frame| frame.function)
+                .unwrap_or_default();
+            if let Some(hook) = self.hook.as_mut() {
+                hook.exit_call(&function);
+            }
+            match result? {
+                ControlFlow::Continue(v) | ControlFlow::Return(v) => {
+                    return Ok(v.returned_by(&function))
+                }
+                ControlFlow::TailCall(next) => call = next,
+            }
         }
+    }
 
-        Err(format!("Undefined variable: {}", name))
+    /// Store a value where the resolver bound a variable
+    fn assign(&mut self, name: &str, binding: Binding, val: Value) {
+        match binding {
+            // Assign to the current frame (local scope)
+            Binding::Local(slot) => {
+                let frame = self.current_frame_mut();
+                if frame.slots.len() <= slot {
+                    frame.slots.resize(slot + 1, None);
+                }
+                frame.slots[slot] = Some(val);
+                if frame.names.len() <= slot {
+                    frame.names.resize(slot + 1, String::new());
+                }
+                if frame.names[slot].is_empty() {
+                    frame.names[slot] = name.to_string();
+                }
+            }
+            // Top-level variable, possibly declared `global` in a function
+            Binding::Global | Binding::Function | Binding::Unresolved => {
+                self.call_stack[0].locals.insert(name.to_string(), val);
+            }
+        }
+    }
+
+    /// Look up a variable where the resolver bound it
+    fn lookup_var(&self, name: &str, binding: Binding) -> Result<Value, String> {
+        let val = match binding {
+            // Locals live in a slot of the current frame
+            Binding::Local(slot) => self
+                .current_frame()
+                .slots
+                .get(slot)
+                .and_then(Option::as_ref),
+            // Top-level variables live in the bottom frame
+            Binding::Global => self.call_stack[0].locals.get(name),
+            // Functions live in globals
+            Binding::Function => self.globals.get(name),
+            Binding::Unresolved => self.call_stack[0]
+                .locals
+                .get(name)
+                .or_else(|| self.globals.get(name)),
+        };
+        val.cloned()
+            .ok_or_else(|| format!("Undefined variable: {}", name))
     }
 
     /// Get a reference to the current (top) frame
@@ -279,53 +727,166 @@ impl Interpreter {
             .last_mut()
             .expect("Call stack should never be empty")
     }
+}
 
-    /// Evaluate a binary operation
-    fn eval_binary_op(&self, op: BinaryOp, left: Value, right: Value) -> Result<Value, String> {
-        match (op, &left, &right) {
-            // Arithmetic operations (integers only)
-            (BinaryOp::Add, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a + b)),
-            (BinaryOp::Sub, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a - b)),
-            (BinaryOp::Mul, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a * b)),
-            (BinaryOp::Div, Value::Int(a), Value::Int(b)) => {
-                if *b == 0 {
-                    Err("Division by zero".to_string())
-                } else {
-                    Ok(Value::Int(a / b))
-                }
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Evaluate a unary operation
+pub(crate) fn eval_unary_op(op: UnaryOp, val: Value) -> Result<Value, String> {
+    match (op, val) {
+        (UnaryOp::Neg, Value::Int(n)) => Ok(promote(n.checked_neg(), || -BigInt::from(n))),
+        (UnaryOp::Neg, Value::BigInt(n)) => Ok(Value::int(-n)),
+        (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (op, val) => Err(format!("Cannot apply {:?} to {}", op, describe(&val))),
+    }
+}
+
+/// Evaluate a binary operation
+pub(crate) fn eval_binary_op(op: BinaryOp, left: Value, right: Value) -> Result<Value, String> {
+    match (op, &left, &right) {
+        // `x is None` holds for every None, whichever function returned it
+        (BinaryOp::Is, _, _) => Ok(Value::Bool(left.is_none() && right.is_none())),
+        (BinaryOp::IsNot, _, _) => Ok(Value::Bool(!(left.is_none() && right.is_none()))),
+
+        // Arithmetic operations (integers only), promoted to big integers on overflow
+        (BinaryOp::Add, Value::Int(a), Value::Int(b)) => {
+            Ok(promote(a.checked_add(*b), || BigInt::from(*a) + b))
+        }
+        (BinaryOp::Sub, Value::Int(a), Value::Int(b)) => {
+            Ok(promote(a.checked_sub(*b), || BigInt::from(*a) - b))
+        }
+        (BinaryOp::Mul, Value::Int(a), Value::Int(b)) => {
+            Ok(promote(a.checked_mul(*b), || BigInt::from(*a) * b))
+        }
+        (BinaryOp::Div, Value::Int(a), Value::Int(b)) => {
+            if *b == 0 {
+                Err("Division by zero".to_string())
+            } else {
+                Ok(promote(a.checked_div(*b), || BigInt::from(*a) / b))
             }
-            (BinaryOp::Mod, Value::Int(a), Value::Int(b)) => {
-                if *b == 0 {
-                    Err("Modulo by zero".to_string())
-                } else {
-                    Ok(Value::Int(a % b))
-                }
+        }
+        (BinaryOp::Mod, Value::Int(a), Value::Int(b)) => {
+            if *b == 0 {
+                Err("Modulo by zero".to_string())
+            } else {
+                Ok(promote(a.checked_rem(*b), || BigInt::from(*a) % b))
             }
+        }
 
-            // Comparison operations (integers)
-            (BinaryOp::Lt, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a < b)),
-            (BinaryOp::Gt, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a > b)),
-            (BinaryOp::Le, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a <= b)),
-            (BinaryOp::Ge, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a >= b)),
-            (BinaryOp::Eq, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a == b)),
-            (BinaryOp::Ne, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a != b)),
+        // Comparison operations (integers)
+        (BinaryOp::Lt, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a < b)),
+        (BinaryOp::Gt, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a > b)),
+        (BinaryOp::Le, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a <= b)),
+        (BinaryOp::Ge, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a >= b)),
+        (BinaryOp::Eq, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a == b)),
+        (BinaryOp::Ne, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a != b)),
 
-            // Boolean equality
-            (BinaryOp::Eq, Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a == b)),
-            (BinaryOp::Ne, Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a != b)),
+        // At least one big integer
+        (_, Value::Int(_) | Value::BigInt(_), Value::Int(_) | Value::BigInt(_)) => {
+            eval_bigint_op(op, left.to_bigint(), right.to_bigint())
+        }
 
-            // Type mismatch
-            _ => Err(format!(
-                "Cannot apply {:?} to {:?} and {:?}",
-                op, left, right
-            )),
+        // Structural equality of records and caught errors
+        (BinaryOp::Eq, Value::Record { .. }, Value::Record { .. })
+        | (BinaryOp::Eq, Value::Error(_), Value::Error(_)) => Ok(Value::Bool(left == right)),
+        (BinaryOp::Ne, Value::Record { .. }, Value::Record { .. })
+        | (BinaryOp::Ne, Value::Error(_), Value::Error(_)) => Ok(Value::Bool(left != right)),
+
+        // None is only equal to None, and can be compared with anything
+        (BinaryOp::Eq, Value::None { .. }, _) | (BinaryOp::Eq, _, Value::None { .. }) => {
+            Ok(Value::Bool(left == right))
+        }
+        (BinaryOp::Ne, Value::None { .. }, _) | (BinaryOp::Ne, _, Value::None { .. }) => {
+            Ok(Value::Bool(left != right))
         }
+
+        // Boolean equality
+        (BinaryOp::Eq, Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a == b)),
+        (BinaryOp::Ne, Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a != b)),
+
+        // Type mismatch
+        _ => Err(format!(
+            "Cannot apply {:?} to {} and {}",
+            op,
+            describe(&left),
+            describe(&right)
+        )),
     }
 }
 
-impl Default for Interpreter {
-    fn default() -> Self {
-        Self::new()
+/// A value as errors show it: a `None` names the function that returned it
+pub(crate) fn describe(value: &Value) -> String {
+    match value {
+        Value::None {
+            returned_by: Some(function),
+        } => format!("None (returned by {})", function),
+        Value::None { returned_by: None } => "None".to_string(),
+        value => format!("{:?}", value),
+    }
+}
+
+/// Read a field of a record
+pub(crate) fn get_field(record: &Value, field: &str) -> Result<Value, String> {
+    match record {
+        Value::Record { name, fields } => fields
+            .iter()
+            .find(|(f, _)| f == field)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| format!("Record {} has no field '{}'", name, field)),
+        value => Err(format!(
+            "Cannot access field '{}' of {}",
+            field,
+            describe(value)
+        )),
+    }
+}
+
+/// A copy of a record with one field changed
+pub(crate) fn set_field(record: Value, field: &str, value: Value) -> Result<Value, String> {
+    match record {
+        Value::Record { name, mut fields } => match fields.iter_mut().find(|(f, _)| f == field) {
+            Some((_, slot)) => {
+                *slot = value;
+                Ok(Value::Record { name, fields })
+            }
+            None => Err(format!("Record {} has no field '{}'", name, field)),
+        },
+        record => Err(format!(
+            "Cannot access field '{}' of {}",
+            field,
+            describe(&record)
+        )),
+    }
+}
+
+fn eval_bigint_op(op: BinaryOp, a: BigInt, b: BigInt) -> Result<Value, String> {
+    match op {
+        BinaryOp::Add => Ok(Value::int(a + b)),
+        BinaryOp::Sub => Ok(Value::int(a - b)),
+        BinaryOp::Mul => Ok(Value::int(a * b)),
+        BinaryOp::Div if b.is_zero() => Err("Division by zero".to_string()),
+        BinaryOp::Div => Ok(Value::int(a / b)),
+        BinaryOp::Mod if b.is_zero() => Err("Modulo by zero".to_string()),
+        BinaryOp::Mod => Ok(Value::int(a % b)),
+        BinaryOp::Lt => Ok(Value::Bool(a < b)),
+        BinaryOp::Gt => Ok(Value::Bool(a > b)),
+        BinaryOp::Le => Ok(Value::Bool(a <= b)),
+        BinaryOp::Ge => Ok(Value::Bool(a >= b)),
+        BinaryOp::Eq => Ok(Value::Bool(a == b)),
+        BinaryOp::Ne => Ok(Value::Bool(a != b)),
+        BinaryOp::Is | BinaryOp::IsNot => unreachable!("`is` is handled by eval_binary_op"),
+    }
+}
+
+/// The result of a checked 64-bit operation, or of `big` if it overflowed
+fn promote(result: Option<i64>, big: impl FnOnce() -> BigInt) -> Value {
+    match result {
+        Some(n) => Value::Int(n),
+        None => Value::int(big()),
     }
 }
 
@@ -496,4 +1057,91 @@ mod tests {
         "#;
         assert_eq!(run(source).unwrap(), Value::Int(20));
     }
+
+    #[test]
+    fn test_tail_call_reuses_frame() {
+        let source = r#"
+            def count(n) {
+                if (n == 0) {
+                    return 0
+                } else {
+                    return count(n - 1)
+                }
+            }
+            count(100000)
+        "#;
+        assert_eq!(run(source).unwrap(), Value::Int(0));
+    }
+
+    #[test]
+    fn test_tail_call_arity_error() {
+        let source = r#"
+            def f(n) {
+                return f(n, 1)
+            }
+            f(1)
+        "#;
+        assert_eq!(
+            run(source).unwrap_err(),
+            "Function f expects 1 arguments, got 2"
+        );
+    }
+
+    #[test]
+    fn test_overflow_promotes_to_big_integer() {
+        let big = |n: &str| Value::BigInt(n.parse().unwrap());
+        assert_eq!(
+            eval_binary_op(BinaryOp::Add, Value::Int(i64::MAX), Value::Int(1)).unwrap(),
+            big("9223372036854775808")
+        );
+        assert_eq!(
+            eval_binary_op(BinaryOp::Div, Value::Int(i64::MIN), Value::Int(-1)).unwrap(),
+            big("9223372036854775808")
+        );
+        assert_eq!(
+            eval_unary_op(UnaryOp::Neg, Value::Int(i64::MIN)).unwrap(),
+            big("9223372036854775808")
+        );
+        assert_eq!(
+            eval_binary_op(BinaryOp::Mod, Value::Int(i64::MIN), Value::Int(-1)).unwrap(),
+            Value::Int(0)
+        );
+    }
+
+    #[test]
+    fn test_big_integer_results_shrink_back() {
+        let big = eval_binary_op(BinaryOp::Mul, Value::Int(i64::MAX), Value::Int(2)).unwrap();
+        assert_eq!(
+            eval_binary_op(BinaryOp::Div, big, Value::Int(2)).unwrap(),
+            Value::Int(i64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_uncaught_exception_pops_frames() {
+        let mut interpreter = Interpreter::without_prelude();
+        let program =
+            parse("def f(n) { if (n == 0) { raise 1 } else { return 1 + f(n - 1) } }\nf(5)")
+                .unwrap();
+        assert!(interpreter.run(&program).is_err());
+        assert_eq!(interpreter.call_depth(), 1);
+    }
+
+    #[test]
+    fn test_hook_errors_are_not_caught() {
+        struct Stop;
+        impl Hook for Stop {
+            fn before_stmt(&mut self, _: &Interpreter, stmt: &Stmt) -> Result<(), String> {
+                match stmt.kind {
+                    StmtKind::Raise(_) => Err("Stopped".to_string()),
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let mut interpreter = Interpreter::without_prelude();
+        interpreter.set_hook(Box::new(Stop));
+        let program = parse("try { raise 1 } catch (e) { e }").unwrap();
+        assert_eq!(interpreter.run(&program).unwrap_err(), "Stopped");
+    }
 }
@@ -1,10 +1,12 @@
 //! Firstlang - A simple interpreted programming language
 //!
 //! Firstlang is a Python-like language that supports:
-//! - Integer and boolean values
+//! - Integer and boolean values, and `None`
 //! - Variables and assignment
 //! - Functions with recursion
 //! - Control flow (if/else, while)
+//! - Modules (`import "math.fl"`, `from math import square`)
+//! - A prelude of helpers written in Firstlang (`abs`, `max`, `min`, `pow`, `gcd`)
 //!
 //! This is an educational language designed to teach programming language
 //! implementation concepts without the complexity of type systems or compilation.
@@ -23,22 +25,52 @@
 //! fib(10)  # Returns 55
 //! ```
 
+use std::path::Path;
+
 extern crate pest;
 
 #[macro_use]
 extern crate pest_derive;
 
 pub mod ast;
+pub mod debugger;
+pub mod formatter;
 pub mod interpreter;
+pub mod module;
 pub mod parser;
+pub mod prelude;
+pub mod profiler;
+pub mod repl;
+pub mod resolver;
+pub mod session;
+pub mod vm;
 
-pub use ast::{Expr, Program, Stmt};
-pub use interpreter::{Interpreter, Value};
+pub use ast::{Expr, Program, Stmt, StmtKind};
+pub use formatter::format;
+pub use interpreter::{Hook, Interpreter, Value};
+pub use module::{load_file, Loader};
 pub use parser::parse;
+pub use resolver::resolve;
 
 /// Convenience function to run source code and get the result
+///
+/// Imports are relative to the current directory, and the prelude is defined.
 pub fn run(source: &str) -> Result<Value, String> {
-    let program = parse(source)?;
+    let program = Loader::new()
+        .with_functions(prelude::functions())
+        .load_source(source, Path::new(""))?;
     let mut interpreter = Interpreter::new();
     interpreter.run(&program)
 }
+
+/// Convenience function to run source code on the bytecode VM
+///
+/// The prelude is compiled along with the program, so both backends see the
+/// same functions.
+pub fn run_vm(source: &str) -> Result<Value, String> {
+    let program = Loader::new()
+        .with_functions(prelude::functions())
+        .load_source(source, Path::new(""))?;
+    let bytecode = vm::compile(&prelude::with_prelude(&program))?;
+    vm::VM::new(bytecode).run()
+}
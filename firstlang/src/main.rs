@@ -1,132 +1,272 @@
 //! Firstlang CLI - Run or interact with Firstlang programs
 //!
 //! Usage:
-//!   firstlang <file.fl>     Run a file
-//!   firstlang               Start REPL
+//!   firstlang <file.fl>         Run a file with the tree-walking interpreter
+//!   firstlang --vm <file.fl>    Run a file on the bytecode VM
+//!   firstlang --debug <file.fl> Run a file in the step debugger
+//!   firstlang --profile <file.fl> Run a file and print time spent per function
+//!   firstlang --folded <out> ... Also write the profile as folded stacks
+//!   firstlang --no-prelude      Do not define the prelude functions
+//!   firstlang fmt <file.fl>...  Format files in place (`--check` only reports them)
+//!   firstlang                   Start REPL
 
 use std::env;
 use std::fs;
-use std::io::{self, BufRead, Write};
+use std::io::{self, BufReader};
 
-use firstlang::{parse, Interpreter, Value};
+use firstlang::debugger::Debugger;
+use firstlang::profiler::Profiler;
+use firstlang::resolver::Resolver;
+use firstlang::vm::{self, VM};
+use firstlang::{prelude, Interpreter, Loader};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() > 1 {
+    if args.get(1).map(String::as_str) == Some("fmt") {
+        return fmt(&args[2..]);
+    }
+
+    let mut filename = None;
+    let mut use_vm = false;
+    let mut use_prelude = true;
+    let mut debug = false;
+    let mut profile = false;
+    let mut folded = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--vm" => use_vm = true,
+            "--no-prelude" => use_prelude = false,
+            "--debug" => debug = true,
+            "--profile" => profile = true,
+            "--folded" => {
+                i += 1;
+                match args.get(i) {
+                    Some(path) => folded = Some(path.clone()),
+                    None => {
+                        eprintln!("Error: --folded needs an output file");
+                        std::process::exit(1);
+                    }
+                }
+                profile = true;
+            }
+            "--help" | "-h" => {
+                print_usage();
+                return;
+            }
+            arg if !arg.starts_with('-') => {
+                filename = Some(arg.to_string());
+            }
+            _ => {
+                eprintln!("Unknown option: {}", args[i]);
+                std::process::exit(1);
+            }
+        }
+        i += 1;
+    }
+
+    match filename {
         // Run a file
-        let filename = &args[1];
-        run_file(filename);
-    } else {
+        Some(filename) => {
+            if debug && use_vm {
+                eprintln!("Error: --debug is only supported by the interpreter, not --vm");
+                std::process::exit(1);
+            }
+            if profile && use_vm {
+                eprintln!("Error: --profile is only supported by the interpreter, not --vm");
+                std::process::exit(1);
+            }
+            if profile && debug {
+                eprintln!("Error: --profile and --debug cannot be used together");
+                std::process::exit(1);
+            }
+            run_file(
+                &filename,
+                use_vm,
+                use_prelude,
+                debug,
+                profile,
+                folded.as_deref(),
+            )
+        }
         // Start REPL
-        repl();
+        None => repl(use_prelude),
+    }
+}
+
+fn print_usage() {
+    println!("Firstlang v0.1.0");
+    println!();
+    println!("Usage:");
+    println!("  firstlang <file.fl>         Run a file with the tree-walking interpreter");
+    println!("  firstlang --vm <file.fl>    Run a file on the bytecode VM");
+    println!("  firstlang --debug <file.fl> Run a file in the step debugger (type 'help' there)");
+    println!("  firstlang --profile <file.fl>  Print calls and time per function after running");
+    println!(
+        "  firstlang --folded <out> ...   Also write the profile as folded stacks for flamegraphs"
+    );
+    println!("  firstlang fmt <file.fl>...  Format files in place");
+    println!(
+        "  firstlang fmt --check ...   List files that are not formatted, without changing them"
+    );
+    println!("  firstlang                   Start REPL");
+    println!("  firstlang --no-prelude ...  Do not define the prelude functions (abs, max, ...)");
+    println!("  firstlang --help            Show this help");
+}
+
+/// Create an interpreter, with or without the prelude
+fn new_interpreter(use_prelude: bool) -> Interpreter {
+    if use_prelude {
+        Interpreter::new()
+    } else {
+        Interpreter::without_prelude()
+    }
+}
+
+/// Create a module loader, letting modules call the prelude functions if enabled
+fn new_loader(use_prelude: bool) -> Loader {
+    if use_prelude {
+        Loader::new().with_functions(prelude::functions())
+    } else {
+        Loader::new()
     }
 }
 
-fn run_file(filename: &str) {
-    let source = match fs::read_to_string(filename) {
-        Ok(s) => s,
+fn run_file(
+    filename: &str,
+    use_vm: bool,
+    use_prelude: bool,
+    debug: bool,
+    profile: bool,
+    folded: Option<&str>,
+) {
+    // Parse the file and the modules it imports
+    let mut program = match new_loader(use_prelude).load_file(filename) {
+        Ok(program) => program,
         Err(e) => {
-            eprintln!("Error reading file '{}': {}", filename, e);
+            eprintln!("Error: {}", e);
             std::process::exit(1);
         }
     };
 
-    match firstlang::run(&source) {
-        Ok(value) => println!("{}", value),
+    // Resolve names ahead of time so mistakes are reported before anything runs
+    let mut resolver = Resolver::new();
+    if use_prelude {
+        resolver = resolver.with_functions(prelude::functions());
+    }
+    match resolver.resolve(&mut program) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("Warning: {}", warning);
+            }
+        }
         Err(e) => {
             eprintln!("Error: {}", e);
             std::process::exit(1);
         }
     }
-}
 
-/// Count unmatched opening brackets in a string
-/// Returns the nesting depth (positive = unclosed brackets)
-fn bracket_depth(s: &str) -> i32 {
-    let mut depth = 0;
-    let mut in_string = false;
-    let mut prev_char = ' ';
-
-    for c in s.chars() {
-        // Handle string literals (skip brackets inside strings)
-        if c == '"' && prev_char != '\\' {
-            in_string = !in_string;
+    let result = if use_vm {
+        if use_prelude {
+            program = prelude::with_prelude(&program);
+        }
+        vm::compile(&program).and_then(|bytecode| VM::new(bytecode).run())
+    } else {
+        let mut interpreter = new_interpreter(use_prelude);
+        if debug {
+            println!("Debugging {}. Type 'help' for commands.", filename);
+            let input = BufReader::new(io::stdin());
+            interpreter.set_hook(Box::new(Debugger::new(input, io::stdout())));
         }
+        let profiler = Profiler::new();
+        if profile {
+            interpreter.set_hook(Box::new(profiler.clone()));
+        }
+        let result = interpreter.run(&program);
+        if profile {
+            report(&profiler, folded);
+        }
+        result
+    };
 
-        if !in_string {
-            match c {
-                '{' | '(' | '[' => depth += 1,
-                '}' | ')' | ']' => depth -= 1,
-                _ => {}
-            }
+    match result {
+        Ok(value) => println!("{}", value),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
         }
-        prev_char = c;
     }
-
-    depth
 }
 
-fn repl() {
-    println!("Firstlang REPL v0.1.0");
-    println!("Type expressions to evaluate, or 'quit' to exit.");
-    println!();
-
-    let mut interpreter = Interpreter::new();
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
-
-    loop {
-        print!(">>> ");
-        stdout.flush().unwrap();
-
-        let mut input = String::new();
-        let mut line = String::new();
-
-        // Read first line
-        if stdin.lock().read_line(&mut line).unwrap() == 0 {
-            // EOF
-            break;
-        }
-
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            continue;
+/// Print the profile to stderr, so it does not mix with the program's
+/// output, and write the folded stacks if asked to
+fn report(profiler: &Profiler, folded: Option<&str>) {
+    eprint!("{}", profiler.table());
+    if let Some(path) = folded {
+        if let Err(e) = fs::write(path, profiler.folded()) {
+            eprintln!("Error: Cannot write '{}': {}", path, e);
+            std::process::exit(1);
         }
+    }
+}
 
-        if trimmed == "quit" || trimmed == "exit" {
-            println!("Goodbye!");
-            break;
+/// Format files in place, or with `--check` list the ones that would change
+/// and fail if there are any
+fn fmt(args: &[String]) {
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            arg if !arg.starts_with('-') => files.push(arg.to_string()),
+            _ => {
+                eprintln!("Unknown option: {}", arg);
+                std::process::exit(1);
+            }
         }
+    }
+    if files.is_empty() {
+        eprintln!("Error: fmt needs at least one file");
+        std::process::exit(1);
+    }
 
-        input.push_str(&line);
-
-        // Check for multi-line input (unclosed brackets)
-        while bracket_depth(&input) > 0 {
-            print!("... ");
-            stdout.flush().unwrap();
-
-            line.clear();
-            if stdin.lock().read_line(&mut line).unwrap() == 0 {
-                // EOF while reading multi-line
-                break;
+    let mut unformatted = false;
+    for file in &files {
+        let formatted = fs::read_to_string(file)
+            .map_err(|e| format!("Cannot read '{}': {}", file, e))
+            .and_then(|source| {
+                let formatted =
+                    firstlang::format(&source).map_err(|e| format!("{}: {}", file, e))?;
+                Ok((source, formatted))
+            });
+        let (source, formatted) = match formatted {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
             }
-            input.push_str(&line);
+        };
+        if formatted == source {
+            continue;
         }
-
-        let input = input.trim();
-
-        // Try to parse and run the input
-        match parse(input) {
-            Ok(program) => match interpreter.run(&program) {
-                Ok(value) => {
-                    if value != Value::Unit {
-                        println!("{}", value);
-                    }
-                }
-                Err(e) => eprintln!("Runtime error: {}", e),
-            },
-            Err(e) => eprintln!("Parse error: {}", e),
+        if check {
+            println!("Would reformat: {}", file);
+            unformatted = true;
+        } else if let Err(e) = fs::write(file, formatted) {
+            eprintln!("Error: Cannot write '{}': {}", file, e);
+            std::process::exit(1);
         }
     }
+    if unformatted {
+        std::process::exit(1);
+    }
+}
+
+fn repl(use_prelude: bool) {
+    if let Err(e) = firstlang::repl::run(use_prelude) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,446 @@
+//! Module loader for Firstlang
+//!
+//! `import "math.fl"` makes the functions and top-level variables of
+//! `math.fl` available as `math.square` and `math.pi`, while
+//! `from math import square` binds `square` itself. Paths are relative to the
+//! importing file, and a bare module name (`import math`) stands for `math.fl`.
+//!
+//! Imports are handled before anything runs by flattening every module into a
+//! single program:
+//! - each module is parsed and resolved on its own, then every function and
+//!   top-level variable it defines is renamed to its qualified name
+//!   (`square` becomes `math.square`), so modules cannot clash,
+//! - the code of a module is placed before the code importing it, once, no
+//!   matter how many files import it,
+//! - import statements are kept: `from ... import` binds its names when it runs.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Binding, Expr, Program, Stmt, StmtKind};
+use crate::parser::parse;
+use crate::resolver::Resolver;
+
+/// Load a file and every module it imports into a single program
+pub fn load_file(path: impl AsRef<Path>) -> Result<Program, String> {
+    Loader::new().load_file(path)
+}
+
+/// Name a module's definitions are accessed through: its file name without extension
+pub fn module_name(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Module loader, caching every module it has loaded
+///
+/// Keeping a loader around (as the REPL does) means a module's code only
+/// runs the first time it is imported.
+#[derive(Default)]
+pub struct Loader {
+    /// Loaded modules by canonical path
+    modules: HashMap<PathBuf, Module>,
+    /// Canonical paths of the loaded modules, dependencies first
+    order: Vec<PathBuf>,
+    /// Files being loaded, outermost first, to detect cyclic imports
+    loading: Vec<PathBuf>,
+    /// Code of the modules loaded by the current `load_*` call, dependencies first
+    code: Program,
+    /// Modules whose code is in `code`
+    pending: Vec<PathBuf>,
+    /// Functions every module can call without importing them (the prelude)
+    functions: Vec<String>,
+}
+
+/// What a loaded module exports
+struct Module {
+    name: String,
+    /// Qualified names of the module's functions
+    functions: Vec<String>,
+    /// Qualified names of the module's top-level variables
+    variables: Vec<String>,
+}
+
+/// Names a module defines, before they are qualified
+#[derive(Default)]
+struct Definitions {
+    functions: HashSet<String>,
+    variables: HashSet<String>,
+    /// Names bound by `from ... import`
+    imports: HashSet<String>,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Treat `names` as functions that are already defined in every module
+    pub fn with_functions(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.functions.extend(names);
+        self
+    }
+
+    /// Canonical paths of the modules loaded so far, each after the modules
+    /// it imports
+    pub fn modules(&self) -> &[PathBuf] {
+        &self.order
+    }
+
+    /// Load a file, preceded by the code of the modules it imports
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<Program, String> {
+        let path = path.as_ref();
+        let source = read(path)?;
+
+        self.loading.push(canonicalize(path)?);
+        let result = self.load_source(&source, path.parent().unwrap_or(Path::new("")));
+        self.loading.pop();
+        result
+    }
+
+    /// Load source code whose imports are relative to `dir`
+    pub fn load_source(&mut self, source: &str, dir: &Path) -> Result<Program, String> {
+        let result = parse(source).and_then(|program| {
+            self.load_imports(&program, dir)?;
+            Ok(program)
+        });
+        let code = std::mem::take(&mut self.code);
+        let pending = std::mem::take(&mut self.pending);
+
+        match result {
+            Ok(program) => Ok(code.into_iter().chain(program).collect()),
+            Err(e) => {
+                // Their code never ran, so they must be loaded again next time
+                for path in pending {
+                    self.modules.remove(&path);
+                    self.order.retain(|p| *p != path);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Load the modules imported at the top level of a program, returning
+    /// the qualified names of their functions and variables
+    fn load_imports(
+        &mut self,
+        program: &Program,
+        dir: &Path,
+    ) -> Result<(Vec<String>, Vec<String>), String> {
+        let mut functions = Vec::new();
+        let mut variables = Vec::new();
+        for stmt in program {
+            if let StmtKind::Import { path, .. } = &stmt.kind {
+                let module = self.load_module(&dir.join(path))?;
+                functions.extend(module.functions.iter().cloned());
+                variables.extend(module.variables.iter().cloned());
+            }
+        }
+        Ok((functions, variables))
+    }
+
+    fn load_module(&mut self, path: &Path) -> Result<&Module, String> {
+        let canonical = canonicalize(path)?;
+
+        if let Some(start) = self.loading.iter().position(|p| *p == canonical) {
+            let cycle: Vec<_> = self.loading[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|p| p.file_name().unwrap_or_default().to_string_lossy())
+                .collect();
+            return Err(format!("Cyclic import: {}", cycle.join(" -> ")));
+        }
+        if self.modules.contains_key(&canonical) {
+            return Ok(&self.modules[&canonical]);
+        }
+
+        let name = module_name(&path.to_string_lossy());
+        if let Some((other, _)) = self.modules.iter().find(|(_, m)| m.name == name) {
+            return Err(format!(
+                "Cannot import '{}': module name '{}' is already used by '{}'",
+                path.display(),
+                name,
+                other.display()
+            ));
+        }
+
+        let source = read(path)?;
+        self.loading.push(canonical.clone());
+        let result = self.load_module_source(&name, &source, path);
+        self.loading.pop();
+        let (module, code) = result?;
+
+        self.code.extend(code);
+        self.pending.push(canonical.clone());
+        self.order.push(canonical.clone());
+        self.modules.insert(canonical.clone(), module);
+        Ok(&self.modules[&canonical])
+    }
+
+    /// Resolve a module on its own and qualify the names it defines
+    fn load_module_source(
+        &mut self,
+        name: &str,
+        source: &str,
+        path: &Path,
+    ) -> Result<(Module, Program), String> {
+        let in_module = |e: String| format!("{}: {}", path.display(), e);
+
+        let mut program = parse(source).map_err(in_module)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let (functions, variables) = self.load_imports(&program, dir)?;
+
+        // Warnings are reported when the whole program is resolved
+        Resolver::new()
+            .with_functions(self.functions.iter().cloned().chain(functions))
+            .with_variables(variables)
+            .resolve(&mut program)
+            .map_err(in_module)?;
+
+        let mut definitions = Definitions::default();
+        collect_definitions(&program, &mut definitions);
+
+        let qualified: HashSet<_> = definitions
+            .functions
+            .iter()
+            .chain(&definitions.variables)
+            .chain(&definitions.imports)
+            .cloned()
+            .collect();
+        qualify(&mut program, name, &qualified);
+
+        let qualify_all = |names: &HashSet<String>| {
+            let mut names: Vec<_> = names.iter().map(|n| format!("{}.{}", name, n)).collect();
+            names.sort();
+            names
+        };
+        let module = Module {
+            name: name.to_string(),
+            functions: qualify_all(&definitions.functions),
+            variables: qualify_all(&definitions.variables),
+        };
+        Ok((module, program))
+    }
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Cannot read '{}': {}", path.display(), e))
+}
+
+fn canonicalize(path: &Path) -> Result<PathBuf, String> {
+    path.canonicalize()
+        .map_err(|e| format!("Cannot read '{}': {}", path.display(), e))
+}
+
+/// Collect the functions and top-level variables a resolved module defines
+fn collect_definitions(stmts: &[Stmt], definitions: &mut Definitions) {
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::Function { name, body, .. } => {
+                definitions.functions.insert(name.clone());
+                collect_definitions(body, definitions);
+            }
+            StmtKind::Assignment {
+                name,
+                binding,
+                value,
+            } => {
+                if *binding == Binding::Global {
+                    definitions.variables.insert(name.clone());
+                }
+                collect_definitions_expr(value, definitions);
+            }
+            StmtKind::FieldAssignment { value, .. } => collect_definitions_expr(value, definitions),
+            StmtKind::Record { name, .. } => {
+                definitions.functions.insert(name.clone());
+            }
+            StmtKind::Global(names) => definitions.variables.extend(names.iter().cloned()),
+            StmtKind::Import { names, .. } => definitions
+                .imports
+                .extend(names.iter().map(|import| import.alias.clone())),
+            StmtKind::Return(expr) | StmtKind::Raise(expr) | StmtKind::Expr(expr) => {
+                collect_definitions_expr(expr, definitions)
+            }
+        }
+    }
+}
+
+fn collect_definitions_expr(expr: &Expr, definitions: &mut Definitions) {
+    match expr {
+        Expr::Int(_) | Expr::BigInt(_) | Expr::Bool(_) | Expr::None | Expr::Var { .. } => {}
+        Expr::Unary { expr, .. } => collect_definitions_expr(expr, definitions),
+        Expr::Binary { left, right, .. } => {
+            collect_definitions_expr(left, definitions);
+            collect_definitions_expr(right, definitions);
+        }
+        Expr::Call { args, .. } => {
+            for arg in args {
+                collect_definitions_expr(arg, definitions);
+            }
+        }
+        Expr::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            collect_definitions_expr(cond, definitions);
+            collect_definitions(then_branch, definitions);
+            collect_definitions(else_branch, definitions);
+        }
+        Expr::While { cond, body } => {
+            collect_definitions_expr(cond, definitions);
+            collect_definitions(body, definitions);
+        }
+        Expr::Block(stmts) => collect_definitions(stmts, definitions),
+        Expr::Try { body, handler, .. } => {
+            collect_definitions(body, definitions);
+            collect_definitions(handler, definitions);
+        }
+        Expr::Field { expr, .. } => collect_definitions_expr(expr, definitions),
+        Expr::Record { fields, .. } => {
+            for (_, value) in fields {
+                collect_definitions_expr(value, definitions);
+            }
+        }
+    }
+}
+
+/// Rename every function and top-level variable in `names` to `module.name`
+///
+/// Locals are left alone, even when they have the same name.
+fn qualify(stmts: &mut [Stmt], module: &str, names: &HashSet<String>) {
+    let rename = |name: &mut String| {
+        if names.contains(name.as_str()) {
+            *name = format!("{}.{}", module, name);
+        }
+    };
+
+    for stmt in stmts {
+        match &mut stmt.kind {
+            StmtKind::Function { name, body, .. } => {
+                rename(name);
+                qualify(body, module, names);
+            }
+            StmtKind::Assignment {
+                name,
+                binding,
+                value,
+            } => {
+                if !matches!(binding, Binding::Local(_)) {
+                    rename(name);
+                }
+                qualify_expr(value, module, names);
+            }
+            StmtKind::FieldAssignment {
+                name,
+                binding,
+                value,
+                ..
+            } => {
+                if !matches!(binding, Binding::Local(_)) {
+                    rename(name);
+                }
+                qualify_expr(value, module, names);
+            }
+            StmtKind::Record { name, .. } => rename(name),
+            StmtKind::Global(globals) => globals.iter_mut().for_each(rename),
+            StmtKind::Import { names: imports, .. } => imports
+                .iter_mut()
+                .for_each(|import| rename(&mut import.alias)),
+            StmtKind::Return(expr) | StmtKind::Raise(expr) | StmtKind::Expr(expr) => {
+                qualify_expr(expr, module, names)
+            }
+        }
+    }
+}
+
+fn qualify_expr(expr: &mut Expr, module: &str, names: &HashSet<String>) {
+    match expr {
+        Expr::Int(_) | Expr::BigInt(_) | Expr::Bool(_) | Expr::None => {}
+        Expr::Var { name, binding } => {
+            if !matches!(binding, Binding::Local(_)) && names.contains(name.as_str()) {
+                *name = format!("{}.{}", module, name);
+            }
+        }
+        Expr::Unary { expr, .. } => qualify_expr(expr, module, names),
+        Expr::Binary { left, right, .. } => {
+            qualify_expr(left, module, names);
+            qualify_expr(right, module, names);
+        }
+        Expr::Call {
+            name,
+            binding,
+            args,
+        } => {
+            if !matches!(binding, Binding::Local(_)) && names.contains(name.as_str()) {
+                *name = format!("{}.{}", module, name);
+            }
+            for arg in args {
+                qualify_expr(arg, module, names);
+            }
+        }
+        Expr::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            qualify_expr(cond, module, names);
+            qualify(then_branch, module, names);
+            qualify(else_branch, module, names);
+        }
+        Expr::While { cond, body } => {
+            qualify_expr(cond, module, names);
+            qualify(body, module, names);
+        }
+        Expr::Block(stmts) => qualify(stmts, module, names),
+        Expr::Try { body, handler, .. } => {
+            qualify(body, module, names);
+            qualify(handler, module, names);
+        }
+        Expr::Field { expr, .. } => qualify_expr(expr, module, names),
+        Expr::Record { fields, .. } => {
+            for (_, value) in fields {
+                qualify_expr(value, module, names);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_name() {
+        assert_eq!(module_name("math.fl"), "math");
+        assert_eq!(module_name("lib/utils.fl"), "utils");
+    }
+
+    #[test]
+    fn test_definitions_are_qualified() {
+        let mut program = parse(
+            "scale = 2\ndef twice(n) { global scale\nreturn n * scale }\ndef f(twice) { return twice }",
+        )
+        .unwrap();
+        Resolver::new().resolve(&mut program).unwrap();
+        let mut definitions = Definitions::default();
+        collect_definitions(&program, &mut definitions);
+        let names = definitions
+            .functions
+            .union(&definitions.variables)
+            .cloned()
+            .collect();
+        qualify(&mut program, "math", &names);
+
+        assert_eq!(program[0].to_string(), "math.scale = 2",);
+        assert_eq!(
+            program[1].to_string(),
+            "def math.twice(n) { global math.scale return (n * math.scale) }"
+        );
+        // The parameter shadows the function and keeps its name
+        assert_eq!(program[2].to_string(), "def math.f(twice) { return twice }");
+    }
+}
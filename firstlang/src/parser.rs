@@ -2,10 +2,11 @@
 //!
 //! Converts source code into an AST using the pest parser generator.
 
+use pest::error::InputLocation;
 use pest::iterators::Pair;
 use pest::Parser;
 
-use crate::ast::{BinaryOp, Expr, Program, Stmt, UnaryOp};
+use crate::ast::{BinaryOp, Binding, Expr, ImportName, Program, Stmt, StmtKind, UnaryOp};
 
 #[derive(Parser)]
 #[grammar = "grammar.pest"]
@@ -29,22 +30,59 @@ pub fn parse(source: &str) -> Result<Program, String> {
     Ok(program)
 }
 
+/// Whether parsing `source` fails only because the input ends too early
+///
+/// The REPL uses this to keep reading lines, e.g. after `def f(n) {`.
+pub fn is_incomplete(source: &str) -> bool {
+    match FirstlangParser::parse(Rule::Program, source) {
+        Ok(_) => false,
+        // Pest reports where it gave up; at the very end means it hit EOI
+        Err(e) => match e.location {
+            InputLocation::Pos(pos) => source[pos..].trim().is_empty(),
+            InputLocation::Span((_, end)) => source[end..].trim().is_empty(),
+        },
+    }
+}
+
 fn parse_stmt(pair: Pair<Rule>) -> Result<Stmt, String> {
+    let line = pair.as_span().start_pos().line_col().0;
+    let end_line = end_line(&pair);
     let inner = pair.into_inner().next().unwrap();
-    match inner.as_rule() {
-        Rule::Function => parse_function(inner),
-        Rule::Return => parse_return(inner),
-        Rule::Assignment => parse_assignment(inner),
-        Rule::Expr => Ok(Stmt::Expr(parse_expr(inner)?)),
+    let kind = match inner.as_rule() {
+        Rule::Function => parse_function(inner)?,
+        Rule::Return => parse_return(inner)?,
+        Rule::Raise => parse_raise(inner)?,
+        Rule::Global => parse_global(inner)?,
+        Rule::Import | Rule::FromImport => parse_import(inner)?,
+        Rule::Assignment => parse_assignment(inner)?,
+        Rule::FieldAssignment => parse_field_assignment(inner)?,
+        Rule::Record => parse_record(inner)?,
+        Rule::Expr => StmtKind::Expr(parse_expr(inner)?),
         // Handle direct expression rules that might appear
-        Rule::Conditional | Rule::WhileLoop | Rule::Comparison => {
-            Ok(Stmt::Expr(parse_expr(inner)?))
+        Rule::Conditional | Rule::WhileLoop | Rule::TryCatch | Rule::Comparison => {
+            StmtKind::Expr(parse_expr(inner)?)
         }
-        r => Err(format!("Unexpected statement rule: {:?}", r)),
-    }
+        r => return Err(format!("Unexpected statement rule: {:?}", r)),
+    };
+    Ok(Stmt::new(kind, line).ending_at(end_line))
 }
 
-fn parse_function(pair: Pair<Rule>) -> Result<Stmt, String> {
+/// Last line of a statement with code on it: the span of a rule also covers
+/// the whitespace and comments skipped after it
+fn end_line(pair: &Pair<Rule>) -> usize {
+    let span = pair.as_span();
+    let last = span
+        .as_str()
+        .lines()
+        .enumerate()
+        .filter(|(_, text)| !text.split('#').next().unwrap_or("").trim().is_empty())
+        .map(|(i, _)| i)
+        .last()
+        .unwrap_or(0);
+    span.start_pos().line_col().0 + last
+}
+
+fn parse_function(pair: Pair<Rule>) -> Result<StmtKind, String> {
     let mut inner = pair.into_inner();
 
     let name = inner.next().unwrap().as_str().to_string();
@@ -64,7 +102,7 @@ fn parse_function(pair: Pair<Rule>) -> Result<Stmt, String> {
         }
     }
 
-    Ok(Stmt::Function { name, params, body })
+    Ok(StmtKind::Function { name, params, body })
 }
 
 fn parse_block(pair: Pair<Rule>) -> Result<Vec<Stmt>, String> {
@@ -77,16 +115,80 @@ fn parse_block(pair: Pair<Rule>) -> Result<Vec<Stmt>, String> {
     Ok(stmts)
 }
 
-fn parse_return(pair: Pair<Rule>) -> Result<Stmt, String> {
-    let expr = pair.into_inner().next().unwrap();
-    Ok(Stmt::Return(parse_expr(expr)?))
+fn parse_return(pair: Pair<Rule>) -> Result<StmtKind, String> {
+    // Skip the RETURN keyword token
+    let expr = pair.into_inner().nth(1).unwrap();
+    Ok(StmtKind::Return(parse_expr(expr)?))
 }
 
-fn parse_assignment(pair: Pair<Rule>) -> Result<Stmt, String> {
+fn parse_raise(pair: Pair<Rule>) -> Result<StmtKind, String> {
+    // Skip the RAISE keyword token
+    let expr = pair.into_inner().nth(1).unwrap();
+    Ok(StmtKind::Raise(parse_expr(expr)?))
+}
+
+fn parse_global(pair: Pair<Rule>) -> Result<StmtKind, String> {
+    let names = pair
+        .into_inner()
+        .filter(|p| p.as_rule() == Rule::Identifier)
+        .map(|p| p.as_str().to_string())
+        .collect();
+    Ok(StmtKind::Global(names))
+}
+
+fn parse_import(pair: Pair<Rule>) -> Result<StmtKind, String> {
+    // Skip the FROM/IMPORT keyword tokens
+    let mut inner = pair
+        .into_inner()
+        .filter(|p| !matches!(p.as_rule(), Rule::FROM | Rule::IMPORT));
+
+    let module = inner.next().unwrap();
+    let path = match module.as_rule() {
+        Rule::ModulePath => module.as_str().trim_matches('"').to_string(),
+        _ => format!("{}.fl", module.as_str()),
+    };
+
+    let names = inner
+        .map(|p| ImportName {
+            name: p.as_str().to_string(),
+            alias: p.as_str().to_string(),
+        })
+        .collect();
+    Ok(StmtKind::Import { path, names })
+}
+
+fn parse_assignment(pair: Pair<Rule>) -> Result<StmtKind, String> {
     let mut inner = pair.into_inner();
     let name = inner.next().unwrap().as_str().to_string();
     let value = parse_expr(inner.next().unwrap())?;
-    Ok(Stmt::Assignment { name, value })
+    Ok(StmtKind::Assignment {
+        name,
+        binding: Binding::Unresolved,
+        value,
+    })
+}
+
+fn parse_field_assignment(pair: Pair<Rule>) -> Result<StmtKind, String> {
+    let mut inner = pair.into_inner();
+    let target = inner.next().unwrap().as_str();
+    let value = parse_expr(inner.next().unwrap())?;
+    let mut fields: Vec<_> = target.split('.').map(str::to_string).collect();
+    let name = fields.remove(0);
+    Ok(StmtKind::FieldAssignment {
+        name,
+        binding: Binding::Unresolved,
+        fields,
+        value,
+    })
+}
+
+fn parse_record(pair: Pair<Rule>) -> Result<StmtKind, String> {
+    let mut inner = pair.into_inner();
+    // Skip the `record` keyword
+    inner.next();
+    let name = inner.next().unwrap().as_str().to_string();
+    let fields = inner.map(|field| field.as_str().to_string()).collect();
+    Ok(StmtKind::Record { name, fields })
 }
 
 fn parse_expr(pair: Pair<Rule>) -> Result<Expr, String> {
@@ -97,15 +199,20 @@ fn parse_expr(pair: Pair<Rule>) -> Result<Expr, String> {
         }
         Rule::Conditional => parse_conditional(pair),
         Rule::WhileLoop => parse_while(pair),
+        Rule::TryCatch => parse_try(pair),
         Rule::Comparison => parse_binary(pair),
         Rule::Additive => parse_binary(pair),
         Rule::Multiplicative => parse_binary(pair),
         Rule::Unary => parse_unary(pair),
         Rule::Call => parse_call(pair),
         Rule::Literal => parse_literal(pair),
-        Rule::Int => Ok(Expr::Int(pair.as_str().parse().unwrap())),
+        Rule::Int => Ok(parse_int(pair.as_str())),
         Rule::Bool => Ok(Expr::Bool(pair.as_str() == "true")),
-        Rule::Identifier => Ok(Expr::Var(pair.as_str().to_string())),
+        Rule::NoneValue => Ok(Expr::None),
+        Rule::Identifier | Rule::QualifiedName => Ok(Expr::Var {
+            name: pair.as_str().to_string(),
+            binding: Binding::Unresolved,
+        }),
         Rule::Block => {
             let stmts = parse_block(pair)?;
             Ok(Expr::Block(stmts))
@@ -133,11 +240,40 @@ fn parse_while(pair: Pair<Rule>) -> Result<Expr, String> {
     Ok(Expr::While { cond, body })
 }
 
+fn parse_try(pair: Pair<Rule>) -> Result<Expr, String> {
+    let mut inner = pair.into_inner();
+    let body = parse_block(inner.next().unwrap())?;
+    let name = inner.next().unwrap().as_str().to_string();
+    let handler = parse_block(inner.next().unwrap())?;
+    Ok(Expr::Try {
+        body,
+        name,
+        binding: Binding::Unresolved,
+        handler,
+    })
+}
+
 fn parse_binary(pair: Pair<Rule>) -> Result<Expr, String> {
     let mut inner = pair.into_inner();
     let mut left = parse_expr(inner.next().unwrap())?;
 
     while let Some(op_pair) = inner.next() {
+        if op_pair.as_rule() == Rule::IsOp {
+            // `is` takes `not` as an inner token
+            let negated = op_pair.into_inner().count() > 1;
+            let op = if negated {
+                BinaryOp::IsNot
+            } else {
+                BinaryOp::Is
+            };
+            let right = parse_expr(inner.next().unwrap())?;
+            left = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+            continue;
+        }
         let op = match op_pair.as_str() {
             "+" => BinaryOp::Add,
             "-" => BinaryOp::Sub,
@@ -192,32 +328,55 @@ fn parse_call(pair: Pair<Rule>) -> Result<Expr, String> {
     // Parse the primary expression (function name or parenthesized expr)
     let mut expr = parse_expr(first)?;
 
-    // Check for CallArgs (function call)
-    for call_args in inner {
-        if call_args.as_rule() == Rule::CallArgs {
-            // Parse arguments inside the CallArgs
-            let args: Vec<Expr> = call_args
-                .into_inner()
-                .map(|p| parse_expr(p))
-                .collect::<Result<_, _>>()?;
-
-            // This is a function call
-            if let Expr::Var(name) = expr {
-                expr = Expr::Call { name, args };
-            } else {
-                return Err("Can only call named functions".to_string());
+    // Apply each call or field access in turn
+    for postfix in inner {
+        match postfix.as_rule() {
+            Rule::CallArgs => {
+                // Parse arguments inside the CallArgs
+                let args: Vec<Expr> = postfix
+                    .into_inner()
+                    .map(|p| parse_expr(p))
+                    .collect::<Result<_, _>>()?;
+
+                // This is a function call
+                if let Expr::Var { name, binding } = expr {
+                    expr = Expr::Call {
+                        name,
+                        binding,
+                        args,
+                    };
+                } else {
+                    return Err("Can only call named functions".to_string());
+                }
             }
+            Rule::Field => {
+                let field = postfix.into_inner().next().unwrap().as_str().to_string();
+                expr = Expr::Field {
+                    expr: Box::new(expr),
+                    field,
+                };
+            }
+            r => return Err(format!("Unexpected postfix rule: {:?}", r)),
         }
     }
 
     Ok(expr)
 }
 
+/// Integer literal, big only when it does not fit in 64 bits
+fn parse_int(digits: &str) -> Expr {
+    match digits.parse() {
+        Ok(n) => Expr::Int(n),
+        Err(_) => Expr::BigInt(digits.parse().expect("Int is made of digits")),
+    }
+}
+
 fn parse_literal(pair: Pair<Rule>) -> Result<Expr, String> {
     let inner = pair.into_inner().next().unwrap();
     match inner.as_rule() {
-        Rule::Int => Ok(Expr::Int(inner.as_str().parse().unwrap())),
+        Rule::Int => Ok(parse_int(inner.as_str())),
         Rule::Bool => Ok(Expr::Bool(inner.as_str() == "true")),
+        Rule::NoneValue => Ok(Expr::None),
         r => Err(format!("Unexpected literal rule: {:?}", r)),
     }
 }
@@ -230,17 +389,24 @@ mod tests {
     fn test_parse_literal() {
         let program = parse("42").unwrap();
         assert_eq!(program.len(), 1);
-        if let Stmt::Expr(Expr::Int(n)) = &program[0] {
+        if let StmtKind::Expr(Expr::Int(n)) = &program[0].kind {
             assert_eq!(*n, 42);
         } else {
             panic!("Expected Int literal");
         }
     }
 
+    #[test]
+    fn test_parse_big_literal() {
+        let program = parse("123456789012345678901234567890").unwrap();
+        assert_eq!(program[0].to_string(), "123456789012345678901234567890");
+        assert!(matches!(program[0].kind, StmtKind::Expr(Expr::BigInt(_))));
+    }
+
     #[test]
     fn test_parse_bool() {
         let program = parse("true").unwrap();
-        if let Stmt::Expr(Expr::Bool(b)) = &program[0] {
+        if let StmtKind::Expr(Expr::Bool(b)) = &program[0].kind {
             assert!(*b);
         } else {
             panic!("Expected Bool literal");
@@ -250,7 +416,7 @@ mod tests {
     #[test]
     fn test_parse_binary() {
         let program = parse("1 + 2").unwrap();
-        if let Stmt::Expr(Expr::Binary { op, .. }) = &program[0] {
+        if let StmtKind::Expr(Expr::Binary { op, .. }) = &program[0].kind {
             assert_eq!(*op, BinaryOp::Add);
         } else {
             panic!("Expected Binary expression");
@@ -260,7 +426,7 @@ mod tests {
     #[test]
     fn test_parse_assignment() {
         let program = parse("x = 42").unwrap();
-        if let Stmt::Assignment { name, value } = &program[0] {
+        if let StmtKind::Assignment { name, value, .. } = &program[0].kind {
             assert_eq!(name, "x");
             assert_eq!(*value, Expr::Int(42));
         } else {
@@ -268,10 +434,129 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_global() {
+        let program = parse("global x, y").unwrap();
+        assert_eq!(
+            program[0].kind,
+            StmtKind::Global(vec!["x".to_string(), "y".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(is_incomplete("def f(n) {"));
+        assert!(is_incomplete("def f(n) {\n  return n\n"));
+        assert!(is_incomplete("1 +"));
+        assert!(is_incomplete("if (x) { 1 } else"));
+        assert!(!is_incomplete("def f(n) { return n }"));
+        assert!(!is_incomplete("1 + )"));
+        assert!(!is_incomplete(""));
+    }
+
+    #[test]
+    fn test_parse_import() {
+        let program = parse("import \"lib/math.fl\"\nfrom utils import a, b").unwrap();
+        assert_eq!(
+            program[0].kind,
+            StmtKind::Import {
+                path: "lib/math.fl".to_string(),
+                names: vec![],
+            }
+        );
+        let StmtKind::Import { path, names } = &program[1].kind else {
+            panic!("Expected Import");
+        };
+        assert_eq!(path, "utils.fl");
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[1].name, "b");
+    }
+
+    #[test]
+    fn test_parse_qualified_call() {
+        let program = parse("math.square(3)").unwrap();
+        assert!(
+            matches!(&program[0].kind, StmtKind::Expr(Expr::Call { name, .. }) if name == "math.square")
+        );
+    }
+
+    #[test]
+    fn test_parse_try() {
+        let program = parse("try { raise 1 } catch (e) { e }").unwrap();
+        let StmtKind::Expr(Expr::Try {
+            body,
+            name,
+            handler,
+            ..
+        }) = &program[0].kind
+        else {
+            panic!("Expected Try");
+        };
+        assert_eq!(body[0].kind, StmtKind::Raise(Expr::Int(1)));
+        assert_eq!(name, "e");
+        assert_eq!(handler.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_is_none() {
+        let program = parse("x is not None == true").unwrap();
+        let expected = Expr::Binary {
+            op: BinaryOp::Eq,
+            left: Box::new(Expr::Binary {
+                op: BinaryOp::IsNot,
+                left: Box::new(Expr::Var {
+                    name: "x".to_string(),
+                    binding: Binding::Unresolved,
+                }),
+                right: Box::new(Expr::None),
+            }),
+            right: Box::new(Expr::Bool(true)),
+        };
+        assert_eq!(program[0].kind, StmtKind::Expr(expected));
+        // Only None can follow `is`
+        assert!(parse("x is 1").is_err());
+        assert!(parse("Nonesuch = 1").is_ok());
+    }
+
+    #[test]
+    fn test_parse_record() {
+        let program = parse("record Point(x, y)\np.x = make(1).y").unwrap();
+        assert_eq!(
+            program[0].kind,
+            StmtKind::Record {
+                name: "Point".to_string(),
+                fields: vec!["x".to_string(), "y".to_string()],
+            }
+        );
+        let StmtKind::FieldAssignment {
+            name,
+            fields,
+            value,
+            ..
+        } = &program[1].kind
+        else {
+            panic!("Expected FieldAssignment");
+        };
+        assert_eq!(
+            (name.as_str(), fields.as_slice()),
+            ("p", ["x".to_string()].as_slice())
+        );
+        assert!(matches!(value, Expr::Field { field, .. } if field == "y"));
+    }
+
+    #[test]
+    fn test_keyword_prefixed_identifiers() {
+        let program = parse("globals = 1\nreturned = globals\nreturn returned").unwrap();
+        assert_eq!(program.len(), 3);
+        assert!(
+            matches!(&program[2].kind, StmtKind::Return(Expr::Var { name, .. }) if name == "returned")
+        );
+    }
+
     #[test]
     fn test_parse_function() {
         let program = parse("def add(a, b) { return a + b }").unwrap();
-        if let Stmt::Function { name, params, .. } = &program[0] {
+        if let StmtKind::Function { name, params, .. } = &program[0].kind {
             assert_eq!(name, "add");
             assert_eq!(params, &["a", "b"]);
         } else {
@@ -282,7 +567,7 @@ mod tests {
     #[test]
     fn test_parse_call() {
         let program = parse("add(1, 2)").unwrap();
-        if let Stmt::Expr(Expr::Call { name, args }) = &program[0] {
+        if let StmtKind::Expr(Expr::Call { name, args, .. }) = &program[0].kind {
             assert_eq!(name, "add");
             assert_eq!(args.len(), 2);
         } else {
@@ -293,7 +578,7 @@ mod tests {
     #[test]
     fn test_parse_conditional() {
         let program = parse("if (x < 10) { 1 } else { 2 }").unwrap();
-        if let Stmt::Expr(Expr::If { .. }) = &program[0] {
+        if let StmtKind::Expr(Expr::If { .. }) = &program[0].kind {
             // Successfully parsed
         } else {
             panic!("Expected If expression");
@@ -303,7 +588,7 @@ mod tests {
     #[test]
     fn test_parse_while() {
         let program = parse("while (x < 10) { x = x + 1 }").unwrap();
-        if let Stmt::Expr(Expr::While { body, .. }) = &program[0] {
+        if let StmtKind::Expr(Expr::While { body, .. }) = &program[0].kind {
             assert_eq!(body.len(), 1);
         } else {
             panic!("Expected While expression");
@@ -0,0 +1,97 @@
+//! Standard prelude
+//!
+//! Helper functions written in Firstlang itself (`src/prelude.fl`) and
+//! embedded into the crate. `Interpreter::new()` loads them into its globals;
+//! other backends run the prelude's definitions ahead of the program with
+//! `with_prelude`.
+//!
+//! `range` and list helpers are left out until Firstlang has collections:
+//! there is no value yet for them to return.
+
+use crate::ast::{Program, StmtKind};
+use crate::parser::parse;
+
+/// Source code of the prelude
+pub const SOURCE: &str = include_str!("prelude.fl");
+
+/// Parse the prelude
+pub fn prelude() -> Program {
+    parse(SOURCE).expect("The prelude should parse")
+}
+
+/// Names of the functions the prelude defines
+pub fn functions() -> Vec<String> {
+    prelude()
+        .into_iter()
+        .filter_map(|stmt| match stmt.kind {
+            StmtKind::Function { name, .. } => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A copy of `program` preceded by the prelude
+pub fn with_prelude(program: &Program) -> Program {
+    let mut combined = prelude();
+    combined.extend(program.iter().cloned());
+    combined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, Value};
+
+    fn run(source: &str) -> Result<Value, String> {
+        Interpreter::new().run(&parse(source)?)
+    }
+
+    #[test]
+    fn test_prelude_resolves_without_warnings() {
+        let mut program = prelude();
+        assert_eq!(
+            crate::resolver::resolve(&mut program).unwrap(),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(functions(), vec!["abs", "max", "min", "pow", "gcd"]);
+    }
+
+    #[test]
+    fn test_abs_max_min() {
+        assert_eq!(run("abs(-5) + abs(5)").unwrap(), Value::Int(10));
+        assert_eq!(run("max(3, 7) * 10 + min(3, 7)").unwrap(), Value::Int(73));
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eq!(run("pow(2, 10)").unwrap(), Value::Int(1024));
+        assert_eq!(run("pow(-3, 3)").unwrap(), Value::Int(-27));
+        assert_eq!(run("pow(5, 0)").unwrap(), Value::Int(1));
+        assert_eq!(run("pow(5, -1)").unwrap(), Value::Int(1));
+    }
+
+    #[test]
+    fn test_gcd() {
+        assert_eq!(run("gcd(48, 18)").unwrap(), Value::Int(6));
+        assert_eq!(run("gcd(-48, 18)").unwrap(), Value::Int(6));
+        assert_eq!(run("gcd(0, 7)").unwrap(), Value::Int(7));
+    }
+
+    #[test]
+    fn test_without_prelude() {
+        let result = Interpreter::without_prelude().run(&parse("abs(-1)").unwrap());
+        assert_eq!(result.unwrap_err(), "Undefined variable: abs");
+    }
+
+    #[test]
+    fn test_prelude_can_be_redefined() {
+        assert_eq!(
+            run("def max(a, b) { return 0 }\nmax(1, 2)").unwrap(),
+            Value::Int(0)
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Profiler for the tree-walking interpreter
+//!
+//! A `Hook` that times every function call. For each function it counts the
+//! calls, the time spent in it including what it called (inclusive) and not
+//! (exclusive), and how deep its recursion went. It also records the time
+//! spent in each call stack in the folded format that flamegraph tools read:
+//! one `outer;inner;innermost microseconds` line per stack.
+//!
+//! ```text
+//! let profiler = Profiler::new();
+//! interpreter.set_hook(Box::new(profiler.clone()));
+//! interpreter.run(&program)?;
+//! eprint!("{}", profiler.table());
+//! ```
+//!
+//! Clones share what was recorded, so the clone given to the interpreter
+//! fills in the one kept for reporting.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::ast::Stmt;
+use crate::interpreter::{Hook, Interpreter};
+
+/// What the profiler recorded for one function
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    /// Time from entering the function until it returned, counting a
+    /// recursive function's nested calls once
+    pub inclusive: Duration,
+    /// Time spent in the function's own statements, not in its callees
+    pub exclusive: Duration,
+    /// Most calls of the function active at the same time
+    pub max_depth: usize,
+}
+
+/// A call that has not returned yet
+struct Active {
+    function: String,
+    start: Instant,
+    /// Time spent in the calls it made
+    children: Duration,
+}
+
+#[derive(Default)]
+struct State {
+    stack: Vec<Active>,
+    functions: HashMap<String, FunctionProfile>,
+    /// Calls of each function currently active
+    depths: HashMap<String, usize>,
+    /// Exclusive time of each call stack, keyed by the folded stack
+    stacks: HashMap<String, Duration>,
+}
+
+/// Collects call counts and times while the interpreter runs
+#[derive(Clone, Default)]
+pub struct Profiler {
+    state: Rc<RefCell<State>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every function called, the most time-consuming first
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let mut functions: Vec<_> = self.state.borrow().functions.values().cloned().collect();
+        functions.sort_by(|a, b| {
+            b.inclusive
+                .cmp(&a.inclusive)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        functions
+    }
+
+    /// The functions as a table, one row per function
+    pub fn table(&self) -> String {
+        let mut table = format!(
+            "{:<24} {:>10} {:>12} {:>12} {:>10}\n",
+            "Function", "Calls", "Total (ms)", "Self (ms)", "Max depth"
+        );
+        for function in self.functions() {
+            table.push_str(&format!(
+                "{:<24} {:>10} {:>12.3} {:>12.3} {:>10}\n",
+                function.name,
+                function.calls,
+                function.inclusive.as_secs_f64() * 1000.0,
+                function.exclusive.as_secs_f64() * 1000.0,
+                function.max_depth
+            ));
+        }
+        table
+    }
+
+    /// Time spent in each call stack, in folded format, sorted by stack
+    pub fn folded(&self) -> String {
+        let state = self.state.borrow();
+        let mut stacks: Vec<_> = state.stacks.iter().collect();
+        stacks.sort();
+        stacks
+            .into_iter()
+            .map(|(stack, time)| format!("{} {}\n", stack, time.as_micros()))
+            .collect()
+    }
+}
+
+impl Hook for Profiler {
+    fn before_stmt(&mut self, _: &Interpreter, _: &Stmt) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn enter_call(&mut self, function: &str) {
+        let state = &mut *self.state.borrow_mut();
+        let depth = state.depths.entry(function.to_string()).or_default();
+        *depth += 1;
+
+        let profile = state
+            .functions
+            .entry(function.to_string())
+            .or_insert_with(|| FunctionProfile {
+                name: function.to_string(),
+                ..FunctionProfile::default()
+            });
+        profile.calls += 1;
+        profile.max_depth = profile.max_depth.max(*depth);
+
+        state.stack.push(Active {
+            function: function.to_string(),
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    fn exit_call(&mut self, _function: &str) {
+        let state = &mut *self.state.borrow_mut();
+        let Some(call) = state.stack.pop() else {
+            return;
+        };
+        let elapsed = call.start.elapsed();
+        let exclusive = elapsed.saturating_sub(call.children);
+
+        let mut stack: Vec<_> = state.stack.iter().map(|c| c.function.as_str()).collect();
+        stack.push(&call.function);
+        *state.stacks.entry(stack.join(";")).or_default() += exclusive;
+
+        if let Some(caller) = state.stack.last_mut() {
+            caller.children += elapsed;
+        }
+
+        let depth = state.depths.get_mut(&call.function).unwrap();
+        *depth -= 1;
+        let outermost = *depth == 0;
+        let profile = state.functions.get_mut(&call.function).unwrap();
+        profile.exclusive += exclusive;
+        if outermost {
+            profile.inclusive += elapsed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn profile(source: &str) -> Profiler {
+        let profiler = Profiler::new();
+        let mut interpreter = Interpreter::without_prelude();
+        interpreter.set_hook(Box::new(profiler.clone()));
+        let _ = interpreter.run(&parse(source).unwrap());
+        profiler
+    }
+
+    fn function(profiler: &Profiler, name: &str) -> FunctionProfile {
+        profiler
+            .functions()
+            .into_iter()
+            .find(|f| f.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_counts_calls_and_depth() {
+        let profiler = profile(
+            r#"
+            def fib(n) { if (n < 2) { return n } else { return fib(n - 1) + fib(n - 2) } }
+            def main() { return fib(10) + fib(5) }
+            main()
+            "#,
+        );
+        let fib = function(&profiler, "fib");
+        assert_eq!(fib.calls, 177 + 15);
+        assert_eq!(fib.max_depth, 10);
+        assert!(fib.exclusive <= fib.inclusive);
+
+        let main = function(&profiler, "main");
+        assert_eq!(main.calls, 1);
+        assert_eq!(main.max_depth, 1);
+        assert!(main.inclusive >= fib.inclusive);
+        assert_eq!(profiler.functions()[0].name, "main");
+    }
+
+    #[test]
+    fn test_folded_stacks() {
+        let profiler = profile(
+            r#"
+            def leaf() { return 1 }
+            def inner() { return leaf() + 1 }
+            def outer() { return inner() + leaf() }
+            outer()
+            "#,
+        );
+        let stacks: Vec<_> = profiler
+            .folded()
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+            .collect();
+        assert_eq!(
+            stacks,
+            ["outer", "outer;inner", "outer;inner;leaf", "outer;leaf"]
+        );
+    }
+
+    #[test]
+    fn test_tail_calls_replace_the_caller() {
+        let profiler = profile(
+            r#"
+            def count(n) { if (n == 0) { return 0 } else { return count(n - 1) } }
+            count(50)
+            "#,
+        );
+        assert_eq!(function(&profiler, "count").calls, 51);
+        assert_eq!(function(&profiler, "count").max_depth, 1);
+        assert_eq!(profiler.folded().lines().count(), 1);
+    }
+
+    #[test]
+    fn test_exceptions_exit_calls() {
+        let profiler = profile(
+            r#"
+            def fail(n) { if (n == 0) { raise 1 } else { return 1 + fail(n - 1) } }
+            try { fail(3) } catch (e) { e }
+            fail(2)
+            "#,
+        );
+        let fail = function(&profiler, "fail");
+        assert_eq!(fail.calls, 4 + 3);
+        assert_eq!(fail.max_depth, 4);
+        assert!(profiler.state.borrow().stack.is_empty());
+    }
+}
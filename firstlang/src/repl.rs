@@ -0,0 +1,327 @@
+//! Interactive REPL for Firstlang
+//!
+//! Lines are read with rustyline (editing, persistent history). Input that
+//! stops in the middle of a statement, like `def f(n) {`, keeps reading until
+//! the parser accepts it. Lines starting with `:` are commands:
+//!
+//! ```text
+//! :vars          List top-level variables
+//! :funcs         List defined functions
+//! :ast <code>    Show the AST of some code without running it
+//! :load <file>   Run a file in the current session
+//! :save <file>   Save the session's functions and variables as source code
+//! :load-session <file>  Replace the session with one saved by `:save`
+//! :reset         Forget every variable and function
+//! :help          Show the commands
+//! :quit          Exit (also `quit`, `exit` or Ctrl-D)
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::ast::Program;
+use crate::interpreter::{Interpreter, Value};
+use crate::module::Loader;
+use crate::parser::{is_incomplete, parse};
+use crate::prelude;
+use crate::session;
+
+const HELP: &str = "\
+:vars          List top-level variables
+:funcs         List defined functions
+:ast <code>    Show the AST of some code without running it
+:load <file>   Run a file in the current session
+:save <file>   Save the session's functions and variables as source code
+:load-session <file>  Replace the session with one saved by :save
+:reset         Forget every variable and function
+:help          Show this help
+:quit          Exit";
+
+/// REPL session state
+pub struct Repl {
+    interpreter: Interpreter,
+    /// Imports are relative to the current directory
+    loader: Loader,
+    use_prelude: bool,
+}
+
+impl Repl {
+    pub fn new(use_prelude: bool) -> Self {
+        let (interpreter, loader) = if use_prelude {
+            (
+                Interpreter::new(),
+                Loader::new().with_functions(prelude::functions()),
+            )
+        } else {
+            (Interpreter::without_prelude(), Loader::new())
+        };
+        Repl {
+            interpreter,
+            loader,
+            use_prelude,
+        }
+    }
+
+    /// Evaluate a complete input (code or a command), returning the text to print
+    pub fn eval(&mut self, input: &str) -> Result<Option<String>, String> {
+        let input = input.trim();
+        match input.strip_prefix(':') {
+            Some(command) => {
+                let (command, arg) = command.split_once(' ').unwrap_or((command, ""));
+                self.command(command, arg.trim())
+            }
+            None => {
+                let program = self.loader.load_source(input, Path::new(""))?;
+                self.run(&program)
+            }
+        }
+    }
+
+    fn command(&mut self, command: &str, arg: &str) -> Result<Option<String>, String> {
+        match command {
+            "vars" => {
+                let mut vars: Vec<_> = self
+                    .interpreter
+                    .variables()
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect();
+                vars.sort();
+                Ok(Some(vars.join("\n")))
+            }
+            "funcs" => {
+                let mut funcs: Vec<_> = self
+                    .interpreter
+                    .functions()
+                    .iter()
+                    .map(|(name, value)| match value {
+                        Value::Function { params, .. } => {
+                            format!("{}({})", name, params.join(", "))
+                        }
+                        _ => name.clone(),
+                    })
+                    .collect();
+                funcs.sort();
+                Ok(Some(funcs.join("\n")))
+            }
+            "ast" => {
+                let program = parse(arg)?;
+                let stmts: Vec<_> = program
+                    .iter()
+                    .map(|stmt| format!("{:#?}", stmt.kind))
+                    .collect();
+                Ok(Some(stmts.join("\n")))
+            }
+            "load" if arg.is_empty() => Err("Usage: :load <file>".to_string()),
+            "load" => {
+                let program = self.loader.load_file(arg)?;
+                self.run(&program)
+            }
+            "save" if arg.is_empty() => Err("Usage: :save <file>".to_string()),
+            "save" => {
+                session::save(arg, &self.interpreter, &self.loader)?;
+                Ok(Some(format!("Saved session to {}", arg)))
+            }
+            "load-session" if arg.is_empty() => Err("Usage: :load-session <file>".to_string()),
+            "load-session" => {
+                // Restore into a fresh REPL, so a failed load keeps the
+                // current session
+                let mut restored = Repl::new(self.use_prelude);
+                session::restore(arg, &mut restored.interpreter, &mut restored.loader)?;
+                *self = restored;
+                Ok(None)
+            }
+            "reset" => {
+                *self = Repl::new(self.use_prelude);
+                Ok(None)
+            }
+            "help" => Ok(Some(HELP.to_string())),
+            _ => Err(format!("Unknown command :{} (try :help)", command)),
+        }
+    }
+
+    fn run(&mut self, program: &Program) -> Result<Option<String>, String> {
+        match self.interpreter.run(program)? {
+            Value::None { .. } => Ok(None),
+            value => Ok(Some(value.to_string())),
+        }
+    }
+}
+
+/// History is kept in `~/.firstlang_history`
+fn history_path() -> Option<PathBuf> {
+    home::home_dir().map(|home| home.join(".firstlang_history"))
+}
+
+/// Run the REPL until the user quits
+pub fn run(use_prelude: bool) -> rustyline::Result<()> {
+    let mut rl = DefaultEditor::new()?;
+    let history = history_path();
+    if let Some(path) = &history {
+        // No history yet on the first run
+        let _ = rl.load_history(path);
+    }
+
+    println!("Firstlang REPL v0.1.0");
+    println!("Type expressions to evaluate, :help for commands, or :quit to exit.");
+    println!();
+
+    let mut repl = Repl::new(use_prelude);
+    loop {
+        let mut input = match rl.readline(">>> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err),
+        };
+
+        let trimmed = input.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if matches!(trimmed, "quit" | "exit" | ":quit" | ":q") {
+            break;
+        }
+
+        // Keep reading while the code stops mid-statement; an empty line gives up
+        while !input.trim_start().starts_with(':') && is_incomplete(&input) {
+            match rl.readline("... ") {
+                Ok(line) if line.trim().is_empty() => break,
+                Ok(line) => {
+                    input.push('\n');
+                    input.push_str(&line);
+                }
+                Err(ReadlineError::Interrupted) => {
+                    input.clear();
+                    break;
+                }
+                Err(ReadlineError::Eof) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        if input.trim().is_empty() {
+            continue;
+        }
+        let _ = rl.add_history_entry(input.as_str());
+
+        match repl.eval(&input) {
+            Ok(Some(output)) if !output.is_empty() => println!("{}", output),
+            Ok(_) => {}
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+
+    if let Some(path) = &history {
+        rl.save_history(path)?;
+    }
+    println!("Goodbye!");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(repl: &mut Repl, input: &str) -> Option<String> {
+        repl.eval(input).unwrap()
+    }
+
+    #[test]
+    fn test_state_persists_between_inputs() {
+        let mut repl = Repl::new(false);
+        assert_eq!(eval(&mut repl, "def double(n) { return n * 2 }"), None);
+        assert_eq!(eval(&mut repl, "x = double(21)"), None);
+        assert_eq!(eval(&mut repl, "x"), Some("42".to_string()));
+    }
+
+    #[test]
+    fn test_vars_and_funcs() {
+        let mut repl = Repl::new(false);
+        eval(&mut repl, "b = true\na = 1\ndef add(x, y) { return x + y }");
+        assert_eq!(
+            eval(&mut repl, ":vars"),
+            Some("a = 1\nb = true".to_string())
+        );
+        assert_eq!(eval(&mut repl, ":funcs"), Some("add(x, y)".to_string()));
+    }
+
+    #[test]
+    fn test_funcs_include_prelude() {
+        let mut repl = Repl::new(true);
+        let funcs = eval(&mut repl, ":funcs").unwrap();
+        assert!(funcs.contains("gcd(a, b)"));
+    }
+
+    #[test]
+    fn test_ast_does_not_run() {
+        let mut repl = Repl::new(false);
+        let ast = eval(&mut repl, ":ast x = 1 + 2").unwrap();
+        assert!(ast.starts_with("Assignment {"));
+        assert!(ast.contains("op: Add"));
+        assert_eq!(eval(&mut repl, ":vars"), Some(String::new()));
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut repl = Repl::new(false);
+        eval(&mut repl, "x = 1");
+        eval(&mut repl, ":reset");
+        assert_eq!(repl.eval("x").unwrap_err(), "Undefined variable: x");
+    }
+
+    #[test]
+    fn test_load() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/factorial.fl");
+        let mut repl = Repl::new(false);
+        assert_eq!(
+            eval(&mut repl, &format!(":load {}", path.display())),
+            Some("120".to_string())
+        );
+        assert!(eval(&mut repl, ":funcs")
+            .unwrap()
+            .contains("factorial_rec(n)"));
+    }
+
+    #[test]
+    fn test_save_and_load_session() {
+        let path = std::env::temp_dir().join(format!("firstlang-repl-{}.fl", std::process::id()));
+        let mut repl = Repl::new(true);
+        eval(
+            &mut repl,
+            "record P(x, y)\ndef norm(p) { return abs(p.x) + abs(p.y) }",
+        );
+        eval(&mut repl, "p = P(-2, 3)");
+        assert_eq!(
+            eval(&mut repl, &format!(":save {}", path.display())),
+            Some(format!("Saved session to {}", path.display()))
+        );
+
+        let mut resumed = Repl::new(true);
+        eval(&mut resumed, "p = 0");
+        assert_eq!(
+            eval(&mut resumed, &format!(":load-session {}", path.display())),
+            None
+        );
+        assert_eq!(eval(&mut resumed, "norm(p)"), Some("5".to_string()));
+        assert_eq!(eval(&mut resumed, ":vars"), eval(&mut repl, ":vars"));
+    }
+
+    #[test]
+    fn test_failed_load_session_keeps_session() {
+        let mut repl = Repl::new(false);
+        eval(&mut repl, "x = 42");
+        assert!(repl.eval(":load-session /nonexistent.fl").is_err());
+        assert_eq!(eval(&mut repl, "x"), Some("42".to_string()));
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let mut repl = Repl::new(false);
+        assert_eq!(
+            repl.eval(":nope").unwrap_err(),
+            "Unknown command :nope (try :help)"
+        );
+    }
+}
@@ -0,0 +1,605 @@
+//! Static resolver for Firstlang
+//!
+//! A pass over the parsed program that runs before execution. It:
+//! - reports names that are never defined, instead of failing when the
+//!   offending line happens to run,
+//! - warns about unused local variables and variables that shadow a function
+//!   or a top-level variable,
+//! - annotates every `Expr::Var`, `Expr::Call` and `StmtKind::Assignment` with a
+//!   `Binding`, so both backends read locals from a slot instead of searching
+//!   by name.
+//!
+//! # Scoping rules
+//!
+//! - The top level of the program and each function body are separate
+//!   scopes. Top-level variables are not visible inside a function unless it
+//!   declares them with `global name`, after which reads and assignments in
+//!   that function refer to the top-level variable.
+//! - `if`/`else` branches, `while` bodies and blocks open a nested block scope.
+//! - Assigning to a variable that is already visible updates it. Assigning to
+//!   any other name declares a new variable in the innermost block, and it
+//!   goes out of scope when that block ends.
+//! - A variable is visible from its first assignment onwards.
+//! - Functions are global: a `def` anywhere in the program can be called from
+//!   anywhere, including before it (mutual recursion). Nested `def`s do not
+//!   capture the enclosing function's variables.
+//! - `record Point(x, y)` declares a constructor function `Point`. A dotted
+//!   name like `p.x` reads field `x` of variable `p`, unless it names a
+//!   member of an imported module.
+//! - `from module import name` declares `name` as a function or top-level
+//!   variable, depending on what `module.name` is. Imports are only allowed
+//!   at the top level.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{Binding, Expr, ImportName, Program, Stmt, StmtKind};
+use crate::module::module_name;
+
+/// Resolve every variable in a program, returning the warnings found
+///
+/// Undefined names are reported as an error, one per line.
+pub fn resolve(program: &mut Program) -> Result<Vec<String>, String> {
+    Resolver::new().resolve(program)
+}
+
+/// Resolver state
+#[derive(Default)]
+pub struct Resolver {
+    /// Functions defined anywhere in the program (or before it)
+    functions: HashSet<String>,
+    /// Top-level variables declared so far
+    variables: HashSet<String>,
+    /// Function (or top level) currently being resolved
+    scope: Scope,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+/// Variables of the function (or top level) being resolved
+#[derive(Default)]
+struct Scope {
+    /// Enclosing function, `None` at the top level
+    function: Option<String>,
+    /// Variables of each open block and their slots, innermost last
+    ///
+    /// At the top level the outermost block is the program itself, whose
+    /// variables are top-level variables rather than slots.
+    blocks: Vec<HashMap<String, usize>>,
+    /// Name of each slot and whether it is ever read
+    slots: Vec<(String, bool)>,
+    /// Number of leading slots holding parameters
+    params: usize,
+    /// Top-level variables declared `global` in this function
+    globals: HashSet<String>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Treat `names` as functions that are already defined (e.g. by earlier REPL input)
+    pub fn with_functions(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.functions.extend(names);
+        self
+    }
+
+    /// Treat `names` as top-level variables that are already defined
+    pub fn with_variables(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.variables.extend(names);
+        self
+    }
+
+    /// Resolve a program, returning its warnings or all of its errors
+    pub fn resolve(mut self, program: &mut Program) -> Result<Vec<String>, String> {
+        // Functions can be called before their `def` runs (mutual recursion)
+        collect_functions(program, &mut self.functions);
+
+        self.resolve_block(program);
+        self.report_unused();
+
+        if self.errors.is_empty() {
+            Ok(self.warnings)
+        } else {
+            Err(self.errors.join("\n"))
+        }
+    }
+
+    fn resolve_block(&mut self, stmts: &mut [Stmt]) {
+        for stmt in stmts {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    /// Resolve the body of an `if`/`else` branch, `while` loop or block
+    fn resolve_nested_block(&mut self, stmts: &mut [Stmt]) {
+        self.scope.blocks.push(HashMap::new());
+        self.resolve_block(stmts);
+        self.scope.blocks.pop();
+    }
+
+    fn resolve_stmt(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.kind {
+            StmtKind::Function { name, params, body } => self.resolve_function(name, params, body),
+            StmtKind::Return(expr) | StmtKind::Raise(expr) | StmtKind::Expr(expr) => {
+                self.resolve_expr(expr)
+            }
+            StmtKind::Assignment {
+                name,
+                binding,
+                value,
+            } => {
+                // The value is resolved first: `x = x + 1` reads the outer `x`
+                self.resolve_expr(value);
+                *binding = self.resolve_assignment(name);
+            }
+            StmtKind::FieldAssignment {
+                name,
+                binding,
+                value,
+                ..
+            } => {
+                self.resolve_expr(value);
+                // The record must exist already: updating a field reads it
+                *binding = self.resolve_name(name);
+                if *binding == Binding::Function && self.functions.contains(name.as_str()) {
+                    self.errors
+                        .push(format!("Cannot assign to a field of function '{}'", name));
+                }
+            }
+            StmtKind::Record { name, fields } => {
+                let mut seen = HashSet::new();
+                for field in fields.iter() {
+                    if !seen.insert(field) {
+                        self.errors
+                            .push(format!("Duplicate field '{}' in record '{}'", field, name));
+                    }
+                }
+            }
+            StmtKind::Import { path, names } => self.resolve_import(path, names),
+            StmtKind::Global(names) => {
+                if let Some(function) = &self.scope.function {
+                    for name in names.iter() {
+                        if self.find_local(name).is_some() {
+                            self.errors.push(format!(
+                                "Cannot declare '{}' global in function '{}': it is already a local variable",
+                                name, function
+                            ));
+                        } else {
+                            self.scope.globals.insert(name.clone());
+                            // The function may create the variable for the top level
+                            self.variables.insert(name.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Bring the names of `from module import ...` into scope
+    fn resolve_import(&mut self, path: &str, names: &[ImportName]) {
+        if self.scope.function.is_some() || !self.scope.blocks.is_empty() {
+            self.errors.push(format!(
+                "Cannot import '{}' here: imports are only allowed at the top level",
+                path
+            ));
+            return;
+        }
+
+        let module = module_name(path);
+        for import in names {
+            let qualified = format!("{}.{}", module, import.name);
+            if self.functions.contains(&qualified) {
+                self.functions.insert(import.alias.clone());
+            } else if self.variables.contains(&qualified) {
+                self.variables.insert(import.alias.clone());
+            } else {
+                self.errors.push(format!(
+                    "Module '{}' has no member '{}'",
+                    module, import.name
+                ));
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, name: &str, params: &[String], body: &mut [Stmt]) {
+        let scope = Scope {
+            function: Some(name.to_string()),
+            blocks: vec![HashMap::new()],
+            ..Scope::default()
+        };
+        let outer = std::mem::replace(&mut self.scope, scope);
+
+        for param in params {
+            self.declare(param);
+        }
+        self.scope.params = params.len();
+
+        self.resolve_block(body);
+        self.report_unused();
+
+        self.scope = outer;
+    }
+
+    fn resolve_expr(&mut self, expr: &mut Expr) {
+        if let Expr::Var { name, .. } = expr {
+            if let Some(access) = self.field_access(name) {
+                *expr = access;
+            }
+        }
+
+        match expr {
+            Expr::Int(_) | Expr::BigInt(_) | Expr::Bool(_) | Expr::None => {}
+            Expr::Var { name, binding } => *binding = self.resolve_name(name),
+            Expr::Unary { expr, .. } => self.resolve_expr(expr),
+            Expr::Binary { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Call {
+                name,
+                binding,
+                args,
+            } => {
+                for arg in args.iter_mut() {
+                    self.resolve_expr(arg);
+                }
+                *binding = self.resolve_name(name);
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr(cond);
+                self.resolve_nested_block(then_branch);
+                self.resolve_nested_block(else_branch);
+            }
+            Expr::While { cond, body } => {
+                self.resolve_expr(cond);
+                self.resolve_nested_block(body);
+            }
+            Expr::Block(stmts) => self.resolve_nested_block(stmts),
+            Expr::Try {
+                body,
+                name,
+                binding,
+                handler,
+            } => {
+                self.resolve_nested_block(body);
+                // The caught value is a local of the handler's block
+                self.scope.blocks.push(HashMap::new());
+                *binding = self.declare(name);
+                // Catching without looking at the exception is fine
+                if let Binding::Local(slot) = *binding {
+                    self.scope.slots[slot].1 = true;
+                }
+                self.resolve_block(handler);
+                self.scope.blocks.pop();
+            }
+            Expr::Field { expr, .. } => self.resolve_expr(expr),
+            Expr::Record { fields, .. } => {
+                for (_, value) in fields.iter_mut() {
+                    self.resolve_expr(value);
+                }
+            }
+        }
+    }
+
+    /// Turn a dotted name whose start is a variable (`p.x.y`) into field
+    /// accesses, leaving names of module members alone
+    fn field_access(&self, name: &str) -> Option<Expr> {
+        if !name.contains('.') || self.is_variable(name) || self.functions.contains(name) {
+            return None;
+        }
+        let parts: Vec<_> = name.split('.').collect();
+        // The longest variable wins, so `math.origin.x` reads a module's variable
+        let base = (1..parts.len())
+            .rev()
+            .find(|&len| self.is_variable(&parts[..len].join(".")))?;
+
+        let var = Expr::Var {
+            name: parts[..base].join("."),
+            binding: Binding::Unresolved,
+        };
+        Some(parts[base..].iter().fold(var, |expr, field| Expr::Field {
+            expr: Box::new(expr),
+            field: field.to_string(),
+        }))
+    }
+
+    /// Whether a name is a variable visible from the current scope
+    fn is_variable(&self, name: &str) -> bool {
+        self.scope.globals.contains(name)
+            || self.find_local(name).is_some()
+            || (self.scope.function.is_none() && self.variables.contains(name))
+    }
+
+    /// Find the binding of a name that is read
+    fn resolve_name(&mut self, name: &str) -> Binding {
+        if self.scope.globals.contains(name) {
+            return Binding::Global;
+        }
+        if let Some(slot) = self.find_local(name) {
+            self.scope.slots[slot].1 = true;
+            return Binding::Local(slot);
+        }
+        if self.scope.function.is_none() && self.variables.contains(name) {
+            return Binding::Global;
+        }
+        if !self.functions.contains(name) {
+            let error = format!("Undefined variable: {}", name);
+            if !self.errors.contains(&error) {
+                self.errors.push(error);
+            }
+        }
+        Binding::Function
+    }
+
+    /// Find the binding of a name that is assigned, declaring it if needed
+    fn resolve_assignment(&mut self, name: &str) -> Binding {
+        if self.scope.globals.contains(name) {
+            return Binding::Global;
+        }
+        if let Some(slot) = self.find_local(name) {
+            return Binding::Local(slot);
+        }
+        if self.scope.function.is_none() && self.variables.contains(name) {
+            return Binding::Global;
+        }
+        self.declare(name)
+    }
+
+    /// Declare a new variable in the innermost block
+    fn declare(&mut self, name: &str) -> Binding {
+        match &self.scope.function {
+            Some(function) if self.functions.contains(name) => self.warnings.push(format!(
+                "Variable '{}' in function '{}' shadows function '{}'",
+                name, function, name
+            )),
+            Some(function) if self.variables.contains(name) => self.warnings.push(format!(
+                "Variable '{}' in function '{}' shadows top-level variable '{}'",
+                name, function, name
+            )),
+            None if self.functions.contains(name) => self
+                .warnings
+                .push(format!("Variable '{}' shadows function '{}'", name, name)),
+            _ => {}
+        }
+
+        match self.scope.blocks.last_mut() {
+            Some(block) => {
+                let slot = self.scope.slots.len();
+                self.scope.slots.push((name.to_string(), false));
+                block.insert(name.to_string(), slot);
+                Binding::Local(slot)
+            }
+            None => {
+                self.variables.insert(name.to_string());
+                Binding::Global
+            }
+        }
+    }
+
+    /// Find the slot of a visible block-local variable
+    fn find_local(&self, name: &str) -> Option<usize> {
+        self.scope
+            .blocks
+            .iter()
+            .rev()
+            .find_map(|block| block.get(name).copied())
+    }
+
+    /// Warn about slots of the current scope that are never read
+    fn report_unused(&mut self) {
+        for (name, used) in self.scope.slots.iter().skip(self.scope.params) {
+            if !used {
+                self.warnings.push(match &self.scope.function {
+                    Some(function) => {
+                        format!("Unused variable '{}' in function '{}'", name, function)
+                    }
+                    None => format!("Unused variable '{}'", name),
+                });
+            }
+        }
+    }
+}
+
+/// Collect the name of every `def`, at any nesting depth
+fn collect_functions(stmts: &[Stmt], names: &mut HashSet<String>) {
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::Function { name, body, .. } => {
+                names.insert(name.clone());
+                collect_functions(body, names);
+            }
+            StmtKind::Record { name, .. } => {
+                names.insert(name.clone());
+            }
+            StmtKind::Return(expr)
+            | StmtKind::Raise(expr)
+            | StmtKind::Expr(expr)
+            | StmtKind::Assignment { value: expr, .. }
+            | StmtKind::FieldAssignment { value: expr, .. } => collect_functions_expr(expr, names),
+            StmtKind::Global(_) | StmtKind::Import { .. } => {}
+        }
+    }
+}
+
+fn collect_functions_expr(expr: &Expr, names: &mut HashSet<String>) {
+    match expr {
+        Expr::Int(_) | Expr::BigInt(_) | Expr::Bool(_) | Expr::None | Expr::Var { .. } => {}
+        Expr::Unary { expr, .. } => collect_functions_expr(expr, names),
+        Expr::Binary { left, right, .. } => {
+            collect_functions_expr(left, names);
+            collect_functions_expr(right, names);
+        }
+        Expr::Call { args, .. } => {
+            for arg in args {
+                collect_functions_expr(arg, names);
+            }
+        }
+        Expr::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            collect_functions_expr(cond, names);
+            collect_functions(then_branch, names);
+            collect_functions(else_branch, names);
+        }
+        Expr::While { cond, body } => {
+            collect_functions_expr(cond, names);
+            collect_functions(body, names);
+        }
+        Expr::Block(stmts) => collect_functions(stmts, names),
+        Expr::Try { body, handler, .. } => {
+            collect_functions(body, names);
+            collect_functions(handler, names);
+        }
+        Expr::Field { expr, .. } => collect_functions_expr(expr, names),
+        Expr::Record { fields, .. } => {
+            for (_, value) in fields {
+                collect_functions_expr(value, names);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn resolve_source(source: &str) -> Result<(Program, Vec<String>), String> {
+        let mut program = parse(source)?;
+        let warnings = resolve(&mut program)?;
+        Ok((program, warnings))
+    }
+
+    #[test]
+    fn test_locals_get_slots() {
+        let (program, _) = resolve_source("def f(a, b) { c = a + b\nreturn c }").unwrap();
+        let StmtKind::Function { body, .. } = &program[0].kind else {
+            panic!("Expected Function");
+        };
+        assert_eq!(
+            body[0].kind,
+            StmtKind::Assignment {
+                name: "c".to_string(),
+                binding: Binding::Local(2),
+                value: Expr::Binary {
+                    op: crate::ast::BinaryOp::Add,
+                    left: Box::new(Expr::Var {
+                        name: "a".to_string(),
+                        binding: Binding::Local(0),
+                    }),
+                    right: Box::new(Expr::Var {
+                        name: "b".to_string(),
+                        binding: Binding::Local(1),
+                    }),
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_functions_and_top_level_looked_up_by_name() {
+        let (program, _) = resolve_source("x = 1\nf(x)\ndef f(n) { return n }").unwrap();
+        let StmtKind::Expr(Expr::Call { binding, args, .. }) = &program[1].kind else {
+            panic!("Expected Call");
+        };
+        assert_eq!(*binding, Binding::Function);
+        assert!(matches!(
+            args[0],
+            Expr::Var {
+                binding: Binding::Global,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_undefined_names_reported_ahead_of_time() {
+        let source = r#"
+            def f(n) {
+                if (n > 0) {
+                    return missing(n)
+                } else {
+                    return y
+                }
+            }
+        "#;
+        assert_eq!(
+            resolve_source(source).unwrap_err(),
+            "Undefined variable: missing\nUndefined variable: y"
+        );
+    }
+
+    #[test]
+    fn test_top_level_variables_not_visible_in_functions() {
+        let source = "x = 1\ndef f() { return x }";
+        assert_eq!(resolve_source(source).unwrap_err(), "Undefined variable: x");
+    }
+
+    #[test]
+    fn test_unused_variable_warning() {
+        let (_, warnings) = resolve_source("def f(n) { tmp = n\nreturn n }").unwrap();
+        assert_eq!(warnings, vec!["Unused variable 'tmp' in function 'f'"]);
+    }
+
+    #[test]
+    fn test_shadowing_warnings() {
+        let source = r#"
+            def double(n) { return n * 2 }
+            def f(double) { return double }
+            double = 3
+        "#;
+        let (_, warnings) = resolve_source(source).unwrap();
+        assert_eq!(
+            warnings,
+            vec![
+                "Variable 'double' in function 'f' shadows function 'double'",
+                "Variable 'double' shadows function 'double'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_known_globals() {
+        let mut program = parse("f(x)").unwrap();
+        let warnings = Resolver::new()
+            .with_functions(["f".to_string()])
+            .with_variables(["x".to_string()])
+            .resolve(&mut program)
+            .unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_dotted_variable_is_field_access() {
+        let (program, _) = resolve_source("record Point(x, y)\np = Point(1, 2)\np.x").unwrap();
+        assert_eq!(
+            program[2].kind,
+            StmtKind::Expr(Expr::Field {
+                expr: Box::new(Expr::Var {
+                    name: "p".to_string(),
+                    binding: Binding::Global,
+                }),
+                field: "x".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_module_member_is_not_field_access() {
+        let mut program = parse("math.pi").unwrap();
+        Resolver::new()
+            .with_variables(["math.pi".to_string()])
+            .resolve(&mut program)
+            .unwrap();
+        assert!(
+            matches!(&program[0].kind, StmtKind::Expr(Expr::Var { name, .. }) if name == "math.pi")
+        );
+    }
+}
@@ -0,0 +1,299 @@
+//! Saving and restoring interpreter sessions
+//!
+//! A session is saved as Firstlang source that recreates it: the modules that
+//! were imported, every function and record declaration, and the top-level
+//! variables as assignments of their values. Restoring a session is running
+//! that file, and the file can be read, edited or attached to a bug report
+//! like any other program.
+//!
+//! The names a module defines (`math.square`) cannot be written as `def`s,
+//! so modules are imported again by their absolute paths instead. Values
+//! that have no literal syntax, like a caught runtime error, are listed in
+//! comments and not restored.
+
+use std::fs;
+use std::path::Path;
+
+use num_bigint::BigInt;
+use num_traits::Signed;
+
+use crate::ast::{Binding, Expr, ImportName, Program, Stmt, StmtKind, UnaryOp};
+use crate::formatter::format_program;
+use crate::interpreter::{Interpreter, Value};
+use crate::module::Loader;
+use crate::prelude::prelude;
+
+/// Source code recreating the functions and top-level variables of
+/// `interpreter`, importing the modules `loader` has loaded
+pub fn snapshot(interpreter: &Interpreter, loader: &Loader) -> String {
+    let mut program = Program::new();
+    let mut unsaved = Vec::new();
+
+    for path in loader.modules() {
+        let path = path.to_string_lossy().into_owned();
+        program.push(stmt(StmtKind::Import {
+            path,
+            names: Vec::<ImportName>::new(),
+        }));
+    }
+
+    // The prelude's functions are defined again by a new interpreter
+    let prelude: Vec<_> = prelude()
+        .into_iter()
+        .map(|stmt| format_program(&[stmt], ""))
+        .collect();
+
+    let functions = interpreter.functions();
+    let mut names: Vec<_> = functions
+        .keys()
+        .filter(|name| !name.contains('.'))
+        .collect();
+    names.sort();
+    for name in names {
+        let Value::Function { params, body } = &functions[name] else {
+            continue;
+        };
+        let kind = if constructor(name, params, body) {
+            StmtKind::Record {
+                name: name.clone(),
+                fields: params.clone(),
+            }
+        } else {
+            StmtKind::Function {
+                name: name.clone(),
+                params: params.clone(),
+                body: body.clone(),
+            }
+        };
+        let stmt = stmt(kind);
+        if !prelude.contains(&format_program(std::slice::from_ref(&stmt), "")) {
+            program.push(stmt);
+        }
+    }
+
+    let variables = interpreter.variables();
+    let mut names: Vec<_> = variables
+        .keys()
+        .filter(|name| !name.contains('.'))
+        .collect();
+    names.sort();
+    for name in names {
+        let value = &variables[name];
+        match literal(value, interpreter) {
+            Some(value) => program.push(stmt(StmtKind::Assignment {
+                name: name.clone(),
+                binding: Binding::Unresolved,
+                value,
+            })),
+            None => unsaved.push(format!("# Not saved: {} = {}\n", name, value)),
+        }
+    }
+
+    format!(
+        "# Firstlang session\n{}{}",
+        unsaved.concat(),
+        format_program(&program, "")
+    )
+}
+
+/// Save the session to a file
+pub fn save(
+    path: impl AsRef<Path>,
+    interpreter: &Interpreter,
+    loader: &Loader,
+) -> Result<(), String> {
+    let path = path.as_ref();
+    fs::write(path, snapshot(interpreter, loader))
+        .map_err(|e| format!("Cannot write '{}': {}", path.display(), e))
+}
+
+/// Run a saved session in `interpreter`
+///
+/// Restoring into a new interpreter and loader resumes the session as it
+/// was saved.
+pub fn restore(
+    path: impl AsRef<Path>,
+    interpreter: &mut Interpreter,
+    loader: &mut Loader,
+) -> Result<(), String> {
+    let program = loader.load_file(path)?;
+    interpreter.run(&program)?;
+    Ok(())
+}
+
+fn stmt(kind: StmtKind) -> Stmt {
+    Stmt::new(kind, 0)
+}
+
+/// Whether a function is the constructor of `record name(params)`
+fn constructor(name: &str, params: &[String], body: &[Stmt]) -> bool {
+    match body {
+        [Stmt {
+            kind:
+                StmtKind::Return(Expr::Record {
+                    name: record,
+                    fields,
+                }),
+            ..
+        }] => {
+            record == name
+                && fields.len() == params.len()
+                && fields.iter().zip(params).all(|((field, value), param)| {
+                    field == param && matches!(value, Expr::Var { name, .. } if name == param)
+                })
+        }
+        _ => false,
+    }
+}
+
+/// An expression evaluating to `value`, if it can be written in source
+fn literal(value: &Value, interpreter: &Interpreter) -> Option<Expr> {
+    let expr = match value {
+        Value::Int(n) => integer(BigInt::from(*n)),
+        Value::BigInt(n) => integer(n.clone()),
+        Value::Bool(b) => Expr::Bool(*b),
+        // Calling the constructor makes the record again
+        Value::Record { name, fields } => Expr::Call {
+            name: name.clone(),
+            binding: Binding::Unresolved,
+            args: fields
+                .iter()
+                .map(|(_, value)| literal(value, interpreter))
+                .collect::<Option<_>>()?,
+        },
+        // A function value is written as the name of the function it is
+        Value::Function { .. } => {
+            let functions = interpreter.functions();
+            let mut names: Vec<_> = functions
+                .iter()
+                .filter(|(_, function)| *function == value)
+                .map(|(name, _)| name)
+                .collect();
+            names.sort();
+            Expr::Var {
+                name: names.first()?.to_string(),
+                binding: Binding::Unresolved,
+            }
+        }
+        Value::None { .. } => Expr::None,
+        // Only the VM makes compiled functions
+        Value::Error(_) | Value::CompiledFunction { .. } => return None,
+    };
+    Some(expr)
+}
+
+/// Integer literal, negated if needed: the grammar has no negative literals
+fn integer(n: BigInt) -> Expr {
+    let literal = Expr::BigInt(n.abs());
+    if n.is_negative() {
+        Expr::Unary {
+            op: UnaryOp::Neg,
+            expr: Box::new(literal),
+        }
+    } else {
+        literal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn run(interpreter: &mut Interpreter, loader: &mut Loader, source: &str) -> Value {
+        let program = loader.load_source(source, Path::new("")).unwrap();
+        interpreter.run(&program).unwrap()
+    }
+
+    /// Restore a snapshot into a new interpreter
+    fn resume(snapshot: &str) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        let mut loader = Loader::new();
+        run(&mut interpreter, &mut loader, snapshot);
+        interpreter
+    }
+
+    #[test]
+    fn test_snapshot_source() {
+        let mut interpreter = Interpreter::new();
+        let mut loader = Loader::new();
+        run(
+            &mut interpreter,
+            &mut loader,
+            r#"
+            record P(x, y)
+            def double(n) { return n * 2 }
+            def abs(n) { return n }
+            p = P(-1, true)
+            big = 0 - 99999999999999999999
+            f = double
+            n = None
+            e = try { 1 / 0 } catch (e) { e }
+            "#,
+        );
+        let expected = "\
+# Firstlang session
+# Not saved: e = Error(\"Division by zero\")
+record P(x, y)
+def abs(n) {
+    return n
+}
+def double(n) {
+    return n * 2
+}
+big = -99999999999999999999
+f = double
+n = None
+p = P(-1, true)
+";
+        assert_eq!(snapshot(&interpreter, &loader), expected);
+    }
+
+    #[test]
+    fn test_resume_snapshot() {
+        let mut interpreter = Interpreter::new();
+        let mut loader = Loader::new();
+        run(
+            &mut interpreter,
+            &mut loader,
+            r#"
+            record P(x, y)
+            def dist(p) { return abs(p.x) + abs(p.y) }
+            p = P(-3, 4)
+            min = -9223372036854775807 - 1
+            "#,
+        );
+
+        let mut resumed = resume(&snapshot(&interpreter, &loader));
+        assert_eq!(resumed.run(&parse("dist(p)").unwrap()), Ok(Value::Int(7)));
+        assert_eq!(resumed.variables(), interpreter.variables());
+    }
+
+    #[test]
+    fn test_snapshot_imports_modules() {
+        let dir = std::env::temp_dir().join(format!("firstlang-session-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("shapes.fl"),
+            "def area(w, h) { return w * h }\nunit = 1",
+        )
+        .unwrap();
+
+        let mut interpreter = Interpreter::new();
+        let mut loader = Loader::new();
+        run(
+            &mut interpreter,
+            &mut loader,
+            &format!(
+                "import \"{}\"\nx = shapes.area(2, 3)",
+                dir.join("shapes.fl").display()
+            ),
+        );
+        let snapshot = snapshot(&interpreter, &loader);
+        assert!(!snapshot.contains("def shapes.area"));
+
+        let mut resumed = resume(&snapshot);
+        let program = parse("shapes.area(x, shapes.unit)").unwrap();
+        assert_eq!(resumed.run(&program), Ok(Value::Int(6)));
+    }
+}
@@ -0,0 +1,485 @@
+//! Compiler from the Firstlang AST to bytecode
+//!
+//! Every function (and the top level of the program) becomes a `Function`
+//! with its own instruction list. Variables are accessed the way the
+//! resolver bound them: locals through frame slots, top-level variables and
+//! functions through their interned name.
+
+use std::rc::Rc;
+
+use crate::ast::{constructor_body, Binding, Expr, Program, Stmt, StmtKind};
+use crate::interpreter::Value;
+use crate::module::module_name;
+use crate::resolver::resolve;
+use crate::vm::OpCode;
+
+/// A compiled program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bytecode {
+    /// Every compiled function, including the top-level `<main>`
+    pub functions: Vec<Function>,
+    /// Constant pool shared by all functions
+    pub constants: Vec<Value>,
+    /// Interned identifiers; globals are indexed by their name's position
+    pub names: Vec<String>,
+    /// Index of the top-level function in `functions`
+    pub main: usize,
+    /// Every record type built by a `MakeRecord`
+    pub records: Vec<RecordType>,
+}
+
+/// Name and fields of a record, in declaration order
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordType {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+/// A compiled function body
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    /// Shared with the function values referring to this function
+    pub params: Rc<[String]>,
+    /// Name of each local slot; parameters come first
+    pub locals: Vec<String>,
+    pub code: Vec<OpCode>,
+}
+
+/// Resolve and compile a parsed program to bytecode
+pub fn compile(program: &Program) -> Result<Bytecode, String> {
+    let mut program = program.clone();
+    resolve(&mut program)?;
+
+    let mut compiler = Compiler::new();
+    let main = compiler.compile_function("<main>", &[], &program);
+    compiler.bytecode.main = main;
+    Ok(compiler.bytecode)
+}
+
+/// AST to bytecode compiler
+struct Compiler {
+    bytecode: Bytecode,
+    /// Name of each local slot of the function currently being compiled
+    locals: Vec<String>,
+    /// Instructions of the function currently being compiled
+    code: Vec<OpCode>,
+    /// Number of `try` bodies of the current function being compiled
+    try_depth: usize,
+    /// Number of functions being compiled, 1 for `<main>` itself
+    function_depth: usize,
+}
+
+impl Compiler {
+    fn new() -> Self {
+        Compiler {
+            bytecode: Bytecode {
+                functions: Vec::new(),
+                constants: Vec::new(),
+                names: Vec::new(),
+                main: 0,
+                records: Vec::new(),
+            },
+            locals: Vec::new(),
+            code: Vec::new(),
+            try_depth: 0,
+            function_depth: 0,
+        }
+    }
+
+    /// Compile a function body in a fresh scope and return its index
+    fn compile_function(&mut self, name: &str, params: &[String], body: &[Stmt]) -> usize {
+        // Parameters take the first slots
+        let outer_locals = std::mem::replace(&mut self.locals, params.to_vec());
+        let outer_code = std::mem::take(&mut self.code);
+        let outer_try_depth = std::mem::take(&mut self.try_depth);
+        self.function_depth += 1;
+
+        self.compile_block(body);
+        self.emit(OpCode::Return);
+        self.try_depth = outer_try_depth;
+        self.function_depth -= 1;
+
+        let code = std::mem::replace(&mut self.code, outer_code);
+        let locals = std::mem::replace(&mut self.locals, outer_locals);
+
+        self.bytecode.functions.push(Function {
+            name: name.to_string(),
+            params: params.into(),
+            locals,
+            code,
+        });
+        self.bytecode.functions.len() - 1
+    }
+
+    /// Compile a list of statements, leaving the value of the last one on the stack
+    fn compile_block(&mut self, stmts: &[Stmt]) {
+        if stmts.is_empty() {
+            self.emit(OpCode::None);
+            return;
+        }
+        for (i, stmt) in stmts.iter().enumerate() {
+            if i > 0 {
+                self.emit(OpCode::Pop);
+            }
+            self.compile_stmt(stmt);
+        }
+    }
+
+    /// Compile a statement, leaving its value on the stack
+    fn compile_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Function { name, params, body } => {
+                let function = self.compile_function(name, params, body);
+                let name = self.intern(name);
+                self.emit(OpCode::DefineFunction { function, name });
+                self.emit(OpCode::None);
+            }
+            // Inside a `try` the call must finish before the handler is
+            // dropped, and at the top level `<main>` must keep its frame so
+            // that returning ends the program
+            StmtKind::Return(Expr::Call {
+                name,
+                binding,
+                args,
+            }) if self.try_depth == 0 && self.function_depth > 1 => {
+                self.compile_call(name, *binding, args, true)
+            }
+            StmtKind::Return(expr) => {
+                self.compile_expr(expr);
+                self.emit(OpCode::Return);
+            }
+            StmtKind::Raise(expr) => {
+                self.compile_expr(expr);
+                self.emit(OpCode::Raise);
+            }
+            StmtKind::Assignment {
+                name,
+                binding,
+                value,
+            } => {
+                self.compile_expr(value);
+                self.compile_store(name, *binding);
+                self.emit(OpCode::None);
+            }
+            StmtKind::FieldAssignment {
+                name,
+                binding,
+                fields,
+                value,
+            } => {
+                // Push the variable and every record along the path but the last...
+                for depth in 0..fields.len() {
+                    self.compile_var(name, *binding);
+                    for field in &fields[..depth] {
+                        let field = self.intern(field);
+                        self.emit(OpCode::GetField(field));
+                    }
+                }
+                // ...then set each field on its record, innermost first
+                self.compile_expr(value);
+                for field in fields.iter().rev() {
+                    let field = self.intern(field);
+                    self.emit(OpCode::SetField(field));
+                }
+                self.compile_store(name, *binding);
+                self.emit(OpCode::None);
+            }
+            StmtKind::Record { name, fields } => {
+                let body = constructor_body(name, fields, stmt.line);
+                let function = self.compile_function(name, fields, &body);
+                let name = self.intern(name);
+                self.emit(OpCode::DefineFunction { function, name });
+                self.emit(OpCode::None);
+            }
+            // Only affects name resolution
+            StmtKind::Global(_) => {
+                self.emit(OpCode::None);
+            }
+            StmtKind::Import { path, names } => {
+                let module = module_name(path);
+                for import in names {
+                    let from = self.intern(&format!("{}.{}", module, import.name));
+                    let to = self.intern(&import.alias);
+                    self.emit(OpCode::Import { from, to });
+                }
+                self.emit(OpCode::None);
+            }
+            StmtKind::Expr(expr) => self.compile_expr(expr),
+        }
+    }
+
+    /// Compile an expression, leaving its value on the stack
+    fn compile_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Int(n) => self.emit_constant(Value::Int(*n)),
+            Expr::BigInt(n) => self.emit_constant(Value::BigInt(n.clone())),
+            Expr::Bool(b) => self.emit_constant(Value::Bool(*b)),
+            Expr::None => {
+                self.emit(OpCode::None);
+            }
+            Expr::Var { name, binding } => self.compile_var(name, *binding),
+            Expr::Unary { op, expr } => {
+                self.compile_expr(expr);
+                self.emit(OpCode::Unary(*op));
+            }
+            Expr::Binary { op, left, right } => {
+                self.compile_expr(left);
+                self.compile_expr(right);
+                self.emit(OpCode::Binary(*op));
+            }
+            Expr::Call {
+                name,
+                binding,
+                args,
+            } => self.compile_call(name, *binding, args, false),
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.compile_expr(cond);
+                let jump_to_else = self.emit(OpCode::JumpIfFalse(0));
+                self.compile_block(then_branch);
+                let jump_to_end = self.emit(OpCode::Jump(0));
+                self.patch_jump(jump_to_else);
+                self.compile_block(else_branch);
+                self.patch_jump(jump_to_end);
+            }
+            Expr::While { cond, body } => {
+                let loop_start = self.code.len();
+                self.compile_expr(cond);
+                let exit = self.emit(OpCode::ExitLoopIfFalse(0));
+                self.compile_block(body);
+                self.emit(OpCode::Pop);
+                self.emit(OpCode::Jump(loop_start));
+                self.patch_jump(exit);
+                self.emit(OpCode::None);
+            }
+            Expr::Block(stmts) => self.compile_block(stmts),
+            Expr::Try {
+                body,
+                name,
+                binding,
+                handler,
+            } => {
+                let push_handler = self.emit(OpCode::PushHandler(0));
+                self.try_depth += 1;
+                self.compile_block(body);
+                self.try_depth -= 1;
+                self.emit(OpCode::PopHandler);
+                let jump_to_end = self.emit(OpCode::Jump(0));
+                self.patch_jump(push_handler);
+                self.compile_store(name, *binding);
+                self.compile_block(handler);
+                self.patch_jump(jump_to_end);
+            }
+            Expr::Field { expr, field } => {
+                self.compile_expr(expr);
+                let field = self.intern(field);
+                self.emit(OpCode::GetField(field));
+            }
+            Expr::Record { name, fields } => {
+                for (_, value) in fields {
+                    self.compile_expr(value);
+                }
+                self.bytecode.records.push(RecordType {
+                    name: name.clone(),
+                    fields: fields.iter().map(|(field, _)| field.clone()).collect(),
+                });
+                self.emit(OpCode::MakeRecord(self.bytecode.records.len() - 1));
+            }
+        }
+    }
+
+    /// Pop the top of the stack into a variable
+    fn compile_store(&mut self, name: &str, binding: Binding) {
+        match binding {
+            Binding::Local(slot) => {
+                self.record_local(slot, name);
+                self.emit(OpCode::SetLocal(slot));
+            }
+            _ => {
+                let name = self.intern(name);
+                self.emit(OpCode::SetGlobal(name));
+            }
+        }
+    }
+
+    /// Call a function with `args`; a tail call replaces the current frame
+    /// instead of returning to it
+    fn compile_call(&mut self, name: &str, binding: Binding, args: &[Expr], tail: bool) {
+        for arg in args {
+            self.compile_expr(arg);
+        }
+        let argc = args.len();
+        if let Binding::Function = binding {
+            let name = self.intern(name);
+            self.emit(if tail {
+                OpCode::TailCallFunction { name, argc }
+            } else {
+                OpCode::CallFunction { name, argc }
+            });
+        } else {
+            // A function value stored in a variable
+            self.compile_var(name, binding);
+            let name = self.intern(name);
+            self.emit(if tail {
+                OpCode::TailCall { name, argc }
+            } else {
+                OpCode::Call { name, argc }
+            });
+        }
+    }
+
+    /// Push the value of a variable
+    fn compile_var(&mut self, name: &str, binding: Binding) {
+        match binding {
+            Binding::Local(slot) => {
+                self.record_local(slot, name);
+                self.emit(OpCode::GetLocal(slot));
+            }
+            Binding::Global => {
+                let name = self.intern(name);
+                self.emit(OpCode::GetGlobal(name));
+            }
+            Binding::Function | Binding::Unresolved => {
+                let name = self.intern(name);
+                self.emit(OpCode::GetFunction(name));
+            }
+        }
+    }
+
+    /// Remember the name of a slot, growing the current function's frame
+    fn record_local(&mut self, slot: usize, name: &str) {
+        if self.locals.len() <= slot {
+            self.locals.resize(slot + 1, String::new());
+        }
+        self.locals[slot] = name.to_string();
+    }
+
+    /// Append an instruction and return its position
+    fn emit(&mut self, op: OpCode) -> usize {
+        self.code.push(op);
+        self.code.len() - 1
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        self.bytecode.constants.push(value);
+        self.emit(OpCode::Constant(self.bytecode.constants.len() - 1));
+    }
+
+    /// Point a previously emitted jump at the next instruction
+    fn patch_jump(&mut self, at: usize) {
+        let target = self.code.len();
+        self.code[at] = match self.code[at] {
+            OpCode::Jump(_) => OpCode::Jump(target),
+            OpCode::JumpIfFalse(_) => OpCode::JumpIfFalse(target),
+            OpCode::ExitLoopIfFalse(_) => OpCode::ExitLoopIfFalse(target),
+            OpCode::PushHandler(_) => OpCode::PushHandler(target),
+            op => unreachable!("Cannot patch non-jump instruction {:?}", op),
+        };
+    }
+
+    fn intern(&mut self, name: &str) -> usize {
+        match self.bytecode.names.iter().position(|n| n == name) {
+            Some(index) => index,
+            None => {
+                self.bytecode.names.push(name.to_string());
+                self.bytecode.names.len() - 1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::BinaryOp;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_locals_resolved_to_slots() {
+        let bytecode = compile(&parse("def f(x) { y = x + 2\nreturn y }").unwrap()).unwrap();
+        let f = bytecode.functions.iter().find(|f| f.name == "f").unwrap();
+        assert_eq!(f.locals, vec!["x", "y"]);
+        assert_eq!(
+            f.code,
+            vec![
+                OpCode::GetLocal(0),
+                OpCode::Constant(0),
+                OpCode::Binary(BinaryOp::Add),
+                OpCode::SetLocal(1),
+                OpCode::None,
+                OpCode::Pop,
+                OpCode::GetLocal(1),
+                OpCode::Return,
+                OpCode::Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_top_level_variables_are_globals() {
+        let bytecode = compile(&parse("x = 1\nx").unwrap()).unwrap();
+        let main = &bytecode.functions[bytecode.main];
+        assert_eq!(bytecode.names, vec!["x"]);
+        assert!(main.locals.is_empty());
+        assert_eq!(main.code[1], OpCode::SetGlobal(0));
+        assert_eq!(main.code[4], OpCode::GetGlobal(0));
+    }
+
+    #[test]
+    fn test_functions_called_by_name() {
+        let bytecode =
+            compile(&parse("def g(n) { return n }\ndef f(n) { return g(n) + 1 }").unwrap())
+                .unwrap();
+        let f = bytecode.functions.iter().find(|f| f.name == "f").unwrap();
+        assert_eq!(bytecode.names, vec!["g", "f"]);
+        assert!(f.code.contains(&OpCode::CallFunction { name: 0, argc: 1 }));
+    }
+
+    #[test]
+    fn test_returned_call_is_tail_call() {
+        let bytecode = compile(&parse("def f(n) { return f(n - 1) }").unwrap()).unwrap();
+        let f = bytecode.functions.iter().find(|f| f.name == "f").unwrap();
+        assert_eq!(
+            f.code[f.code.len() - 2..],
+            [
+                OpCode::TailCallFunction { name: 0, argc: 1 },
+                OpCode::Return
+            ]
+        );
+    }
+
+    #[test]
+    fn test_top_level_returned_call_is_not_tail_call() {
+        let bytecode = compile(&parse("def f() { return 1 }\nreturn f()").unwrap()).unwrap();
+        let main = &bytecode.functions[bytecode.main];
+        assert!(main
+            .code
+            .contains(&OpCode::CallFunction { name: 0, argc: 0 }));
+        assert!(!main
+            .code
+            .iter()
+            .any(|op| matches!(op, OpCode::TailCallFunction { .. })));
+    }
+
+    #[test]
+    fn test_while_jumps_back_to_condition() {
+        let bytecode = compile(&parse("while (false) { 1 }").unwrap()).unwrap();
+        let main = &bytecode.functions[bytecode.main];
+        assert_eq!(
+            main.code,
+            vec![
+                OpCode::Constant(0),
+                OpCode::ExitLoopIfFalse(5),
+                OpCode::Constant(1),
+                OpCode::Pop,
+                OpCode::Jump(0),
+                OpCode::None,
+                OpCode::Return,
+            ]
+        );
+    }
+}
@@ -0,0 +1,17 @@
+//! Bytecode compiler and stack VM for Firstlang
+//!
+//! An alternative to the tree-walking interpreter: the AST is compiled once
+//! into flat instruction lists with variables resolved to slots, then run on
+//! a stack machine with explicit call frames. Both backends produce the same
+//! `Value`s and error messages.
+
+pub mod bytecode;
+pub mod opcode;
+#[allow(clippy::module_inception)]
+pub mod vm;
+
+pub use crate::vm::{
+    bytecode::{compile, Bytecode, Function, RecordType},
+    opcode::OpCode,
+    vm::VM,
+};
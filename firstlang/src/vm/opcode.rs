@@ -0,0 +1,66 @@
+//! Instruction set for the Firstlang bytecode VM
+
+use crate::ast::{BinaryOp, UnaryOp};
+
+/// A single VM instruction
+///
+/// Operands are indices into the constant pool, the current frame's local
+/// slots, the interned name table or the current function's code (for jumps).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    /// Push a constant from the constant pool
+    Constant(usize),
+    /// Push `None`
+    None,
+    /// Discard the top of the stack
+    Pop,
+    /// Push the value stored in a local slot
+    GetLocal(usize),
+    /// Pop the top of the stack into a local slot
+    SetLocal(usize),
+    /// Push the top-level variable with a name
+    GetGlobal(usize),
+    /// Pop the top of the stack into the top-level variable with a name
+    SetGlobal(usize),
+    /// Push the function bound to a name
+    GetFunction(usize),
+    /// Bind a compiled function to its name
+    DefineFunction { function: usize, name: usize },
+    /// Bind the function or top-level variable named `from` to the name `to`
+    Import { from: usize, to: usize },
+    /// Apply a unary operator to the top of the stack
+    Unary(UnaryOp),
+    /// Apply a binary operator to the top two values of the stack
+    Binary(BinaryOp),
+    /// Pop the field values of a record type (in declaration order) and push the record
+    MakeRecord(usize),
+    /// Replace the record on top of the stack with the value of a named field
+    GetField(usize),
+    /// Pop a value and a record and push a copy of the record with a named field set
+    SetField(usize),
+    /// Unconditional jump
+    Jump(usize),
+    /// Pop an `if` condition and jump when it is false
+    JumpIfFalse(usize),
+    /// Pop a `while` condition and jump out of the loop when it is false
+    ExitLoopIfFalse(usize),
+    /// Call the function bound to a name with `argc` arguments
+    CallFunction { name: usize, argc: usize },
+    /// Pop a function value and call it with `argc` arguments
+    ///
+    /// `name` is only used for error messages.
+    Call { name: usize, argc: usize },
+    /// `CallFunction` in tail position: the callee replaces the current frame
+    TailCallFunction { name: usize, argc: usize },
+    /// `Call` in tail position: the callee replaces the current frame
+    TailCall { name: usize, argc: usize },
+    /// Return the top of the stack from the current function
+    Return,
+    /// Catch exceptions with the handler at an instruction, which finds the
+    /// exception on top of the stack
+    PushHandler(usize),
+    /// Stop catching exceptions with the innermost handler
+    PopHandler,
+    /// Pop a value and raise it as an exception
+    Raise,
+}
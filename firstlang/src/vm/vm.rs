@@ -0,0 +1,446 @@
+//! Stack-based virtual machine for Firstlang bytecode
+
+use crate::interpreter::{
+    describe, eval_binary_op, eval_unary_op, get_field, set_field, Unwind, Value,
+};
+use crate::vm::{Bytecode, OpCode};
+
+/// An active function call
+#[derive(Debug, Clone, Copy)]
+struct CallFrame {
+    /// Index of the running function in `Bytecode::functions`
+    function: usize,
+    /// Name it was called by, `None` for the top level
+    name: Option<usize>,
+    /// Next instruction to execute
+    ip: usize,
+    /// Start of this call's slots in `VM::locals`
+    locals_base: usize,
+    /// Height of the operand stack when the call started
+    stack_base: usize,
+}
+
+/// An active `try`, and how to get back to it when something raises
+#[derive(Debug, Clone, Copy)]
+struct Handler {
+    /// Number of frames, the innermost running the `try`
+    frames: usize,
+    /// Height of the operand stack when the `try` started
+    stack: usize,
+    /// Number of local slots when the `try` started
+    locals: usize,
+    /// Instruction of the `catch` block
+    target: usize,
+}
+
+/// The virtual machine state
+pub struct VM {
+    bytecode: Bytecode,
+    /// Operand stack
+    stack: Vec<Value>,
+    /// Local slots of every active call, `None` until first assigned
+    locals: Vec<Option<Value>>,
+    frames: Vec<CallFrame>,
+    /// Active `try`s, innermost last
+    handlers: Vec<Handler>,
+    /// Top-level variables, indexed like `Bytecode::names`
+    globals: Vec<Option<Value>>,
+    /// Compiled function bound to each name
+    functions: Vec<Option<usize>>,
+}
+
+impl VM {
+    pub fn new(bytecode: Bytecode) -> Self {
+        let names = bytecode.names.len();
+        VM {
+            bytecode,
+            stack: Vec::new(),
+            locals: Vec::new(),
+            frames: Vec::new(),
+            handlers: Vec::new(),
+            globals: vec![None; names],
+            functions: vec![None; names],
+        }
+    }
+
+    /// Run the program and return the value of its top-level code
+    pub fn run(&mut self) -> Result<Value, String> {
+        let main = self.bytecode.main;
+        self.enter(main, None, 0);
+
+        loop {
+            match self.execute() {
+                Ok(value) => return Ok(value),
+                Err(Unwind::Exception(value)) => self.catch(value)?,
+                Err(abort) => return Err(abort.into()),
+            }
+        }
+    }
+
+    /// Execute instructions until the program returns or raises
+    fn execute(&mut self) -> Result<Value, Unwind> {
+        loop {
+            let frame = self
+                .frames
+                .last_mut()
+                .expect("Call stack should never be empty");
+            let op = self.bytecode.functions[frame.function].code[frame.ip];
+            frame.ip += 1;
+            let locals_base = frame.locals_base;
+
+            match op {
+                OpCode::Constant(index) => {
+                    let value = self.bytecode.constants[index].clone();
+                    self.stack.push(value);
+                }
+                OpCode::None => self.stack.push(Value::NONE),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal(slot) => match &self.locals[locals_base + slot] {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => {
+                        let function = &self.bytecode.functions[self.current_frame().function];
+                        return Err(format!("Undefined variable: {}", function.locals[slot]).into());
+                    }
+                },
+                OpCode::SetLocal(slot) => {
+                    let value = self.pop();
+                    self.locals[locals_base + slot] = Some(value);
+                }
+                OpCode::GetGlobal(name) => match &self.globals[name] {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => {
+                        return Err(
+                            format!("Undefined variable: {}", self.bytecode.names[name]).into()
+                        )
+                    }
+                },
+                OpCode::SetGlobal(name) => {
+                    let value = self.pop();
+                    self.globals[name] = Some(value);
+                }
+                OpCode::GetFunction(name) => match self.functions[name] {
+                    Some(function) => self.stack.push(Value::CompiledFunction {
+                        index: function,
+                        params: self.bytecode.functions[function].params.clone(),
+                    }),
+                    None => {
+                        return Err(
+                            format!("Undefined variable: {}", self.bytecode.names[name]).into()
+                        )
+                    }
+                },
+                OpCode::DefineFunction { function, name } => {
+                    self.functions[name] = Some(function);
+                }
+                OpCode::Import { from, to } => {
+                    if let Some(function) = self.functions[from] {
+                        self.functions[to] = Some(function);
+                    } else {
+                        match &self.globals[from] {
+                            Some(value) => self.globals[to] = Some(value.clone()),
+                            None => {
+                                return Err(format!(
+                                    "Undefined variable: {}",
+                                    self.bytecode.names[from]
+                                )
+                                .into())
+                            }
+                        }
+                    }
+                }
+                OpCode::Unary(op) => {
+                    let value = self.pop();
+                    self.stack.push(eval_unary_op(op, value)?);
+                }
+                OpCode::Binary(op) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(eval_binary_op(op, left, right)?);
+                }
+                OpCode::MakeRecord(index) => {
+                    let record = &self.bytecode.records[index];
+                    let values = self.stack.split_off(self.stack.len() - record.fields.len());
+                    self.stack.push(Value::Record {
+                        name: record.name.clone(),
+                        fields: record.fields.iter().cloned().zip(values).collect(),
+                    });
+                }
+                OpCode::GetField(field) => {
+                    let record = self.pop();
+                    let value = get_field(&record, &self.bytecode.names[field])?;
+                    self.stack.push(value);
+                }
+                OpCode::SetField(field) => {
+                    let value = self.pop();
+                    let record = self.pop();
+                    let record = set_field(record, &self.bytecode.names[field], value)?;
+                    self.stack.push(record);
+                }
+                OpCode::Jump(target) => self.current_frame_mut().ip = target,
+                OpCode::JumpIfFalse(target) => match self.pop() {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => self.current_frame_mut().ip = target,
+                    value => {
+                        return Err(
+                            format!("Condition must be boolean, got {}", describe(&value)).into(),
+                        )
+                    }
+                },
+                OpCode::ExitLoopIfFalse(target) => match self.pop() {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => self.current_frame_mut().ip = target,
+                    value => {
+                        return Err(format!(
+                            "While condition must be boolean, got {}",
+                            describe(&value)
+                        )
+                        .into())
+                    }
+                },
+                OpCode::CallFunction { name, argc } => {
+                    let function = self.function(name)?;
+                    self.call(function, name, argc)?;
+                }
+                OpCode::Call { name, argc } => {
+                    let function = self.callee(name)?;
+                    self.call(function, name, argc)?;
+                }
+                OpCode::TailCallFunction { name, argc } => {
+                    let function = self.function(name)?;
+                    self.tail_call(function, name, argc)?;
+                }
+                OpCode::TailCall { name, argc } => {
+                    let function = self.callee(name)?;
+                    self.tail_call(function, name, argc)?;
+                }
+                OpCode::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().expect("Call stack should never be empty");
+                    self.locals.truncate(frame.locals_base);
+                    self.stack.truncate(frame.stack_base);
+                    // Handlers of `try`s the function returned from
+                    while self
+                        .handlers
+                        .last()
+                        .is_some_and(|handler| handler.frames > self.frames.len())
+                    {
+                        self.handlers.pop();
+                    }
+                    let Some(name) = frame.name else {
+                        return Ok(value);
+                    };
+                    self.stack
+                        .push(value.returned_by(&self.bytecode.names[name]));
+                }
+                OpCode::PushHandler(target) => self.handlers.push(Handler {
+                    frames: self.frames.len(),
+                    stack: self.stack.len(),
+                    locals: self.locals.len(),
+                    target,
+                }),
+                OpCode::PopHandler => {
+                    self.handlers.pop();
+                }
+                OpCode::Raise => return Err(Unwind::Exception(self.pop())),
+            }
+        }
+    }
+
+    /// Unwind to the innermost handler and jump to it with the exception on
+    /// the stack, or report the exception if nothing catches it
+    fn catch(&mut self, exception: Value) -> Result<(), String> {
+        let Some(handler) = self.handlers.pop() else {
+            return Err(Unwind::Exception(exception).into());
+        };
+        self.frames.truncate(handler.frames);
+        self.stack.truncate(handler.stack);
+        self.locals.truncate(handler.locals);
+        self.stack.push(exception);
+        self.current_frame_mut().ip = handler.target;
+        Ok(())
+    }
+
+    /// Compiled function bound to a name
+    fn function(&self, name: usize) -> Result<usize, String> {
+        self.functions[name]
+            .ok_or_else(|| format!("Undefined variable: {}", self.bytecode.names[name]))
+    }
+
+    /// Pop a function value and return the compiled function it refers to
+    fn callee(&mut self, name: usize) -> Result<usize, String> {
+        match self.pop() {
+            Value::CompiledFunction { index, .. } => Ok(index),
+            _ => Err(format!("{} is not a function", self.bytecode.names[name])),
+        }
+    }
+
+    /// Call a compiled function whose `argc` arguments are on top of the stack
+    fn call(&mut self, function: usize, name: usize, argc: usize) -> Result<(), String> {
+        self.check_arity(function, name, argc)?;
+
+        let args_start = self.stack.len() - argc;
+        let locals_base = self.locals.len();
+        self.locals.extend(self.stack.drain(args_start..).map(Some));
+        self.enter(function, Some(name), locals_base);
+        Ok(())
+    }
+
+    /// Like `call`, but the callee takes over the current frame, so tail
+    /// recursion runs in constant space
+    fn tail_call(&mut self, function: usize, name: usize, argc: usize) -> Result<(), String> {
+        self.check_arity(function, name, argc)?;
+
+        let frame = self.frames.pop().expect("Call stack should never be empty");
+        let args_start = self.stack.len() - argc;
+        let args: Vec<_> = self.stack.drain(args_start..).collect();
+        self.stack.truncate(frame.stack_base);
+        self.locals.truncate(frame.locals_base);
+        self.locals.extend(args.into_iter().map(Some));
+        self.enter(function, Some(name), frame.locals_base);
+        Ok(())
+    }
+
+    fn check_arity(&self, function: usize, name: usize, argc: usize) -> Result<(), String> {
+        let expected = self.bytecode.functions[function].params.len();
+        if expected != argc {
+            return Err(format!(
+                "Function {} expects {} arguments, got {}",
+                self.bytecode.names[name], expected, argc
+            ));
+        }
+        Ok(())
+    }
+
+    /// Push a frame for `function`, called by `name`, whose first slots
+    /// start at `locals_base`
+    fn enter(&mut self, function: usize, name: Option<usize>, locals_base: usize) {
+        let num_locals = self.bytecode.functions[function].locals.len();
+        self.locals.resize(locals_base + num_locals, None);
+        self.frames.push(CallFrame {
+            function,
+            name,
+            ip: 0,
+            locals_base,
+            stack_base: self.stack.len(),
+        });
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("Operand stack underflow")
+    }
+
+    fn current_frame(&self) -> &CallFrame {
+        self.frames
+            .last()
+            .expect("Call stack should never be empty")
+    }
+
+    fn current_frame_mut(&mut self) -> &mut CallFrame {
+        self.frames
+            .last_mut()
+            .expect("Call stack should never be empty")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::vm::compile;
+
+    fn run(source: &str) -> Result<Value, String> {
+        let program = parse(source)?;
+        VM::new(compile(&program)?).run()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(run("1 + 2 * 3").unwrap(), Value::Int(7));
+        assert_eq!(run("-(4 - 6)").unwrap(), Value::Int(2));
+    }
+
+    #[test]
+    fn test_block_value_is_last_statement() {
+        assert_eq!(run("x = 1\nx = x + 1").unwrap(), Value::NONE);
+        assert_eq!(run("x = 1\nx + 1").unwrap(), Value::Int(2));
+        assert_eq!(run("").unwrap(), Value::NONE);
+    }
+
+    #[test]
+    fn test_return_inside_loop_leaves_function() {
+        let source = r#"
+            def first_multiple(n, k) {
+                i = 1
+                while (true) {
+                    if (i % k == 0) {
+                        if (i >= n) {
+                            return i
+                        } else {
+                            0
+                        }
+                    } else {
+                        0
+                    }
+                    i = i + 1
+                }
+            }
+            first_multiple(10, 7)
+        "#;
+        assert_eq!(run(source).unwrap(), Value::Int(14));
+    }
+
+    #[test]
+    fn test_function_value_in_local() {
+        let source = r#"
+            def double(x) {
+                return x * 2
+            }
+            f = double
+            f(21)
+        "#;
+        assert_eq!(run(source).unwrap(), Value::Int(42));
+    }
+
+    #[test]
+    fn test_function_value_is_returned() {
+        let source = "def id(x) { return x }\nid";
+        assert!(matches!(
+            run(source).unwrap(),
+            Value::CompiledFunction { params, .. } if *params == ["x"]
+        ));
+    }
+
+    #[test]
+    fn test_top_level_variables_not_visible_in_functions() {
+        let source = r#"
+            x = 1
+            def f() {
+                return x
+            }
+            f()
+        "#;
+        assert_eq!(run(source).unwrap_err(), "Undefined variable: x");
+    }
+
+    #[test]
+    fn test_not_a_function() {
+        assert_eq!(run("x = 1\nx()").unwrap_err(), "x is not a function");
+    }
+
+    #[test]
+    fn test_deep_recursion() {
+        let source = r#"
+            def count(n) {
+                if (n == 0) {
+                    return 0
+                } else {
+                    return 1 + count(n - 1)
+                }
+            }
+            count(100000)
+        "#;
+        assert_eq!(run(source).unwrap(), Value::Int(100000));
+    }
+}
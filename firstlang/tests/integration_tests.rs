@@ -1,9 +1,51 @@
 //! End-to-End Integration Tests for Firstlang
 //!
 //! These tests demonstrate the full capabilities of Firstlang
-//! and serve as examples for the book.
+//! and serve as examples for the book. Every program runs on both
+//! the tree-walking interpreter and the bytecode VM.
 
-use firstlang::{run, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use firstlang::vm::{self, VM};
+use firstlang::{prelude, Interpreter, Loader, Value};
+
+/// Run a program on both backends, checking that they agree on the
+/// resulting value (or error message)
+fn run(source: &str) -> Result<Value, String> {
+    let interpreted = firstlang::run(source);
+    let compiled = firstlang::run_vm(source);
+    assert_eq!(interpreted, compiled, "backends disagree on:\n{}", source);
+    interpreted
+}
+
+/// Load a file with its imports and run it on both backends
+fn run_file(path: &Path) -> Result<Value, String> {
+    let program = Loader::new()
+        .with_functions(prelude::functions())
+        .load_file(path)?;
+    let interpreted = Interpreter::new().run(&program);
+    let compiled =
+        vm::compile(&prelude::with_prelude(&program)).and_then(|bytecode| VM::new(bytecode).run());
+    assert_eq!(
+        interpreted,
+        compiled,
+        "backends disagree on {}",
+        path.display()
+    );
+    interpreted
+}
+
+/// Write `files` to a fresh directory, returning the path of the first one
+fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("firstlang-{}-{}", test, std::process::id()));
+    for (name, source) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+    dir.join(files[0].0)
+}
 
 // =============================================================================
 // Basic Expressions
@@ -322,6 +364,24 @@ fn test_countdown() {
     assert_eq!(run(source).unwrap(), Value::Int(10));
 }
 
+#[test]
+fn test_return_from_inside_loop() {
+    let source = r#"
+        def first_square_above(n) {
+            i = 0
+            while (true) {
+                if (i * i > n) {
+                    return i
+                } else {
+                    i = i + 1
+                }
+            }
+        }
+        first_square_above(50)
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(8));
+}
+
 // =============================================================================
 // Recursion
 // =============================================================================
@@ -420,6 +480,70 @@ fn test_fibonacci_larger() {
     assert_eq!(run(source).unwrap(), Value::Int(6765));
 }
 
+#[test]
+fn test_factorial_promotes_to_big_integer() {
+    let source = r#"
+        def fact(n) {
+            if (n <= 1) {
+                return 1
+            } else {
+                return n * fact(n - 1)
+            }
+        }
+        fact(25)
+    "#;
+    assert_eq!(
+        run(source).unwrap().to_string(),
+        "15511210043330985984000000"
+    );
+}
+
+#[test]
+fn test_fibonacci_big() {
+    let source = r#"
+        def fib(n) {
+            a = 0
+            b = 1
+            i = 0
+            while (i < n) {
+                temp = a + b
+                a = b
+                b = temp
+                i = i + 1
+            }
+            return a
+        }
+        fib(100)
+    "#;
+    assert_eq!(run(source).unwrap().to_string(), "354224848179261915075");
+}
+
+#[test]
+fn test_big_integers_shrink_back() {
+    let source = r#"
+        big = 9223372036854775807 + 1
+        big - 1
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(i64::MAX));
+}
+
+#[test]
+fn test_big_integer_literals_and_comparison() {
+    assert_eq!(
+        run("100000000000000000000 > 99999999999999999999").unwrap(),
+        Value::Bool(true)
+    );
+    assert_eq!(
+        run("100000000000000000000 % 7 == 2").unwrap(),
+        Value::Bool(true)
+    );
+    assert_eq!(run("-9223372036854775808").unwrap(), Value::Int(i64::MIN));
+    assert_eq!(
+        run("100000000000000000000 / 0").unwrap_err(),
+        "Division by zero"
+    );
+}
+
 #[test]
 fn test_sum_to_n_recursive() {
     let source = r#"
@@ -472,6 +596,955 @@ fn test_mutual_recursion_even_odd() {
     assert_eq!(run(source).unwrap(), Value::Bool(true));
 }
 
+#[test]
+fn test_tail_recursion_runs_in_constant_stack() {
+    let source = r#"
+        def loop(n, acc) {
+            if (n == 0) {
+                return acc
+            } else {
+                return loop(n - 1, acc + n)
+            }
+        }
+        loop(1000000, 0)
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(500000500000));
+}
+
+#[test]
+fn test_mutual_tail_recursion_runs_in_constant_stack() {
+    let source = r#"
+        def is_even(n) {
+            if (n == 0) {
+                return true
+            } else {
+                return is_odd(n - 1)
+            }
+        }
+        def is_odd(n) {
+            if (n == 0) {
+                return false
+            } else {
+                return is_even(n - 1)
+            }
+        }
+        is_even(100000)
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn test_tail_call_from_loop() {
+    let source = r#"
+        def count(n) {
+            while (true) {
+                if (n == 0) {
+                    return 0
+                } else {
+                    return count(n - 1)
+                }
+            }
+        }
+        count(100000)
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(0));
+}
+
+#[test]
+fn test_top_level_return_of_call() {
+    let source = r#"
+        def f() {
+            return 1
+        }
+        return f()
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(1));
+
+    let source = r#"
+        def f(n) {
+            return n * 2
+        }
+        g = f
+        return g(21)
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(42));
+}
+
+// =============================================================================
+// Scoping
+// =============================================================================
+
+#[test]
+fn test_block_local_does_not_leak_from_if() {
+    let source = r#"
+        def pick(c) {
+            if (c) {
+                t = 1
+            } else {
+                t = 2
+            }
+            return t
+        }
+        pick(true)
+    "#;
+    let result = run(source);
+    assert!(result.unwrap_err().contains("Undefined variable: t"));
+}
+
+#[test]
+fn test_block_local_does_not_leak_at_top_level() {
+    let source = r#"
+        while (false) {
+            y = 1
+        }
+        y
+    "#;
+    let result = run(source);
+    assert!(result.unwrap_err().contains("Undefined variable: y"));
+}
+
+#[test]
+fn test_assignment_in_block_updates_enclosing_variable() {
+    let source = r#"
+        x = 1
+        if (true) {
+            x = 2
+        } else {
+            x = 3
+        }
+        x
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(2));
+}
+
+#[test]
+fn test_sibling_blocks_have_separate_locals() {
+    let source = r#"
+        def f(c) {
+            if (c) {
+                t = 10
+            } else {
+                t = 20
+            }
+            if (c) {
+                t = 1
+                return t
+            } else {
+                return 0
+            }
+        }
+        f(true)
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(1));
+}
+
+#[test]
+fn test_while_body_local() {
+    let source = r#"
+        total = 0
+        i = 0
+        while (i < 4) {
+            square = i * i
+            total = total + square
+            i = i + 1
+        }
+        total
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(14));
+}
+
+#[test]
+fn test_top_level_not_visible_in_function() {
+    let source = r#"
+        x = 1
+        def f() {
+            return x
+        }
+        f()
+    "#;
+    let result = run(source);
+    assert!(result.unwrap_err().contains("Undefined variable: x"));
+}
+
+#[test]
+fn test_global_read() {
+    let source = r#"
+        x = 10
+        def f() {
+            global x
+            return x + 1
+        }
+        f()
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(11));
+}
+
+#[test]
+fn test_global_mutation() {
+    let source = r#"
+        count = 0
+        def increment() {
+            global count
+            count = count + 1
+        }
+        increment()
+        increment()
+        count
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(2));
+}
+
+#[test]
+fn test_global_created_by_function() {
+    let source = r#"
+        def init() {
+            global limit
+            limit = 5
+        }
+        init()
+        limit
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(5));
+}
+
+#[test]
+fn test_global_after_local_is_error() {
+    let source = r#"
+        def f() {
+            x = 1
+            global x
+        }
+    "#;
+    let result = run(source);
+    assert!(result.unwrap_err().contains("Cannot declare 'x' global"));
+}
+
+#[test]
+fn test_parameter_shadows_top_level_variable() {
+    let source = r#"
+        x = 1
+        def f(x) {
+            x = x + 100
+            return x
+        }
+        f(5) + x
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(106));
+}
+
+#[test]
+fn test_local_shadows_function() {
+    let source = r#"
+        def double(n) {
+            return n * 2
+        }
+        def f() {
+            double = 3
+            return double
+        }
+        f() + double(1)
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(5));
+}
+
+#[test]
+fn test_nested_function() {
+    let source = r#"
+        def outer(n) {
+            def inner(m) {
+                return m * 2
+            }
+            return inner(n) + 1
+        }
+        outer(4)
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(9));
+}
+
+#[test]
+fn test_nested_function_does_not_capture() {
+    let source = r#"
+        def outer() {
+            secret = 1
+            def inner() {
+                return secret
+            }
+            return inner()
+        }
+        outer()
+    "#;
+    let result = run(source);
+    assert!(result.unwrap_err().contains("Undefined variable: secret"));
+}
+
+#[test]
+fn test_recursive_calls_have_separate_locals() {
+    let source = r#"
+        def f(n) {
+            saved = n
+            if (n > 0) {
+                f(n - 1)
+            } else {
+                0
+            }
+            return saved
+        }
+        f(3)
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(3));
+}
+
+#[test]
+fn test_recursion_with_global_counter() {
+    let source = r#"
+        calls = 0
+        def fib(n) {
+            global calls
+            calls = calls + 1
+            if (n < 2) {
+                return n
+            } else {
+                return fib(n - 1) + fib(n - 2)
+            }
+        }
+        fib(10)
+        calls
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(177));
+}
+
+// =============================================================================
+// Modules
+// =============================================================================
+
+#[test]
+fn test_modules_example() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/modules/main.fl");
+    assert_eq!(run_file(&path).unwrap(), Value::Int(72));
+}
+
+#[test]
+fn test_from_import_function_and_variable() {
+    let main = write_files(
+        "from_import",
+        &[
+            ("main.fl", "from consts import answer, half\nhalf(answer)"),
+            ("consts.fl", "answer = 84\ndef half(n) { return n / 2 }"),
+        ],
+    );
+    assert_eq!(run_file(&main).unwrap(), Value::Int(42));
+}
+
+#[test]
+fn test_module_globals_are_shared() {
+    let main = write_files(
+        "module_globals",
+        &[
+            (
+                "main.fl",
+                "import counter\ncounter.bump()\ncounter.bump()\ncounter.count",
+            ),
+            (
+                "counter.fl",
+                "count = 0\ndef bump() {\nglobal count\ncount = count + 1\n}",
+            ),
+        ],
+    );
+    assert_eq!(run_file(&main).unwrap(), Value::Int(2));
+}
+
+#[test]
+fn test_module_runs_once() {
+    // Both main.fl and a.fl import shared.fl; its top-level code runs only once
+    let main = write_files(
+        "module_cache",
+        &[
+            ("main.fl", "import a\nimport shared\nshared.bump()"),
+            ("a.fl", "import shared\nfirst = shared.bump()"),
+            (
+                "shared.fl",
+                "count = 0\ndef bump() {\nglobal count\ncount = count + 1\nreturn count\n}",
+            ),
+        ],
+    );
+    assert_eq!(run_file(&main).unwrap(), Value::Int(2));
+}
+
+#[test]
+fn test_module_names_do_not_clash() {
+    let main = write_files(
+        "module_clash",
+        &[
+            (
+                "main.fl",
+                "import lib\ndef helper() { return 1 }\nhelper() * 10 + lib.twice()",
+            ),
+            (
+                "lib.fl",
+                "def helper() { return 2 }\ndef twice() { return helper() * 2 }",
+            ),
+        ],
+    );
+    assert_eq!(run_file(&main).unwrap(), Value::Int(14));
+}
+
+#[test]
+fn test_imports_relative_to_importing_file() {
+    let main = write_files(
+        "module_paths",
+        &[
+            ("main.fl", "import \"lib/geometry.fl\"\ngeometry.area(3)"),
+            (
+                "lib/geometry.fl",
+                "from math import square\ndef area(n) { return square(n) }",
+            ),
+            ("lib/math.fl", "def square(n) { return n * n }"),
+        ],
+    );
+    assert_eq!(run_file(&main).unwrap(), Value::Int(9));
+}
+
+#[test]
+fn test_module_names_need_qualifying() {
+    let main = write_files(
+        "module_unqualified",
+        &[
+            ("main.fl", "import math\nsquare(2)"),
+            ("math.fl", "def square(n) { return n * n }"),
+        ],
+    );
+    let result = run_file(&main);
+    assert!(result.unwrap_err().contains("Undefined variable: square"));
+}
+
+#[test]
+fn test_import_missing_member() {
+    let main = write_files(
+        "module_member",
+        &[
+            ("main.fl", "from math import cube"),
+            ("math.fl", "def square(n) { return n * n }"),
+        ],
+    );
+    let result = run_file(&main);
+    assert!(result
+        .unwrap_err()
+        .contains("Module 'math' has no member 'cube'"));
+}
+
+#[test]
+fn test_modules_can_use_prelude() {
+    let main = write_files(
+        "module_prelude",
+        &[
+            ("main.fl", "from dist import distance\ndistance(7, 3)"),
+            ("dist.fl", "def distance(a, b) { return abs(a - b) }"),
+        ],
+    );
+    assert_eq!(run_file(&main).unwrap(), Value::Int(4));
+}
+
+#[test]
+fn test_cyclic_import() {
+    let main = write_files(
+        "module_cycle",
+        &[
+            ("main.fl", "import a"),
+            ("a.fl", "import b"),
+            ("b.fl", "import a"),
+        ],
+    );
+    let result = run_file(&main);
+    assert!(result
+        .unwrap_err()
+        .contains("Cyclic import: a.fl -> b.fl -> a.fl"));
+}
+
+#[test]
+fn test_import_missing_file() {
+    let main = write_files("module_missing", &[("main.fl", "import nowhere")]);
+    let result = run_file(&main);
+    assert!(result.unwrap_err().contains("Cannot read"));
+}
+
+#[test]
+fn test_import_only_at_top_level() {
+    let main = write_files(
+        "module_nested",
+        &[
+            ("main.fl", "def f() {\nimport math\nreturn 1\n}"),
+            ("math.fl", "def square(n) { return n * n }"),
+        ],
+    );
+    let result = run_file(&main);
+    assert!(result
+        .unwrap_err()
+        .contains("imports are only allowed at the top level"));
+}
+
+// =============================================================================
+// Prelude
+// =============================================================================
+
+#[test]
+fn test_prelude_functions() {
+    assert_eq!(run("abs(-3)").unwrap(), Value::Int(3));
+    assert_eq!(run("max(2, 9)").unwrap(), Value::Int(9));
+    assert_eq!(run("min(2, 9)").unwrap(), Value::Int(2));
+    assert_eq!(run("pow(3, 4)").unwrap(), Value::Int(81));
+    assert_eq!(run("gcd(84, 36)").unwrap(), Value::Int(12));
+}
+
+#[test]
+fn test_prelude_in_functions() {
+    let source = r#"
+        def lcm(a, b) {
+            return abs(a * b) / gcd(a, b)
+        }
+        lcm(4, 6)
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(12));
+}
+
+#[test]
+fn test_prelude_function_redefined() {
+    let source = r#"
+        def abs(n) {
+            return 42
+        }
+        abs(-1)
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(42));
+}
+
+// =============================================================================
+// Records
+// =============================================================================
+
+#[test]
+fn test_record_constructor_and_fields() {
+    let source = r#"
+        record Point(x, y)
+        p = Point(3, 4)
+        p.x * p.x + p.y * p.y
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(25));
+}
+
+#[test]
+fn test_record_display() {
+    let source = r#"
+        record Point(x, y)
+        record Line(start, end)
+        Line(Point(0, 0), Point(1, 2))
+    "#;
+    assert_eq!(
+        run(source).unwrap().to_string(),
+        "Line(start=Point(x=0, y=0), end=Point(x=1, y=2))"
+    );
+}
+
+#[test]
+fn test_record_field_update_copies() {
+    let source = r#"
+        record Point(x, y)
+        p = Point(1, 2)
+        q = p
+        q.x = 10
+        p.x + q.x
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(11));
+}
+
+#[test]
+fn test_nested_field_update() {
+    let source = r#"
+        record Point(x, y)
+        record Line(start, end)
+        l = Line(Point(0, 0), Point(1, 2))
+        l.end.y = 5
+        l.end.y + l.start.y
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(5));
+}
+
+#[test]
+fn test_records_in_functions() {
+    let source = r#"
+        record Point(x, y)
+        def add(a, b) {
+            return Point(a.x + b.x, a.y + b.y)
+        }
+        def scale(p, k) {
+            p.x = p.x * k
+            p.y = p.y * k
+            return p
+        }
+        scale(add(Point(1, 2), Point(3, 4)), 10).y
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(60));
+}
+
+#[test]
+fn test_record_structural_equality() {
+    let source = r#"
+        record Point(x, y)
+        record Pair(x, y)
+        p = Point(1, 2)
+        if (p == Point(1, 2)) {
+            if (p != Point(2, 1)) {
+                Point(1, 2) == Pair(1, 2)
+            } else {
+                true
+            }
+        } else {
+            true
+        }
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Bool(false));
+}
+
+#[test]
+fn test_record_errors() {
+    let point = "record Point(x, y)\n";
+    assert_eq!(
+        run(&format!("{}Point(1, 2).z", point)).unwrap_err(),
+        "Record Point has no field 'z'"
+    );
+    assert_eq!(
+        run(&format!("{}p = Point(1, 2)\np.z = 3", point)).unwrap_err(),
+        "Record Point has no field 'z'"
+    );
+    assert_eq!(
+        run(&format!("{}Point(1)", point)).unwrap_err(),
+        "Function Point expects 2 arguments, got 1"
+    );
+    assert_eq!(
+        run("n = 1\nn.x").unwrap_err(),
+        "Cannot access field 'x' of Int(1)"
+    );
+    assert_eq!(
+        run("record Point(x, x)").unwrap_err(),
+        "Duplicate field 'x' in record 'Point'"
+    );
+}
+
+#[test]
+fn test_record_from_module() {
+    let main = write_files(
+        "record_module",
+        &[
+            (
+                "main.fl",
+                "import geo\np = geo.Point(1, 2)\np.y = geo.origin.y + 5\np",
+            ),
+            ("geo.fl", "record Point(x, y)\norigin = Point(0, 1)"),
+        ],
+    );
+    assert_eq!(run_file(&main).unwrap().to_string(), "geo.Point(x=1, y=6)");
+}
+
+// =============================================================================
+// Exceptions
+// =============================================================================
+
+#[test]
+fn test_catch_raised_value() {
+    let source = r#"
+        def check(n) {
+            if (n < 0) {
+                raise n
+            } else {
+                return n
+            }
+        }
+        try { check(-5) } catch (e) { 0 - e }
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(5));
+}
+
+#[test]
+fn test_catch_runtime_error() {
+    assert_eq!(
+        run("try { 1 / 0 } catch (e) { e }").unwrap(),
+        Value::Error("Division by zero".to_string())
+    );
+    let source = r#"
+        def f(x) { return x }
+        try { f(1, 2) } catch (e) { e }
+    "#;
+    assert_eq!(
+        run(source).unwrap().to_string(),
+        "Error(\"Function f expects 1 arguments, got 2\")"
+    );
+}
+
+#[test]
+fn test_uncaught_exceptions() {
+    assert_eq!(run("raise 5").unwrap_err(), "Uncaught exception: 5");
+    // Re-raising a runtime error reports it as if it was never caught
+    assert_eq!(
+        run("try { 1 % 0 } catch (e) { raise e }").unwrap_err(),
+        "Modulo by zero"
+    );
+}
+
+#[test]
+fn test_exception_unwinds_deep_recursion() {
+    let source = r#"
+        def dive(n) {
+            if (n == 0) {
+                raise 42
+            } else {
+                return 1 + dive(n - 1)
+            }
+        }
+        try { dive(30) } catch (e) { e }
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(42));
+}
+
+#[test]
+fn test_returned_call_inside_try_is_caught() {
+    let source = r#"
+        def fail() { raise 7 }
+        def safe() {
+            try {
+                return fail()
+            } catch (e) {
+                return e * 2
+            }
+        }
+        safe()
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(14));
+}
+
+#[test]
+fn test_handler_ends_with_its_function() {
+    let source = r#"
+        def quiet() {
+            try { return 1 } catch (e) { return 2 }
+        }
+        def noisy() {
+            quiet()
+            raise 3
+        }
+        try { noisy() } catch (e) { e * 10 }
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(30));
+}
+
+#[test]
+fn test_nested_try_and_loops() {
+    let source = r#"
+        i = 0
+        total = 0
+        while (i < 5) {
+            x = try {
+                if (i % 2 == 0) { raise i } else { i }
+            } catch (e) {
+                try { raise e * 10 } catch (inner) { inner }
+            }
+            total = total + x
+            i = i + 1
+        }
+        total
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(64));
+}
+
+#[test]
+fn test_raise_record() {
+    let source = r#"
+        record NotFound(key)
+        try { raise NotFound(3) } catch (e) { e.key }
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(3));
+}
+
+// =============================================================================
+// None
+// =============================================================================
+
+#[test]
+fn test_none_literal() {
+    assert_eq!(run("None").unwrap(), Value::NONE);
+    assert_eq!(run("None is None").unwrap(), Value::Bool(true));
+    assert_eq!(run("1 is None").unwrap(), Value::Bool(false));
+    assert_eq!(run("1 is not None").unwrap(), Value::Bool(true));
+    assert_eq!(run("None == None").unwrap(), Value::Bool(true));
+    assert_eq!(run("None == 0").unwrap(), Value::Bool(false));
+    assert_eq!(run("false != None").unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn test_function_without_return_gives_none() {
+    let source = r#"
+        def log(x) {
+            y = x
+        }
+        log(1) is None
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn test_none_for_not_found() {
+    let source = r#"
+        # Smallest divisor of n between 2 and limit, or None
+        def divisor(n, limit) {
+            d = 2
+            while (d <= limit) {
+                if (n % d == 0) {
+                    return d
+                } else {
+                    d = d + 1
+                }
+            }
+            return None
+        }
+        found = divisor(91, 10)
+        missing = divisor(97, 10)
+        if (missing is None) { found } else { 0 }
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(7));
+}
+
+#[test]
+fn test_none_in_records() {
+    let source = r#"
+        record Node(value, next)
+        def length(node) {
+            if (node is None) { return 0 } else { return 1 + length(node.next) }
+        }
+        length(Node(1, Node(2, Node(3, None))))
+    "#;
+    assert_eq!(run(source).unwrap(), Value::Int(3));
+}
+
+#[test]
+fn test_none_errors_name_the_function() {
+    let nothing = "def nothing() { x = 1 }\n";
+    assert_eq!(
+        run(&format!("{}nothing() + 1", nothing)).unwrap_err(),
+        "Cannot apply Add to None (returned by nothing) and Int(1)"
+    );
+    assert_eq!(
+        run(&format!("{}x = nothing()\ny = -x", nothing)).unwrap_err(),
+        "Cannot apply Neg to None (returned by nothing)"
+    );
+    assert_eq!(
+        run(&format!("{}if (nothing()) {{ 1 }} else {{ 2 }}", nothing)).unwrap_err(),
+        "Condition must be boolean, got None (returned by nothing)"
+    );
+    // The function that returned nothing first is named, not the callers passing it on
+    assert_eq!(
+        run(&format!(
+            "{}def outer() {{ return nothing() }}\nouter() * 2",
+            nothing
+        ))
+        .unwrap_err(),
+        "Cannot apply Mul to None (returned by nothing) and Int(2)"
+    );
+    assert_eq!(
+        run("None + 1").unwrap_err(),
+        "Cannot apply Add to None and Int(1)"
+    );
+}
+
+#[test]
+fn test_none_is_a_keyword() {
+    assert!(run("None = 1").is_err());
+    assert!(run("is = 1").is_err());
+}
+
+// =============================================================================
+// Formatting
+// =============================================================================
+
+/// Source of every example program, keyed by its path under `examples/`
+fn examples() -> Vec<(String, String)> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut examples = Vec::new();
+    for dir in [root.clone(), root.join("modules")] {
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "fl") {
+                let name = path.strip_prefix(&root).unwrap().display().to_string();
+                examples.push((name, fs::read_to_string(&path).unwrap()));
+            }
+        }
+    }
+    examples.sort();
+    examples
+}
+
+#[test]
+fn test_format_examples_is_idempotent() {
+    for (name, source) in examples() {
+        let formatted = firstlang::format(&source).unwrap();
+        assert_eq!(
+            firstlang::format(&formatted).unwrap(),
+            formatted,
+            "formatting {} twice changes it",
+            name
+        );
+        // Comments survive formatting
+        assert_eq!(
+            formatted.matches('#').count(),
+            source.matches('#').count(),
+            "formatting {} loses comments",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_fmt_check_passes_on_examples() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_firstlang"))
+        .args(["fmt", "--check"])
+        .args(examples().iter().map(|(name, _)| root.join(name)))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn test_formatted_examples_run_the_same() {
+    let examples = examples();
+    let formatted: Vec<_> = examples
+        .iter()
+        .map(|(name, source)| (name.as_str(), firstlang::format(source).unwrap()))
+        .collect();
+    let files: Vec<_> = formatted
+        .iter()
+        .map(|(name, source)| (*name, source.as_str()))
+        .collect();
+    let dir = write_files("format", &files)
+        .parent()
+        .unwrap()
+        .to_path_buf();
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    for (name, _) in &examples {
+        if name.starts_with("modules") && !name.ends_with("main.fl") {
+            continue;
+        }
+        assert_eq!(
+            run_file(&dir.join(name)),
+            run_file(&root.join(name)),
+            "formatting changes what {} does",
+            name
+        );
+    }
+}
+
 // =============================================================================
 // Complex Programs
 // =============================================================================
@@ -574,6 +1647,23 @@ fn test_undefined_variable_error() {
     assert!(result.unwrap_err().contains("Undefined variable"));
 }
 
+#[test]
+fn test_undefined_variable_in_branch_never_taken() {
+    // Names are resolved before the program runs
+    let source = r#"
+        def f(n) {
+            if (n > 100) {
+                return typo
+            } else {
+                return n
+            }
+        }
+        f(1)
+    "#;
+    let result = run(source);
+    assert!(result.unwrap_err().contains("Undefined variable: typo"));
+}
+
 #[test]
 fn test_undefined_function_error() {
     let result = run("foo()");
@@ -0,0 +1,155 @@
+//! Ahead-of-time compilation for Secondlang
+//!
+//! Instead of running a program in the JIT, `build_program` writes it out as
+//! native code for the machine it runs on: an object file, an assembly
+//! listing, or an executable linked with the system C compiler. Executables
+//! get a C `main` that calls `__main` and prints its result, so running one
+//! prints what `secondlang file.sl` prints.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use inkwell::context::Context;
+use inkwell::module::Module;
+use inkwell::targets::{
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine,
+};
+use inkwell::OptimizationLevel;
+
+use crate::ast::Program;
+use crate::codegen::{CodeGen, LlvmOpts};
+
+/// What `build_program` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// Executable, linked with `cc`
+    Exe,
+    /// Object file
+    Obj,
+    /// Assembly
+    Asm,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exe" => Ok(Emit::Exe),
+            "obj" => Ok(Emit::Obj),
+            "asm" => Ok(Emit::Asm),
+            _ => Err(format!(
+                "Unknown output kind: {} (expected exe, obj or asm)",
+                s
+            )),
+        }
+    }
+}
+
+// ANCHOR: build
+/// Compile a type-checked program to native code and write it to `output`
+pub fn build_program(
+    program: &Program,
+    output: &Path,
+    emit: Emit,
+    opts: &LlvmOpts,
+) -> Result<(), String> {
+    let context = Context::create();
+    let mut codegen = CodeGen::new(&context, "secondlang");
+    codegen.compile(program)?;
+    if emit == Emit::Exe {
+        codegen.compile_c_main()?;
+    }
+
+    // The passes optimize for the target, so it comes first
+    let machine = target_machine(opts.level)?;
+    let module = codegen.get_module();
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    codegen.optimize(opts)?;
+
+    match emit {
+        Emit::Obj => write(&machine, module, FileType::Object, output),
+        Emit::Asm => write(&machine, module, FileType::Assembly, output),
+        Emit::Exe => {
+            let object = object_path(output);
+            write(&machine, module, FileType::Object, &object)?;
+            let linked = link(&object, output);
+            let _ = fs::remove_file(&object);
+            linked
+        }
+    }
+}
+// ANCHOR_END: build
+
+/// Target machine for the machine we are running on
+pub(crate) fn target_machine(level: OptimizationLevel) -> Result<TargetMachine, String> {
+    Target::initialize_native(&InitializationConfig::default())
+        .map_err(|e| format!("Failed to initialize native target: {}", e))?;
+
+    let triple = TargetMachine::get_default_triple();
+    let target = Target::from_triple(&triple)
+        .map_err(|e| format!("Failed to get target from triple: {}", e))?;
+
+    // Position-independent code, which `cc` links into PIE executables
+    target
+        .create_target_machine(
+            &triple,
+            "generic", // CPU
+            "",        // Features
+            level,
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| "Failed to create target machine".to_string())
+}
+
+fn write(
+    machine: &TargetMachine,
+    module: &Module,
+    file_type: FileType,
+    path: &Path,
+) -> Result<(), String> {
+    machine
+        .write_to_file(module, file_type, path)
+        .map_err(|e| format!("Cannot write '{}': {}", path.display(), e))
+}
+
+/// Temporary object file to link into `output`
+fn object_path(output: &Path) -> PathBuf {
+    let name = output
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    std::env::temp_dir().join(format!("secondlang-{}-{}.o", std::process::id(), name))
+}
+
+/// Link an object file into an executable with the C compiler in `$CC`, or `cc`
+fn link(object: &Path, output: &Path) -> Result<(), String> {
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&cc)
+        .arg(object)
+        .arg("-o")
+        .arg(output)
+        .status()
+        .map_err(|e| format!("Cannot run linker '{}': {}", cc, e))?;
+    if !status.success() {
+        return Err(format!("Linker '{}' failed: {}", cc, status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_emit() {
+        assert_eq!("exe".parse(), Ok(Emit::Exe));
+        assert_eq!("obj".parse(), Ok(Emit::Obj));
+        assert_eq!("asm".parse(), Ok(Emit::Asm));
+        assert!("wasm".parse::<Emit>().is_err());
+    }
+}
@@ -19,6 +19,12 @@ pub enum Stmt {
         return_type: Type,
         body: Vec<Stmt>,
     },
+    /// Declaration of a function defined outside the program, in C
+    Extern {
+        name: String,
+        params: Vec<(String, Type)>,
+        return_type: Type,
+    },
     /// Return statement
     Return(TypedExpr),
     /// Assignment with optional type annotation
@@ -27,6 +33,8 @@ pub enum Stmt {
         type_ann: Option<Type>,
         value: TypedExpr,
     },
+    /// Assignment to an array element: `target` is an `Expr::Index`
+    IndexAssign { target: TypedExpr, value: TypedExpr },
     /// Expression statement
     Expr(TypedExpr),
 }
@@ -45,10 +53,12 @@ impl TypedExpr {
         TypedExpr { expr, ty }
     }
 
+    /// An expression not type checked yet, whose type is a placeholder
+    /// variable until the type checker infers it
     pub fn unknown(expr: Expr) -> Self {
         TypedExpr {
             expr,
-            ty: Type::Unknown,
+            ty: Type::Var(0),
         }
     }
 }
@@ -60,6 +70,8 @@ impl TypedExpr {
 pub enum Expr {
     /// Integer literal
     Int(i64),
+    /// Floating-point literal
+    Float(f64),
     /// Boolean literal
     Bool(bool),
     /// Variable reference
@@ -72,6 +84,21 @@ pub enum Expr {
         left: Box<TypedExpr>,
         right: Box<TypedExpr>,
     },
+    /// Conversion between numeric types: `int(x)` or `float(x)`
+    Cast { to: Type, expr: Box<TypedExpr> },
+    /// Array literal: `[1, 2, 3]`
+    Array(Vec<TypedExpr>),
+    /// Array of `size` copies of a value: `[0; 10]`
+    ArrayRepeat { value: Box<TypedExpr>, size: usize },
+    /// Array element: `a[i]`
+    ///
+    /// Codegen checks the index is in bounds while `checked` is set. The
+    /// constant folder clears it for constant indices within the array.
+    Index {
+        array: Box<TypedExpr>,
+        index: Box<TypedExpr>,
+        checked: bool,
+    },
     /// Function call
     Call { name: String, args: Vec<TypedExpr> },
     /// Conditional
@@ -136,6 +163,23 @@ impl fmt::Display for Stmt {
                     return_type
                 )
             }
+            Stmt::Extern {
+                name,
+                params,
+                return_type,
+            } => {
+                let params_str: Vec<_> = params
+                    .iter()
+                    .map(|(n, t)| format!("{}: {}", n, t))
+                    .collect();
+                write!(
+                    f,
+                    "extern def {}({}) -> {}",
+                    name,
+                    params_str.join(", "),
+                    return_type
+                )
+            }
             Stmt::Return(expr) => write!(f, "return {}", expr),
             Stmt::Assignment {
                 name,
@@ -148,6 +192,7 @@ impl fmt::Display for Stmt {
                     write!(f, "{} = {}", name, value)
                 }
             }
+            Stmt::IndexAssign { target, value } => write!(f, "{} = {}", target.expr, value),
             Stmt::Expr(expr) => write!(f, "{}", expr),
         }
     }
@@ -163,12 +208,20 @@ impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Expr::Int(n) => write!(f, "{}", n),
+            Expr::Float(x) => write!(f, "{:?}", x),
             Expr::Bool(b) => write!(f, "{}", b),
             Expr::Var(name) => write!(f, "{}", name),
             Expr::Unary { op, expr } => write!(f, "({}{})", op, expr.expr),
             Expr::Binary { op, left, right } => {
                 write!(f, "({} {} {})", left.expr, op, right.expr)
             }
+            Expr::Cast { to, expr } => write!(f, "{}({})", to, expr.expr),
+            Expr::Array(elements) => {
+                let elements_str: Vec<_> = elements.iter().map(|e| e.expr.to_string()).collect();
+                write!(f, "[{}]", elements_str.join(", "))
+            }
+            Expr::ArrayRepeat { value, size } => write!(f, "[{}; {}]", value.expr, size),
+            Expr::Index { array, index, .. } => write!(f, "{}[{}]", array.expr, index.expr),
             Expr::Call { name, args } => {
                 let args_str: Vec<_> = args.iter().map(|a| a.expr.to_string()).collect();
                 write!(f, "{}({})", name, args_str.join(", "))
@@ -4,24 +4,66 @@
 
 use std::collections::HashMap;
 
+use inkwell::attributes::{Attribute, AttributeLoc};
 use inkwell::builder::Builder;
 use inkwell::context::Context;
-use inkwell::module::Module;
-use inkwell::types::BasicMetadataTypeEnum;
-use inkwell::values::{BasicMetadataValueEnum, FunctionValue, IntValue, PointerValue};
-use inkwell::{IntPredicate, OptimizationLevel};
-
+use inkwell::module::{Linkage, Module};
+use inkwell::passes::PassBuilderOptions;
+use inkwell::types::{ArrayType, BasicMetadataTypeEnum, BasicType, BasicTypeEnum};
+use inkwell::values::{
+    BasicMetadataValueEnum, BasicValueEnum, FloatValue, FunctionValue, IntValue, PointerValue,
+};
+use inkwell::{AddressSpace, FloatPredicate, IntPredicate, OptimizationLevel};
+
+use crate::aot::target_machine;
 use crate::ast::{BinaryOp, Expr, Program, Stmt, TypedExpr, UnaryOp};
+use crate::host::HostFunctions;
+use crate::jit::CompiledModule;
+use crate::typeck::{LEN, PRINT};
 use crate::types::Type;
 
+/// Name of the function wrapping the top-level statements
+pub const MAIN_FUNCTION: &str = "__main";
+
+// ANCHOR: llvm_opts
+/// LLVM optimizations applied to the generated module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LlvmOpts {
+    /// Pass pipeline for `CodeGen::run_passes`, e.g. "mem2reg,instcombine"
+    pub passes: Option<String>,
+    /// Optimization level of the JIT and of native code generation
+    pub level: OptimizationLevel,
+}
+
+impl Default for LlvmOpts {
+    fn default() -> Self {
+        LlvmOpts {
+            passes: None,
+            level: OptimizationLevel::Default,
+        }
+    }
+}
+
+impl LlvmOpts {
+    /// The settings of `-O0` to `-O3`: LLVM's standard pipeline for that
+    /// level, and code generation at that level
+    pub fn level(level: OptimizationLevel) -> Self {
+        LlvmOpts {
+            passes: Some(format!("default<O{}>", level as u32)),
+            level,
+        }
+    }
+}
+// ANCHOR_END: llvm_opts
+
 // ANCHOR: codegen_struct
 /// Code generator state
 pub struct CodeGen<'ctx> {
     context: &'ctx Context,
     module: Module<'ctx>,
     builder: Builder<'ctx>,
-    /// Map from variable names to their stack allocations
-    variables: HashMap<String, PointerValue<'ctx>>,
+    /// Map from variable names to their stack allocations and LLVM types
+    variables: HashMap<String, (PointerValue<'ctx>, BasicTypeEnum<'ctx>)>,
     /// Map from function names to LLVM functions
     functions: HashMap<String, FunctionValue<'ctx>>,
     /// Current function being compiled
@@ -47,16 +89,27 @@ impl<'ctx> CodeGen<'ctx> {
     // ANCHOR: compile
     /// Compile a program and return the module
     pub fn compile(&mut self, program: &Program) -> Result<(), String> {
+        self.declare_libc_functions();
+
         // First pass: declare all functions
         for stmt in program {
-            if let Stmt::Function {
-                name,
-                params,
-                return_type,
-                ..
-            } = stmt
-            {
-                self.declare_function(name, params, return_type)?;
+            match stmt {
+                Stmt::Function {
+                    name,
+                    params,
+                    return_type,
+                    ..
+                } => {
+                    self.declare_function(name, params, return_type, false)?;
+                }
+                Stmt::Extern {
+                    name,
+                    params,
+                    return_type,
+                } => {
+                    self.declare_function(name, params, return_type, true)?;
+                }
+                _ => {}
             }
         }
 
@@ -67,10 +120,8 @@ impl<'ctx> CodeGen<'ctx> {
             }
         }
 
-        // Third pass: create __main wrapper for top-level expression
-        if let Some(Stmt::Expr(expr)) = program.last() {
-            self.compile_main_wrapper(expr)?;
-        }
+        // Third pass: create __main wrapper for all top-level statements
+        self.compile_main_wrapper_all(program)?;
 
         // Verify module
         self.module
@@ -81,40 +132,190 @@ impl<'ctx> CodeGen<'ctx> {
     }
     // ANCHOR_END: compile
 
-    /// Create a __main wrapper function for top-level expression
-    fn compile_main_wrapper(&mut self, expr: &TypedExpr) -> Result<(), String> {
+    /// Declare the C library functions the generated code calls
+    fn declare_libc_functions(&mut self) {
+        let i32_type = self.context.i32_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+
+        // int printf(const char *format, ...)
+        let printf_type = i32_type.fn_type(&[ptr_type.into()], true);
+        self.module
+            .add_function("printf", printf_type, Some(Linkage::External));
+
+        // int puts(const char *s)
+        let puts_type = i32_type.fn_type(&[ptr_type.into()], false);
+        self.module
+            .add_function("puts", puts_type, Some(Linkage::External));
+
+        // int fflush(FILE *stream)
+        let fflush_type = i32_type.fn_type(&[ptr_type.into()], false);
+        self.module
+            .add_function("fflush", fflush_type, Some(Linkage::External));
+
+        // int dprintf(int fd, const char *format, ...)
+        let dprintf_type = i32_type.fn_type(&[i32_type.into(), ptr_type.into()], true);
+        self.module
+            .add_function("dprintf", dprintf_type, Some(Linkage::External));
+
+        // void abort(void)
+        let abort_type = self.context.void_type().fn_type(&[], false);
+        self.module
+            .add_function("abort", abort_type, Some(Linkage::External));
+    }
+
+    /// Create a __main wrapper function running every top-level statement
+    ///
+    /// Statements run in program order, so variables assigned at the top
+    /// level are visible to the statements after them. `__main` returns the
+    /// value of the last statement. A program made only of functions has no
+    /// `__main`.
+    fn compile_main_wrapper_all(&mut self, program: &Program) -> Result<(), String> {
+        // Collect all non-function top-level statements
+        let stmts: Vec<&Stmt> = program
+            .iter()
+            .filter(|stmt| !matches!(stmt, Stmt::Function { .. } | Stmt::Extern { .. }))
+            .collect();
+
+        if stmts.is_empty() {
+            return Ok(());
+        }
+
         // Create __main function: fn() -> i64
         let ret_type = self.context.i64_type();
         let fn_type = ret_type.fn_type(&[], false);
-        let function = self.module.add_function("__main", fn_type, None);
+        let function = self.module.add_function(MAIN_FUNCTION, fn_type, None);
 
-        // Create entry block
         let entry = self.context.append_basic_block(function, "entry");
         self.builder.position_at_end(entry);
         self.current_fn = Some(function);
+        self.variables.clear();
 
-        // Compile the expression and return its value
-        let value = self.compile_expr(expr)?;
-        self.builder.build_return(Some(&value)).unwrap();
+        // Compile all statements
+        let mut last_value: Option<BasicValueEnum> = None;
+        for stmt in stmts {
+            last_value = self.compile_stmt(stmt)?;
+        }
+
+        // Return the last value (or 0 if no value), unless a top-level
+        // `return` already did
+        if self
+            .builder
+            .get_insert_block()
+            .unwrap()
+            .get_terminator()
+            .is_none()
+        {
+            let ret_val = match last_value {
+                Some(BasicValueEnum::FloatValue(_)) => {
+                    return Err(
+                        "The result of a program must be an int or bool, not a float: \
+                         convert it with int(...)"
+                            .to_string(),
+                    )
+                }
+                Some(BasicValueEnum::ArrayValue(_)) => {
+                    return Err(
+                        "The result of a program must be an int or bool, not an array".to_string(),
+                    )
+                }
+                Some(value) => value,
+                None => ret_type.const_int(0, false).into(),
+            };
+            self.build_return(ret_val);
+        }
 
         Ok(())
     }
 
+    /// The `__main` wrapper of the top-level statements
+    pub fn main_function(&self) -> Result<FunctionValue<'ctx>, String> {
+        self.module
+            .get_function(MAIN_FUNCTION)
+            .ok_or_else(|| "Nothing to run: the program has no top-level statements".to_string())
+    }
+
+    /// Add a C `main` that calls `__main` and prints its result
+    ///
+    /// This is the entry point of executables built ahead of time.
+    pub fn compile_c_main(&mut self) -> Result<(), String> {
+        let main = self.main_function()?;
+        if self.module.get_function("main").is_some() {
+            return Err("Cannot build an executable from a program defining main".to_string());
+        }
+
+        let i32_type = self.context.i32_type();
+        let printf = self.module.get_function("printf").unwrap();
+
+        // int main()
+        let function = self
+            .module
+            .add_function("main", i32_type.fn_type(&[], false), None);
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+
+        let call = self.builder.build_call(main, &[], "result").unwrap();
+        let result = call.try_as_basic_value().unwrap_basic().into_int_value();
+        let format = self
+            .builder
+            .build_global_string_ptr("%lld\n", "format")
+            .unwrap();
+        self.builder
+            .build_call(
+                printf,
+                &[format.as_pointer_value().into(), result.into()],
+                "printf",
+            )
+            .unwrap();
+        self.builder
+            .build_return(Some(&i32_type.const_int(0, false)))
+            .unwrap();
+
+        self.module
+            .verify()
+            .map_err(|e| format!("Module verification failed: {}", e.to_string()))
+    }
+
     /// Declare a function (without body)
+    ///
+    /// An `external` function is defined outside the module, in C: the JIT
+    /// finds it among the symbols of the process, and the linker in the C
+    /// library or in the objects linked with the program. Such a function
+    /// returning unit returns `void`.
     fn declare_function(
         &mut self,
         name: &str,
         params: &[(String, Type)],
         return_type: &Type,
+        external: bool,
     ) -> Result<FunctionValue<'ctx>, String> {
-        let ret_type = self.llvm_type(return_type)?;
-        let param_types: Vec<BasicMetadataTypeEnum> = params
+        let param_types = params
             .iter()
-            .map(|(_, t)| self.llvm_type(t).unwrap().into())
-            .collect();
+            .map(|(_, t)| Ok(self.llvm_type(t)?.into()))
+            .collect::<Result<Vec<BasicMetadataTypeEnum>, String>>()?;
+
+        let fn_type = if external && *return_type == Type::Unit {
+            self.context.void_type().fn_type(&param_types, false)
+        } else {
+            self.llvm_type(return_type)?.fn_type(&param_types, false)
+        };
+
+        if external {
+            // The C library functions the compiler calls are declared
+            // already
+            if let Some(function) = self.module.get_function(name) {
+                if function.get_type() != fn_type {
+                    return Err(format!(
+                        "extern def {} does not match the C declaration of {}",
+                        name, name
+                    ));
+                }
+                self.functions.insert(name.to_string(), function);
+                return Ok(function);
+            }
+        }
 
-        let fn_type = ret_type.fn_type(&param_types, false);
-        let function = self.module.add_function(name, fn_type, None);
+        let linkage = external.then_some(Linkage::External);
+        let function = self.module.add_function(name, fn_type, linkage);
 
         // Set parameter names
         for (i, (param_name, _)) in params.iter().enumerate() {
@@ -124,23 +325,98 @@ impl<'ctx> CodeGen<'ctx> {
                 .set_name(param_name);
         }
 
+        // Bools are `i1`. The C ABI passes and returns them zero-extended,
+        // which is what Rust's `bool` and C's `_Bool` rely on, so say so
+        let zeroext = self
+            .context
+            .create_enum_attribute(Attribute::get_named_enum_kind_id("zeroext"), 0);
+        for (i, (_, param_type)) in params.iter().enumerate() {
+            if *param_type == Type::Bool {
+                function.add_attribute(AttributeLoc::Param(i as u32), zeroext);
+            }
+        }
+        if *return_type == Type::Bool {
+            function.add_attribute(AttributeLoc::Return, zeroext);
+        }
+
         self.functions.insert(name.to_string(), function);
         Ok(function)
     }
 
     /// Get LLVM type for our type
-    fn llvm_type(&self, ty: &Type) -> Result<inkwell::types::IntType<'ctx>, String> {
+    fn llvm_type(&self, ty: &Type) -> Result<BasicTypeEnum<'ctx>, String> {
         match ty {
-            Type::Int => Ok(self.context.i64_type()),
-            Type::Bool => Ok(self.context.bool_type()),
-            Type::Unit => Ok(self.context.i64_type()), // Use i64 for unit
-            Type::Unknown => Ok(self.context.i64_type()), // Default to i64
+            Type::Int => Ok(self.context.i64_type().into()),
+            Type::Float => Ok(self.context.f64_type().into()),
+            Type::Bool => Ok(self.context.bool_type().into()),
+            Type::Unit => Ok(self.context.i64_type().into()), // Use i64 for unit
+            Type::Array { element, size } => {
+                let size =
+                    u32::try_from(*size).map_err(|_| format!("Array type {} is too large", ty))?;
+                Ok(self.llvm_type(element)?.array_type(size).into())
+            }
+            Type::Var(_) => Err(format!("Cannot get LLVM type for unresolved type {}", ty)),
             Type::Function { .. } => Err("Cannot get LLVM type for function type".to_string()),
         }
     }
 
+    /// Fit an integer value to an integer type of another width
+    ///
+    /// Bools are `i1` in variables, parameters and return values, while
+    /// comparisons produce an `i64`.
+    fn convert(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        ty: BasicTypeEnum<'ctx>,
+    ) -> BasicValueEnum<'ctx> {
+        match (value, ty) {
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::IntType(t))
+                if v.get_type().get_bit_width() < t.get_bit_width() =>
+            {
+                self.builder.build_int_z_extend(v, t, "ext").unwrap().into()
+            }
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::IntType(t))
+                if v.get_type().get_bit_width() > t.get_bit_width() =>
+            {
+                self.builder
+                    .build_int_truncate(v, t, "trunc")
+                    .unwrap()
+                    .into()
+            }
+            _ => value,
+        }
+    }
+
+    /// Zero of an LLVM type: the value of empty blocks and loops
+    fn zero(&self, ty: BasicTypeEnum<'ctx>) -> BasicValueEnum<'ctx> {
+        match ty {
+            BasicTypeEnum::FloatType(t) => t.const_float(0.0).into(),
+            BasicTypeEnum::IntType(t) => t.const_int(0, false).into(),
+            BasicTypeEnum::ArrayType(t) => t.const_zero().into(),
+            _ => self.context.i64_type().const_int(0, false).into(),
+        }
+    }
+
+    /// Return `value` from the current function, fitted to its return type
+    fn build_return(&self, value: BasicValueEnum<'ctx>) {
+        let function = self.current_fn.unwrap();
+        let value = match function.get_type().get_return_type() {
+            Some(ty) => self.convert(value, ty),
+            None => value,
+        };
+        self.builder.build_return(Some(&value)).unwrap();
+    }
+
+    /// Compile a condition to an `i1` for a branch
+    fn compile_cond(&mut self, cond: &TypedExpr) -> Result<IntValue<'ctx>, String> {
+        let value = self.compile_expr(cond)?;
+        Ok(self
+            .convert(value, self.context.bool_type().into())
+            .into_int_value())
+    }
+
     /// Compile a statement
-    fn compile_stmt(&mut self, stmt: &Stmt) -> Result<Option<IntValue<'ctx>>, String> {
+    fn compile_stmt(&mut self, stmt: &Stmt) -> Result<Option<BasicValueEnum<'ctx>>, String> {
         match stmt {
             Stmt::Function {
                 name, params, body, ..
@@ -161,11 +437,13 @@ impl<'ctx> CodeGen<'ctx> {
 
                 // Allocate parameters
                 for (i, (param_name, param_type)) in params.iter().enumerate() {
-                    let param_value = function.get_nth_param(i as u32).unwrap().into_int_value();
+                    let param_value = function.get_nth_param(i as u32).unwrap();
+                    let llvm_type = self.llvm_type(param_type)?;
                     let alloca =
-                        self.create_entry_block_alloca(&function, param_name, param_type)?;
+                        self.create_entry_block_alloca(&function, param_name, llvm_type)?;
                     self.builder.build_store(alloca, param_value).unwrap();
-                    self.variables.insert(param_name.clone(), alloca);
+                    self.variables
+                        .insert(param_name.clone(), (alloca, llvm_type));
                 }
 
                 // Compile body
@@ -182,12 +460,19 @@ impl<'ctx> CodeGen<'ctx> {
                     .get_terminator()
                     .is_none()
                 {
-                    if let Some(val) = last_value {
-                        self.builder.build_return(Some(&val)).unwrap();
-                    } else {
-                        let zero = self.context.i64_type().const_int(0, false);
-                        self.builder.build_return(Some(&zero)).unwrap();
-                    }
+                    // Falling off the end after a loop, or of an empty body,
+                    // returns a zero of the return type
+                    let return_type = function.get_type().get_return_type().unwrap();
+                    let value = match last_value {
+                        Some(val)
+                            if val.get_type() == return_type
+                                || (val.is_int_value() && return_type.is_int_type()) =>
+                        {
+                            val
+                        }
+                        _ => self.zero(return_type),
+                    };
+                    self.build_return(value);
                 }
 
                 Ok(None)
@@ -195,7 +480,7 @@ impl<'ctx> CodeGen<'ctx> {
 
             Stmt::Return(expr) => {
                 let value = self.compile_expr(expr)?;
-                self.builder.build_return(Some(&value)).unwrap();
+                self.build_return(value);
                 Ok(Some(value))
             }
 
@@ -203,51 +488,74 @@ impl<'ctx> CodeGen<'ctx> {
                 let val = self.compile_expr(value)?;
 
                 // Check if variable exists
-                if let Some(ptr) = self.variables.get(name) {
-                    self.builder.build_store(*ptr, val).unwrap();
+                if let Some((ptr, ty)) = self.variables.get(name).cloned() {
+                    let val = self.convert(val, ty);
+                    self.builder.build_store(ptr, val).unwrap();
                 } else {
-                    // Create new variable
+                    // Create new variable, sized like the compiled value:
+                    // comparisons produce an i64 even though they are typed bool
                     let function = self.current_fn.unwrap();
-                    let alloca = self.create_entry_block_alloca(&function, name, &value.ty)?;
+                    let ty = val.get_type();
+                    let alloca = self.create_entry_block_alloca(&function, name, ty)?;
                     self.builder.build_store(alloca, val).unwrap();
-                    self.variables.insert(name.clone(), alloca);
+                    self.variables.insert(name.clone(), (alloca, ty));
                 }
 
                 Ok(Some(val))
             }
 
+            Stmt::IndexAssign { target, value } => {
+                let (ptr, ty) = self.element_pointer(target)?;
+                let val = self.compile_expr(value)?;
+                let stored = self.convert(val, ty);
+                self.builder.build_store(ptr, stored).unwrap();
+                Ok(Some(val))
+            }
+
             Stmt::Expr(expr) => {
                 let val = self.compile_expr(expr)?;
                 Ok(Some(val))
             }
+
+            // Declared in the first pass, with no body to compile
+            Stmt::Extern { .. } => Ok(None),
         }
     }
 
     // ANCHOR: compile_expr
     /// Compile an expression
-    fn compile_expr(&mut self, expr: &TypedExpr) -> Result<IntValue<'ctx>, String> {
+    fn compile_expr(&mut self, expr: &TypedExpr) -> Result<BasicValueEnum<'ctx>, String> {
         match &expr.expr {
-            Expr::Int(n) => Ok(self.context.i64_type().const_int(*n as u64, false)),
+            Expr::Int(n) => Ok(self.context.i64_type().const_int(*n as u64, false).into()),
 
-            Expr::Bool(b) => Ok(self.context.bool_type().const_int(*b as u64, false)),
+            Expr::Float(x) => Ok(self.context.f64_type().const_float(*x).into()),
+
+            Expr::Bool(b) => Ok(self.context.bool_type().const_int(*b as u64, false).into()),
 
             Expr::Var(name) => {
-                let ptr = self
+                let (ptr, ty) = *self
                     .variables
                     .get(name)
                     .ok_or_else(|| format!("Undefined variable: {}", name))?;
-                let val = self
-                    .builder
-                    .build_load(self.context.i64_type(), *ptr, name)
-                    .unwrap();
-                Ok(val.into_int_value())
+                Ok(self.builder.build_load(ty, ptr, name).unwrap())
             }
 
             Expr::Unary { op, expr: inner } => {
                 let val = self.compile_expr(inner)?;
-                match op {
-                    UnaryOp::Neg => Ok(self.builder.build_int_neg(val, "neg").unwrap()),
-                    UnaryOp::Not => Ok(self.builder.build_not(val, "not").unwrap()),
+                match (op, val) {
+                    (UnaryOp::Neg, BasicValueEnum::FloatValue(x)) => {
+                        Ok(self.builder.build_float_neg(x, "neg").unwrap().into())
+                    }
+                    (UnaryOp::Neg, _) => Ok(self
+                        .builder
+                        .build_int_neg(val.into_int_value(), "neg")
+                        .unwrap()
+                        .into()),
+                    (UnaryOp::Not, _) => Ok(self
+                        .builder
+                        .build_not(val.into_int_value(), "not")
+                        .unwrap()
+                        .into()),
                 }
             }
 
@@ -255,75 +563,88 @@ impl<'ctx> CodeGen<'ctx> {
                 let l = self.compile_expr(left)?;
                 let r = self.compile_expr(right)?;
 
-                match op {
-                    BinaryOp::Add => Ok(self.builder.build_int_add(l, r, "add").unwrap()),
-                    BinaryOp::Sub => Ok(self.builder.build_int_sub(l, r, "sub").unwrap()),
-                    BinaryOp::Mul => Ok(self.builder.build_int_mul(l, r, "mul").unwrap()),
-                    BinaryOp::Div => Ok(self.builder.build_int_signed_div(l, r, "div").unwrap()),
-                    BinaryOp::Mod => Ok(self.builder.build_int_signed_rem(l, r, "mod").unwrap()),
-                    BinaryOp::Lt => {
-                        let cmp = self
-                            .builder
-                            .build_int_compare(IntPredicate::SLT, l, r, "lt")
-                            .unwrap();
-                        Ok(self
-                            .builder
-                            .build_int_z_extend(cmp, self.context.i64_type(), "ext")
-                            .unwrap())
-                    }
-                    BinaryOp::Gt => {
-                        let cmp = self
-                            .builder
-                            .build_int_compare(IntPredicate::SGT, l, r, "gt")
-                            .unwrap();
-                        Ok(self
-                            .builder
-                            .build_int_z_extend(cmp, self.context.i64_type(), "ext")
-                            .unwrap())
-                    }
-                    BinaryOp::Le => {
-                        let cmp = self
-                            .builder
-                            .build_int_compare(IntPredicate::SLE, l, r, "le")
-                            .unwrap();
-                        Ok(self
-                            .builder
-                            .build_int_z_extend(cmp, self.context.i64_type(), "ext")
-                            .unwrap())
-                    }
-                    BinaryOp::Ge => {
-                        let cmp = self
-                            .builder
-                            .build_int_compare(IntPredicate::SGE, l, r, "ge")
-                            .unwrap();
-                        Ok(self
-                            .builder
-                            .build_int_z_extend(cmp, self.context.i64_type(), "ext")
-                            .unwrap())
-                    }
-                    BinaryOp::Eq => {
-                        let cmp = self
-                            .builder
-                            .build_int_compare(IntPredicate::EQ, l, r, "eq")
-                            .unwrap();
-                        Ok(self
-                            .builder
-                            .build_int_z_extend(cmp, self.context.i64_type(), "ext")
-                            .unwrap())
-                    }
-                    BinaryOp::Ne => {
-                        let cmp = self
-                            .builder
-                            .build_int_compare(IntPredicate::NE, l, r, "ne")
-                            .unwrap();
-                        Ok(self
-                            .builder
-                            .build_int_z_extend(cmp, self.context.i64_type(), "ext")
-                            .unwrap())
-                    }
+                if left.ty == Type::Float {
+                    let (l, r) = (l.into_float_value(), r.into_float_value());
+                    return Ok(self.compile_float_binary(*op, l, r));
                 }
+
+                // Both operands have the same type, but a bool may be an
+                // i1 or an i64
+                let l = l.into_int_value();
+                let r = self.convert(r, l.get_type().into()).into_int_value();
+                Ok(self.compile_int_binary(*op, l, r).into())
             }
 
+            Expr::Cast { to, expr: inner } => {
+                let val = self.compile_expr(inner)?;
+                match (&inner.ty, to) {
+                    (Type::Int, Type::Float) => Ok(self
+                        .builder
+                        .build_signed_int_to_float(
+                            val.into_int_value(),
+                            self.context.f64_type(),
+                            "tofloat",
+                        )
+                        .unwrap()
+                        .into()),
+                    (Type::Float, Type::Int) => Ok(self
+                        .builder
+                        .build_float_to_signed_int(
+                            val.into_float_value(),
+                            self.context.i64_type(),
+                            "toint",
+                        )
+                        .unwrap()
+                        .into()),
+                    _ => Ok(val),
+                }
+            }
+
+            Expr::Array(elements) => {
+                let array_type = self.llvm_type(&expr.ty)?.into_array_type();
+                let element_type = array_type.get_element_type();
+
+                let mut array = array_type.get_undef();
+                for (i, element) in elements.iter().enumerate() {
+                    let value = self.compile_expr(element)?;
+                    let value = self.convert(value, element_type);
+                    array = self
+                        .builder
+                        .build_insert_value(array, value, i as u32, "array")
+                        .unwrap()
+                        .into_array_value();
+                }
+                Ok(array.into())
+            }
+
+            Expr::ArrayRepeat { value, .. } => {
+                let array_type = self.llvm_type(&expr.ty)?.into_array_type();
+                let value = self.compile_expr(value)?;
+                let value = self.convert(value, array_type.get_element_type());
+
+                let function = self.current_fn.unwrap();
+                let ptr = self.create_entry_block_alloca(&function, "array", array_type.into())?;
+                self.build_fill(ptr, array_type, value);
+                Ok(self.builder.build_load(array_type, ptr, "array").unwrap())
+            }
+
+            Expr::Index { .. } => {
+                let (ptr, ty) = self.element_pointer(expr)?;
+                Ok(self.builder.build_load(ty, ptr, "elem").unwrap())
+            }
+
+            Expr::Call { name, args } if name == LEN => {
+                // The length is part of the type, but the argument may have
+                // side effects
+                self.compile_expr(&args[0])?;
+                let Type::Array { size, .. } = args[0].ty else {
+                    return Err(format!("{} of a non-array", LEN));
+                };
+                Ok(self.context.i64_type().const_int(size as u64, false).into())
+            }
+
+            Expr::Call { name, args } if name == PRINT => self.compile_print(&args[0]),
+
             Expr::Call { name, args } => {
                 let function = self
                     .functions
@@ -331,16 +652,22 @@ impl<'ctx> CodeGen<'ctx> {
                     .cloned()
                     .ok_or_else(|| format!("Undefined function: {}", name))?;
 
-                let arg_values: Vec<BasicMetadataValueEnum> = args
-                    .iter()
-                    .map(|a| self.compile_expr(a).map(|v| v.into()))
-                    .collect::<Result<_, _>>()?;
+                let mut arg_values: Vec<BasicMetadataValueEnum> = Vec::new();
+                for (i, arg) in args.iter().enumerate() {
+                    let value = self.compile_expr(arg)?;
+                    let param_type = function.get_nth_param(i as u32).unwrap().get_type();
+                    arg_values.push(self.convert(value, param_type).into());
+                }
 
                 let call = self
                     .builder
                     .build_call(function, &arg_values, "call")
                     .unwrap();
-                Ok(call.try_as_basic_value().unwrap_basic().into_int_value())
+                // A void extern function returns unit
+                match function.get_type().get_return_type() {
+                    Some(_) => Ok(call.try_as_basic_value().unwrap_basic()),
+                    None => Ok(self.context.i64_type().const_zero().into()),
+                }
             }
 
             Expr::If {
@@ -348,12 +675,9 @@ impl<'ctx> CodeGen<'ctx> {
                 then_branch,
                 else_branch,
             } => {
-                let cond_val = self.compile_expr(cond)?;
                 // Convert to i1 for branch
-                let cond_bool = self
-                    .builder
-                    .build_int_truncate(cond_val, self.context.bool_type(), "cond")
-                    .unwrap();
+                let cond_bool = self.compile_cond(cond)?;
+                let phi_type = self.llvm_type(&expr.ty)?;
 
                 let function = self.current_fn.unwrap();
                 let then_bb = self.context.append_basic_block(function, "then");
@@ -364,31 +688,38 @@ impl<'ctx> CodeGen<'ctx> {
                     .build_conditional_branch(cond_bool, then_bb, else_bb)
                     .unwrap();
 
-                // Then branch
+                // Then branch. Like the type checker, every branch has its
+                // own scope: variables assigned first in it are not visible
+                // after it, and may have another type in the other branch
+                let outer_variables = self.variables.clone();
                 self.builder.position_at_end(then_bb);
-                let mut then_val = self.context.i64_type().const_int(0, false);
+                let mut then_val = self.zero(phi_type);
                 for stmt in then_branch {
                     if let Some(v) = self.compile_stmt(stmt)? {
                         then_val = v;
                     }
                 }
+                self.variables = outer_variables.clone();
                 let then_end = self.builder.get_insert_block().unwrap();
                 let then_has_terminator = then_end.get_terminator().is_some();
                 if !then_has_terminator {
+                    then_val = self.convert(then_val, phi_type);
                     self.builder.build_unconditional_branch(merge_bb).unwrap();
                 }
 
                 // Else branch
                 self.builder.position_at_end(else_bb);
-                let mut else_val = self.context.i64_type().const_int(0, false);
+                let mut else_val = self.zero(phi_type);
                 for stmt in else_branch {
                     if let Some(v) = self.compile_stmt(stmt)? {
                         else_val = v;
                     }
                 }
+                self.variables = outer_variables;
                 let else_end = self.builder.get_insert_block().unwrap();
                 let else_has_terminator = else_end.get_terminator().is_some();
                 if !else_has_terminator {
+                    else_val = self.convert(else_val, phi_type);
                     self.builder.build_unconditional_branch(merge_bb).unwrap();
                 }
 
@@ -400,13 +731,10 @@ impl<'ctx> CodeGen<'ctx> {
                         merge_bb.delete().unwrap();
                     }
                     // Return a dummy value - the actual return happened in the branches
-                    Ok(self.context.i64_type().const_int(0, false))
+                    Ok(self.zero(phi_type))
                 } else {
                     self.builder.position_at_end(merge_bb);
-                    let phi = self
-                        .builder
-                        .build_phi(self.context.i64_type(), "phi")
-                        .unwrap();
+                    let phi = self.builder.build_phi(phi_type, "phi").unwrap();
 
                     // Only add incoming from branches that don't have terminators
                     if !then_has_terminator {
@@ -416,7 +744,7 @@ impl<'ctx> CodeGen<'ctx> {
                         phi.add_incoming(&[(&else_val, else_end)]);
                     }
 
-                    Ok(phi.as_basic_value().into_int_value())
+                    Ok(phi.as_basic_value())
                 }
             }
 
@@ -430,20 +758,18 @@ impl<'ctx> CodeGen<'ctx> {
 
                 // Condition
                 self.builder.position_at_end(cond_bb);
-                let cond_val = self.compile_expr(cond)?;
-                let cond_bool = self
-                    .builder
-                    .build_int_truncate(cond_val, self.context.bool_type(), "cond")
-                    .unwrap();
+                let cond_bool = self.compile_cond(cond)?;
                 self.builder
                     .build_conditional_branch(cond_bool, body_bb, end_bb)
                     .unwrap();
 
-                // Body
+                // Body, in its own scope
+                let outer_variables = self.variables.clone();
                 self.builder.position_at_end(body_bb);
                 for stmt in body {
                     self.compile_stmt(stmt)?;
                 }
+                self.variables = outer_variables;
                 if self
                     .builder
                     .get_insert_block()
@@ -456,28 +782,309 @@ impl<'ctx> CodeGen<'ctx> {
 
                 // End
                 self.builder.position_at_end(end_bb);
-                Ok(self.context.i64_type().const_int(0, false))
+                Ok(self.context.i64_type().const_int(0, false).into())
             }
 
             Expr::Block(stmts) => {
-                let mut last_val = self.context.i64_type().const_int(0, false);
+                let outer_variables = self.variables.clone();
+                let mut last_val = self.context.i64_type().const_int(0, false).into();
                 for stmt in stmts {
                     if let Some(v) = self.compile_stmt(stmt)? {
                         last_val = v;
                     }
                 }
+                self.variables = outer_variables;
                 Ok(last_val)
             }
         }
     }
     // ANCHOR_END: compile_expr
 
+    /// Compile an arithmetic or comparison operation on integers
+    fn compile_int_binary(
+        &self,
+        op: BinaryOp,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+    ) -> IntValue<'ctx> {
+        let compare = |predicate, name| {
+            let cmp = self
+                .builder
+                .build_int_compare(predicate, l, r, name)
+                .unwrap();
+            self.builder
+                .build_int_z_extend(cmp, self.context.i64_type(), "ext")
+                .unwrap()
+        };
+
+        match op {
+            BinaryOp::Add => self.builder.build_int_add(l, r, "add").unwrap(),
+            BinaryOp::Sub => self.builder.build_int_sub(l, r, "sub").unwrap(),
+            BinaryOp::Mul => self.builder.build_int_mul(l, r, "mul").unwrap(),
+            BinaryOp::Div => self.builder.build_int_signed_div(l, r, "div").unwrap(),
+            BinaryOp::Mod => self.builder.build_int_signed_rem(l, r, "mod").unwrap(),
+            BinaryOp::Lt => compare(IntPredicate::SLT, "lt"),
+            BinaryOp::Gt => compare(IntPredicate::SGT, "gt"),
+            BinaryOp::Le => compare(IntPredicate::SLE, "le"),
+            BinaryOp::Ge => compare(IntPredicate::SGE, "ge"),
+            BinaryOp::Eq => compare(IntPredicate::EQ, "eq"),
+            BinaryOp::Ne => compare(IntPredicate::NE, "ne"),
+        }
+    }
+
+    /// Compile an arithmetic or comparison operation on floats
+    ///
+    /// Comparisons are ordered except `!=`, so that `x != x` is true for NaN
+    /// like in C.
+    fn compile_float_binary(
+        &self,
+        op: BinaryOp,
+        l: FloatValue<'ctx>,
+        r: FloatValue<'ctx>,
+    ) -> BasicValueEnum<'ctx> {
+        let compare = |predicate, name| -> BasicValueEnum<'ctx> {
+            let cmp = self
+                .builder
+                .build_float_compare(predicate, l, r, name)
+                .unwrap();
+            self.builder
+                .build_int_z_extend(cmp, self.context.i64_type(), "ext")
+                .unwrap()
+                .into()
+        };
+
+        match op {
+            BinaryOp::Add => self.builder.build_float_add(l, r, "fadd").unwrap().into(),
+            BinaryOp::Sub => self.builder.build_float_sub(l, r, "fsub").unwrap().into(),
+            BinaryOp::Mul => self.builder.build_float_mul(l, r, "fmul").unwrap().into(),
+            BinaryOp::Div => self.builder.build_float_div(l, r, "fdiv").unwrap().into(),
+            BinaryOp::Mod => self.builder.build_float_rem(l, r, "fmod").unwrap().into(),
+            BinaryOp::Lt => compare(FloatPredicate::OLT, "lt"),
+            BinaryOp::Gt => compare(FloatPredicate::OGT, "gt"),
+            BinaryOp::Le => compare(FloatPredicate::OLE, "le"),
+            BinaryOp::Ge => compare(FloatPredicate::OGE, "ge"),
+            BinaryOp::Eq => compare(FloatPredicate::OEQ, "eq"),
+            BinaryOp::Ne => compare(FloatPredicate::UNE, "ne"),
+        }
+    }
+
+    /// Print the value of `arg` on its own line
+    ///
+    /// Ints and floats go through `printf`, bools through `puts`. The output
+    /// is flushed right away, so that it comes before whatever the host
+    /// prints after running the program.
+    fn compile_print(&mut self, arg: &TypedExpr) -> Result<BasicValueEnum<'ctx>, String> {
+        let value = self.compile_expr(arg)?;
+
+        if arg.ty == Type::Bool {
+            let value = self
+                .convert(value, self.context.bool_type().into())
+                .into_int_value();
+            let true_str = self
+                .builder
+                .build_global_string_ptr("true", "true")
+                .unwrap();
+            let false_str = self
+                .builder
+                .build_global_string_ptr("false", "false")
+                .unwrap();
+            let text = self
+                .builder
+                .build_select(
+                    value,
+                    true_str.as_pointer_value(),
+                    false_str.as_pointer_value(),
+                    "text",
+                )
+                .unwrap();
+            let puts = self.module.get_function("puts").unwrap();
+            self.builder
+                .build_call(puts, &[text.into()], "puts")
+                .unwrap();
+        } else {
+            let format = if arg.ty == Type::Float {
+                "%g\n"
+            } else {
+                "%lld\n"
+            };
+            let format = self
+                .builder
+                .build_global_string_ptr(format, "format")
+                .unwrap();
+            let printf = self.module.get_function("printf").unwrap();
+            self.builder
+                .build_call(
+                    printf,
+                    &[format.as_pointer_value().into(), value.into()],
+                    "printf",
+                )
+                .unwrap();
+        }
+
+        // fflush(NULL) flushes every output stream
+        let fflush = self.module.get_function("fflush").unwrap();
+        let null = self.context.ptr_type(AddressSpace::default()).const_null();
+        self.builder
+            .build_call(fflush, &[null.into()], "fflush")
+            .unwrap();
+
+        Ok(self.context.i64_type().const_zero().into())
+    }
+
+    /// Store `value` into every element of the array at `ptr`
+    fn build_fill(
+        &self,
+        ptr: PointerValue<'ctx>,
+        array_type: ArrayType<'ctx>,
+        value: BasicValueEnum<'ctx>,
+    ) {
+        let len = array_type.len();
+        if len == 0 {
+            return;
+        }
+
+        let i64_type = self.context.i64_type();
+        let function = self.current_fn.unwrap();
+        let before = self.builder.get_insert_block().unwrap();
+        let loop_bb = self.context.append_basic_block(function, "fill");
+        let end_bb = self.context.append_basic_block(function, "fill_end");
+        self.builder.build_unconditional_branch(loop_bb).unwrap();
+
+        // for (i = 0; i < len; i++) ptr[i] = value
+        self.builder.position_at_end(loop_bb);
+        let i = self.builder.build_phi(i64_type, "i").unwrap();
+        i.add_incoming(&[(&i64_type.const_zero(), before)]);
+        let i_value = i.as_basic_value().into_int_value();
+        let elem = unsafe {
+            self.builder
+                .build_in_bounds_gep(array_type, ptr, &[i64_type.const_zero(), i_value], "elem")
+                .unwrap()
+        };
+        self.builder.build_store(elem, value).unwrap();
+        let next = self
+            .builder
+            .build_int_add(i_value, i64_type.const_int(1, false), "next")
+            .unwrap();
+        i.add_incoming(&[(&next, loop_bb)]);
+        let len_value = i64_type.const_int(len as u64, false);
+        let done = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, next, len_value, "done")
+            .unwrap();
+        self.builder
+            .build_conditional_branch(done, end_bb, loop_bb)
+            .unwrap();
+
+        self.builder.position_at_end(end_bb);
+    }
+
+    /// Pointer to the array element `expr`, an `Expr::Index`, and its type
+    ///
+    /// The index is checked to be in bounds unless the constant folder
+    /// proved it is.
+    fn element_pointer(
+        &mut self,
+        expr: &TypedExpr,
+    ) -> Result<(PointerValue<'ctx>, BasicTypeEnum<'ctx>), String> {
+        let Expr::Index {
+            array,
+            index,
+            checked,
+        } = &expr.expr
+        else {
+            return Err(format!("Cannot assign to {}", expr.expr));
+        };
+
+        let array_type = self.llvm_type(&array.ty)?.into_array_type();
+        let ptr = self.array_pointer(array)?;
+        let index_value = self.compile_expr(index)?.into_int_value();
+        if *checked {
+            self.build_bounds_check(index_value, array_type.len(), expr);
+        }
+
+        let zero = self.context.i64_type().const_zero();
+        let elem = unsafe {
+            self.builder
+                .build_in_bounds_gep(array_type, ptr, &[zero, index_value], "elem")
+                .unwrap()
+        };
+        Ok((elem, array_type.get_element_type()))
+    }
+
+    /// Pointer to an array: the variable or outer array element holding it,
+    /// or else a copy on the stack
+    fn array_pointer(&mut self, array: &TypedExpr) -> Result<PointerValue<'ctx>, String> {
+        match &array.expr {
+            Expr::Var(name) => self
+                .variables
+                .get(name)
+                .map(|(ptr, _)| *ptr)
+                .ok_or_else(|| format!("Undefined variable: {}", name)),
+            Expr::Index { .. } => Ok(self.element_pointer(array)?.0),
+            _ => {
+                let value = self.compile_expr(array)?;
+                let function = self.current_fn.unwrap();
+                let ptr = self.create_entry_block_alloca(&function, "array", value.get_type())?;
+                self.builder.build_store(ptr, value).unwrap();
+                Ok(ptr)
+            }
+        }
+    }
+
+    /// Abort with a message naming `expr` unless `0 <= index < len`
+    fn build_bounds_check(&self, index: IntValue<'ctx>, len: u32, expr: &TypedExpr) {
+        let i64_type = self.context.i64_type();
+        let function = self.current_fn.unwrap();
+        let in_bounds_bb = self.context.append_basic_block(function, "in_bounds");
+        let out_of_bounds_bb = self.context.append_basic_block(function, "out_of_bounds");
+
+        // A negative index is a huge unsigned one, so one comparison checks
+        // both ends
+        let len_value = i64_type.const_int(len as u64, false);
+        let in_bounds = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, index, len_value, "in_bounds")
+            .unwrap();
+        self.builder
+            .build_conditional_branch(in_bounds, in_bounds_bb, out_of_bounds_bb)
+            .unwrap();
+
+        self.builder.position_at_end(out_of_bounds_bb);
+        let message = format!(
+            "Index out of bounds in `{}`: the index is %lld but the length is {}\n",
+            expr.expr.to_string().replace('%', "%%"),
+            len
+        );
+        let format = self
+            .builder
+            .build_global_string_ptr(&message, "bounds_message")
+            .unwrap();
+        let stderr = self.context.i32_type().const_int(2, false);
+        let dprintf = self.module.get_function("dprintf").unwrap();
+        self.builder
+            .build_call(
+                dprintf,
+                &[
+                    stderr.into(),
+                    format.as_pointer_value().into(),
+                    index.into(),
+                ],
+                "dprintf",
+            )
+            .unwrap();
+        let abort = self.module.get_function("abort").unwrap();
+        self.builder.build_call(abort, &[], "abort").unwrap();
+        self.builder.build_unreachable().unwrap();
+
+        self.builder.position_at_end(in_bounds_bb);
+    }
+
     /// Create an alloca in the entry block
     fn create_entry_block_alloca(
         &self,
         function: &FunctionValue<'ctx>,
         name: &str,
-        ty: &Type,
+        ty: BasicTypeEnum<'ctx>,
     ) -> Result<PointerValue<'ctx>, String> {
         let builder = self.context.create_builder();
         let entry = function.get_first_basic_block().unwrap();
@@ -487,8 +1094,7 @@ impl<'ctx> CodeGen<'ctx> {
             None => builder.position_at_end(entry),
         }
 
-        let llvm_type = self.llvm_type(ty)?;
-        Ok(builder.build_alloca(llvm_type, name).unwrap())
+        Ok(builder.build_alloca(ty, name).unwrap())
     }
 
     /// Get the compiled module
@@ -500,30 +1106,75 @@ impl<'ctx> CodeGen<'ctx> {
     pub fn print_ir(&self) -> String {
         self.module.print_to_string().to_string()
     }
+
+    // ANCHOR: run_passes
+    /// Run LLVM optimization passes using the New Pass Manager
+    ///
+    /// # Arguments
+    /// * `passes` - A comma-separated list of passes, e.g., "dce,mem2reg,instcombine"
+    ///   or a preset like "default<O2>"
+    /// * `level` - Optimization level of the target machine the passes query
+    ///
+    /// # Common passes for teaching:
+    /// - `dce` - Dead Code Elimination
+    /// - `mem2reg` - Promote allocas to SSA registers
+    /// - `instcombine` - Combine redundant instructions
+    /// - `simplifycfg` - Simplify control flow graph
+    /// - `gvn` - Global Value Numbering
+    /// - `default<O0>` through `default<O3>` - Standard optimization levels
+    pub fn run_passes(&self, passes: &str, level: OptimizationLevel) -> Result<(), String> {
+        let target_machine = target_machine(level)?;
+
+        // Create pass builder options
+        let pass_options = PassBuilderOptions::create();
+        pass_options.set_verify_each(true); // Verify IR after each pass
+
+        // Run the passes
+        self.module
+            .run_passes(passes, &target_machine, pass_options)
+            .map_err(|e| format!("Failed to run passes: {}", e))
+    }
+    // ANCHOR_END: run_passes
+
+    /// Run the pass pipeline of `opts`, if it has one
+    pub fn optimize(&self, opts: &LlvmOpts) -> Result<(), String> {
+        match &opts.passes {
+            Some(passes) => self.run_passes(passes, opts.level),
+            None => Ok(()),
+        }
+    }
 }
 
 // ANCHOR: jit_run
 /// JIT compile and run a program
 pub fn jit_run(program: &Program) -> Result<i64, String> {
-    let context = Context::create();
-    let mut codegen = CodeGen::new(&context, "secondlang");
-
-    codegen.compile(program)?;
+    jit_run_with_opts(program, &LlvmOpts::default())
+}
+// ANCHOR_END: jit_run
 
-    // Create execution engine
-    let engine = codegen
-        .module
-        .create_jit_execution_engine(OptimizationLevel::Default)
-        .map_err(|e| format!("Failed to create JIT: {}", e.to_string()))?;
+// ANCHOR: jit_run_optimized
+/// JIT compile and run a program with LLVM optimizations
+pub fn jit_run_with_opts(program: &Program, opts: &LlvmOpts) -> Result<i64, String> {
+    jit_run_with_host(program, opts, &HostFunctions::new())
+}
+// ANCHOR_END: jit_run_optimized
+
+/// JIT compile and run a program that calls host functions
+///
+/// The program must have been declared with `HostFunctions::declare` before
+/// type checking. Each host function is mapped to its Rust function, which
+/// the JIT calls instead of looking up a symbol.
+pub fn jit_run_with_host(
+    program: &Program,
+    opts: &LlvmOpts,
+    host: &HostFunctions,
+) -> Result<i64, String> {
+    let context = Context::create();
+    let module = CompiledModule::new(&context, program, opts, host)?;
 
-    // Call the __main wrapper function which contains the top-level expression
-    unsafe {
-        let func: inkwell::execution_engine::JitFunction<unsafe extern "C" fn() -> i64> =
-            engine.get_function("__main").map_err(|e| e.to_string())?;
-        Ok(func.call())
-    }
+    // Call the __main wrapper function which contains the top-level statements
+    module.run_main()
 }
-// ANCHOR_END: jit_run
 
 #[cfg(test)]
 mod tests {
@@ -563,4 +1214,179 @@ mod tests {
         let mut codegen = CodeGen::new(&context, "test");
         codegen.compile(&program).unwrap();
     }
+
+    #[test]
+    fn test_compile_float_function() {
+        let source = r#"
+            def scale(x: float, n: int) -> float {
+                return x * float(n)
+            }
+            int(scale(1.5, 4))
+        "#;
+        let mut program = parse(source).unwrap();
+        typecheck(&mut program).unwrap();
+
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.compile(&program).unwrap();
+        let scale = codegen.get_module().get_function("scale").unwrap();
+        assert!(scale.get_type().get_return_type().unwrap().is_float_type());
+    }
+
+    #[test]
+    fn test_compile_float_function_ending_in_loop() {
+        let source = r#"
+            def f(x: float) -> float {
+                while (x > 0.0) {
+                    return x
+                }
+            }
+            int(f(2.5))
+        "#;
+        let mut program = parse(source).unwrap();
+        typecheck(&mut program).unwrap();
+
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.compile(&program).unwrap();
+        assert!(codegen.print_ir().contains("ret double 0.000000e+00"));
+    }
+
+    #[test]
+    fn test_compile_array_parameter_too_large() {
+        let source = r#"
+            def f(xs: [int; 5000000000]) -> int {
+                return 0
+            }
+        "#;
+        let mut program = parse(source).unwrap();
+        typecheck(&mut program).unwrap();
+
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        let err = codegen.compile(&program).unwrap_err();
+        assert!(err.contains("is too large"), "{}", err);
+    }
+
+    #[test]
+    fn test_compile_branch_scopes() {
+        // `x` is an int in one branch and a float in the other
+        let source = r#"
+            def f(c: bool) -> int {
+                if (c) {
+                    x = 1
+                    0
+                } else {
+                    x = 2.5
+                    y = x + 1.0
+                    0
+                }
+            }
+            f(false)
+        "#;
+        let mut program = parse(source).unwrap();
+        typecheck(&mut program).unwrap();
+
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.compile(&program).unwrap();
+        assert!(codegen.print_ir().contains("fadd double"));
+    }
+
+    #[test]
+    fn test_compile_bools_are_zero_extended() {
+        let source = r#"
+            extern def report(x: float, flag: bool)
+            def is_positive(n: int) -> bool {
+                return n > 0
+            }
+            def negate(b: bool) -> bool {
+                return !b
+            }
+        "#;
+        let mut program = parse(source).unwrap();
+        typecheck(&mut program).unwrap();
+
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.compile(&program).unwrap();
+        let ir = codegen.print_ir();
+        assert!(ir.contains("define zeroext i1 @is_positive(i64"), "{}", ir);
+        assert!(
+            ir.contains("define zeroext i1 @negate(i1 zeroext"),
+            "{}",
+            ir
+        );
+        assert!(
+            ir.contains("declare void @report(double, i1 zeroext)"),
+            "{}",
+            ir
+        );
+    }
+
+    #[test]
+    fn test_compile_top_level_statements() {
+        let source = r#"
+            x = 42
+            y = x * 2
+        "#;
+        let mut program = parse(source).unwrap();
+        typecheck(&mut program).unwrap();
+
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.compile(&program).unwrap();
+        assert!(codegen.get_module().get_function(MAIN_FUNCTION).is_some());
+    }
+
+    #[test]
+    fn test_compile_functions_only_has_no_main() {
+        let source = r#"
+            def answer() -> int {
+                return 42
+            }
+        "#;
+        let mut program = parse(source).unwrap();
+        typecheck(&mut program).unwrap();
+
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.compile(&program).unwrap();
+        assert!(codegen.get_module().get_function(MAIN_FUNCTION).is_none());
+    }
+
+    #[test]
+    fn test_compile_extern_is_declaration() {
+        let source = r#"
+            extern def labs(x: int) -> int
+            extern def srand(seed: int)
+            labs(0 - 3)
+        "#;
+        let mut program = parse(source).unwrap();
+        typecheck(&mut program).unwrap();
+
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.compile(&program).unwrap();
+        let labs = codegen.get_module().get_function("labs").unwrap();
+        assert_eq!(labs.count_basic_blocks(), 0);
+        assert_eq!(labs.get_linkage(), Linkage::External);
+        let srand = codegen.get_module().get_function("srand").unwrap();
+        assert!(srand.get_type().get_return_type().is_none());
+    }
+
+    #[test]
+    fn test_compile_extern_conflicting_with_libc() {
+        let source = r#"
+            extern def puts(x: int) -> int
+            puts(1)
+        "#;
+        let mut program = parse(source).unwrap();
+        typecheck(&mut program).unwrap();
+
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        let err = codegen.compile(&program).unwrap_err();
+        assert!(err.contains("puts"));
+    }
 }
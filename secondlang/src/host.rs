@@ -0,0 +1,192 @@
+//! Host functions: Rust functions that JIT-compiled programs can call
+//!
+//! An application embedding Secondlang registers `extern "C"` functions by
+//! name in `HostFunctions`. `declare` gives the program an `extern def` for
+//! each of them, so the type checker knows their signatures and the code
+//! generator declares them, and the JIT maps each declaration to the address
+//! of its Rust function instead of looking for a C symbol:
+//!
+//! ```ignore
+//! extern "C" fn twice(n: i64) -> i64 {
+//!     n * 2
+//! }
+//!
+//! let mut host = HostFunctions::new();
+//! host.register("twice", twice as extern "C" fn(i64) -> i64);
+//! assert_eq!(run_with_host("twice(21)", &host), Ok(42));
+//! ```
+
+use crate::ast::{Program, Stmt};
+use crate::types::Type;
+
+/// A Rust type that is passed to and from compiled code as a Secondlang type
+pub trait NativeType {
+    /// The Secondlang type with the same representation
+    fn secondlang_type() -> Type;
+}
+
+impl NativeType for i64 {
+    fn secondlang_type() -> Type {
+        Type::Int
+    }
+}
+
+impl NativeType for f64 {
+    fn secondlang_type() -> Type {
+        Type::Float
+    }
+}
+
+impl NativeType for bool {
+    fn secondlang_type() -> Type {
+        Type::Bool
+    }
+}
+
+/// `()` is only a return type: a function returning it returns nothing
+impl NativeType for () {
+    fn secondlang_type() -> Type {
+        Type::Unit
+    }
+}
+
+/// An `extern "C"` function pointer that compiled code can call
+pub trait HostFn: Copy {
+    /// The Secondlang types of the parameters
+    fn params() -> Vec<Type>;
+    /// The Secondlang return type
+    fn return_type() -> Type;
+    /// The address of the function
+    fn address(self) -> usize;
+}
+
+macro_rules! impl_host_fn {
+    ($($param:ident),*) => {
+        impl<R: NativeType, $($param: NativeType),*> HostFn for extern "C" fn($($param),*) -> R {
+            fn params() -> Vec<Type> {
+                vec![$($param::secondlang_type()),*]
+            }
+
+            fn return_type() -> Type {
+                R::secondlang_type()
+            }
+
+            fn address(self) -> usize {
+                self as usize
+            }
+        }
+    };
+}
+
+impl_host_fn!();
+impl_host_fn!(A);
+impl_host_fn!(A, B);
+impl_host_fn!(A, B, C);
+impl_host_fn!(A, B, C, D);
+impl_host_fn!(A, B, C, D, E);
+impl_host_fn!(A, B, C, D, E, F);
+
+/// A registered host function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostFunction {
+    pub name: String,
+    pub params: Vec<Type>,
+    pub return_type: Type,
+    /// Address of the Rust function
+    pub address: usize,
+}
+
+/// The host functions available to a program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostFunctions {
+    functions: Vec<HostFunction>,
+}
+
+impl HostFunctions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make `function` callable as `name`, replacing any function registered
+    /// under that name before
+    pub fn register<F: HostFn>(&mut self, name: &str, function: F) -> &mut Self {
+        let function = HostFunction {
+            name: name.to_string(),
+            params: F::params(),
+            return_type: F::return_type(),
+            address: function.address(),
+        };
+        match self.functions.iter_mut().find(|f| f.name == name) {
+            Some(existing) => *existing = function,
+            None => self.functions.push(function),
+        }
+        self
+    }
+
+    /// Look up a registered function
+    pub fn get(&self, name: &str) -> Option<&HostFunction> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HostFunction> {
+        self.functions.iter()
+    }
+
+    /// Add an `extern def` for every host function to the start of a program,
+    /// before it is type checked
+    pub fn declare(&self, program: &mut Program) {
+        let externs = self.functions.iter().map(|f| Stmt::Extern {
+            name: f.name.clone(),
+            params: f
+                .params
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| (format!("arg{}", i + 1), ty.clone()))
+                .collect(),
+            return_type: f.return_type.clone(),
+        });
+        program.splice(0..0, externs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" fn add(a: i64, b: i64) -> i64 {
+        a + b
+    }
+
+    extern "C" fn report(_x: f64, _flag: bool) {}
+
+    #[test]
+    fn test_register_signatures() {
+        let mut host = HostFunctions::new();
+        host.register("add", add as extern "C" fn(i64, i64) -> i64)
+            .register("report", report as extern "C" fn(f64, bool));
+
+        let add_fn = host.get("add").unwrap();
+        assert_eq!(add_fn.params, vec![Type::Int, Type::Int]);
+        assert_eq!(add_fn.return_type, Type::Int);
+        let address = add as extern "C" fn(i64, i64) -> i64;
+        assert_eq!(add_fn.address, address as usize);
+
+        let report_fn = host.get("report").unwrap();
+        assert_eq!(report_fn.params, vec![Type::Float, Type::Bool]);
+        assert_eq!(report_fn.return_type, Type::Unit);
+    }
+
+    #[test]
+    fn test_declare_adds_externs() {
+        let mut host = HostFunctions::new();
+        host.register("add", add as extern "C" fn(i64, i64) -> i64);
+
+        let mut program = vec![];
+        host.declare(&mut program);
+        assert_eq!(program.len(), 1);
+        assert_eq!(
+            program[0].to_string(),
+            "extern def add(arg1: int, arg2: int) -> int"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Calling JIT-compiled functions from Rust
+//!
+//! `jit_run` compiles a program, runs its top-level statements and throws the
+//! compiled code away. A `CompiledModule` keeps it: its functions can be
+//! looked up by name as Rust function types and called any number of times.
+//! Every lookup checks the Rust type against the Secondlang signature the
+//! type checker gave the function:
+//!
+//! ```ignore
+//! let context = Context::create();
+//! let module = compile_module(&context, "def add(a: int, b: int) -> int { return a + b }")?;
+//! let add = module.get::<fn(i64, i64) -> i64>("add")?;
+//! assert_eq!(add.call(2, 3), 5);
+//! ```
+
+use std::collections::HashMap;
+
+use inkwell::context::Context;
+use inkwell::execution_engine::{ExecutionEngine, JitFunction, UnsafeFunctionPointer};
+
+use crate::ast::{Program, Stmt};
+use crate::codegen::{CodeGen, LlvmOpts, MAIN_FUNCTION};
+use crate::host::{HostFunctions, NativeType};
+use crate::types::Type;
+
+/// A Rust function type, like `fn(i64, i64) -> i64`, to call a compiled
+/// function as
+pub trait Signature {
+    /// The type of the compiled function itself
+    type Native: UnsafeFunctionPointer;
+
+    /// The Secondlang function type with the same parameters and result
+    fn secondlang_type() -> Type;
+}
+
+/// A compiled function, callable with the parameters and result of `F`
+pub struct Function<'ctx, F: Signature> {
+    inner: JitFunction<'ctx, F::Native>,
+}
+
+macro_rules! impl_signature {
+    ($($param:ident),*) => {
+        impl<R: NativeType, $($param: NativeType),*> Signature for fn($($param),*) -> R {
+            type Native = unsafe extern "C" fn($($param),*) -> R;
+
+            fn secondlang_type() -> Type {
+                Type::Function {
+                    params: vec![$($param::secondlang_type()),*],
+                    ret: Box::new(R::secondlang_type()),
+                }
+            }
+        }
+
+        impl<'ctx, R: NativeType, $($param: NativeType),*> Function<'ctx, fn($($param),*) -> R> {
+            /// Call the compiled function
+            #[allow(non_snake_case)]
+            pub fn call(&self, $($param: $param),*) -> R {
+                // Safe because `CompiledModule::get` checked the signature,
+                // and bools cross as zero-extended `i1`s, as Rust expects
+                unsafe { self.inner.call($($param),*) }
+            }
+        }
+    };
+}
+
+impl_signature!();
+impl_signature!(A);
+impl_signature!(A, B);
+impl_signature!(A, B, C);
+impl_signature!(A, B, C, D);
+impl_signature!(A, B, C, D, E);
+impl_signature!(A, B, C, D, E, F);
+
+/// A JIT-compiled program
+///
+/// The module keeps the execution engine, and with it the compiled code,
+/// alive for as long as it lives.
+pub struct CompiledModule<'ctx> {
+    engine: ExecutionEngine<'ctx>,
+    /// Secondlang types of the functions the program defines
+    signatures: HashMap<String, Type>,
+    /// Whether the program has top-level statements to run
+    has_main: bool,
+}
+
+impl<'ctx> CompiledModule<'ctx> {
+    /// JIT compile a type-checked program, mapping the host functions it was
+    /// declared with to their Rust functions
+    pub fn new(
+        context: &'ctx Context,
+        program: &Program,
+        opts: &LlvmOpts,
+        host: &HostFunctions,
+    ) -> Result<Self, String> {
+        let mut codegen = CodeGen::new(context, "secondlang");
+        codegen.compile(program)?;
+        codegen.optimize(opts)?;
+        let has_main = codegen.main_function().is_ok();
+
+        let module = codegen.get_module();
+        let engine = module
+            .create_jit_execution_engine(opts.level)
+            .map_err(|e| format!("Failed to create JIT: {}", e.to_string()))?;
+
+        // LLVM passes may have removed the declarations of functions the
+        // program does not call
+        for function in host.iter() {
+            if let Some(declaration) = module.get_function(&function.name) {
+                engine.add_global_mapping(&declaration, function.address);
+            }
+        }
+
+        let signatures = program
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Function {
+                    name,
+                    params,
+                    return_type,
+                    ..
+                } => Some((
+                    name.clone(),
+                    Type::Function {
+                        params: params.iter().map(|(_, t)| t.clone()).collect(),
+                        ret: Box::new(return_type.clone()),
+                    },
+                )),
+                _ => None,
+            })
+            .collect();
+
+        Ok(CompiledModule {
+            engine,
+            signatures,
+            has_main,
+        })
+    }
+
+    /// Look up a function the program defines, as the Rust function type `F`
+    ///
+    /// Fails unless the parameters and result of `F` are those of the
+    /// function: `i64` for `int`, `f64` for `float` and `bool` for `bool`.
+    pub fn get<F: Signature>(&self, name: &str) -> Result<Function<'ctx, F>, String> {
+        let signature = self
+            .signatures
+            .get(name)
+            .ok_or_else(|| format!("Undefined function: {}", name))?;
+        let expected = F::secondlang_type();
+        if *signature != expected {
+            return Err(format!(
+                "Function {} has type {}, not {}",
+                name, signature, expected
+            ));
+        }
+
+        let inner = unsafe { self.engine.get_function(name) }.map_err(|e| e.to_string())?;
+        Ok(Function { inner })
+    }
+
+    /// Run the top-level statements and return the value of the last one
+    pub fn run_main(&self) -> Result<i64, String> {
+        if !self.has_main {
+            return Err("Nothing to run: the program has no top-level statements".to_string());
+        }
+
+        unsafe {
+            let main: JitFunction<unsafe extern "C" fn() -> i64> = self
+                .engine
+                .get_function(MAIN_FUNCTION)
+                .map_err(|e| e.to_string())?;
+            Ok(main.call())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::typeck::typecheck;
+
+    #[test]
+    fn test_signature_types() {
+        assert_eq!(
+            <fn(i64, f64) -> bool as Signature>::secondlang_type(),
+            Type::Function {
+                params: vec![Type::Int, Type::Float],
+                ret: Box::new(Type::Bool),
+            }
+        );
+        assert_eq!(
+            <fn() -> i64 as Signature>::secondlang_type().to_string(),
+            "() -> int"
+        );
+    }
+
+    #[test]
+    fn test_get_checks_signature() {
+        let mut program = parse("def inc(n) { return n + 1 }").unwrap();
+        typecheck(&mut program).unwrap();
+
+        let context = Context::create();
+        let module = CompiledModule::new(
+            &context,
+            &program,
+            &LlvmOpts::default(),
+            &HostFunctions::new(),
+        )
+        .unwrap();
+        assert_eq!(module.get::<fn(i64) -> i64>("inc").unwrap().call(41), 42);
+
+        let err = module.get::<fn(f64) -> f64>("inc").err().unwrap();
+        assert_eq!(
+            err,
+            "Function inc has type (int) -> int, not (float) -> float"
+        );
+        assert!(module.get::<fn() -> i64>("missing").is_err());
+    }
+}
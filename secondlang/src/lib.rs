@@ -10,6 +10,9 @@
 //! - AST optimization passes (constant folding, algebraic simplification)
 //! - LLVM IR code generation
 //! - JIT compilation to native code
+//! - Host functions: Rust functions that compiled programs can call
+//! - Calls from Rust to compiled functions, checked against their types
+//! - Ahead-of-time compilation to object files and executables
 //!
 //! # Example
 //!
@@ -30,27 +33,93 @@ extern crate pest;
 #[macro_use]
 extern crate pest_derive;
 
+pub mod aot;
 pub mod ast;
 pub mod codegen;
+pub mod host;
+pub mod jit;
 pub mod parser;
 pub mod typeck;
 pub mod types;
 pub mod visitor;
 
+pub use aot::{build_program, Emit};
 pub use ast::{Expr, Program, Stmt, TypedExpr};
-pub use codegen::{jit_run, CodeGen};
+pub use codegen::{jit_run, jit_run_with_host, jit_run_with_opts, CodeGen, LlvmOpts};
+pub use host::{HostFn, HostFunctions, NativeType};
+pub use jit::{CompiledModule, Function, Signature};
 pub use parser::parse;
 pub use typeck::typecheck;
 pub use types::Type;
 pub use visitor::{AlgebraicSimplifier, ConstantFolder, ExprVisitor, PrettyPrinter};
 
+use std::path::Path;
+
+use inkwell::context::Context;
+
 /// Convenience function to compile and run source code
 pub fn run(source: &str) -> Result<i64, String> {
-    run_with_opts(source, false)
+    run_with_opts(source, false, &LlvmOpts::default())
+}
+
+/// Compile and run source code with LLVM optimization passes
+///
+/// # Arguments
+/// * `source` - The source code to compile
+/// * `passes` - Optimization passes (e.g., "dce,mem2reg,instcombine" or "default<O2>")
+pub fn run_optimized(source: &str, passes: &str) -> Result<i64, String> {
+    let opts = LlvmOpts {
+        passes: Some(passes.to_string()),
+        ..LlvmOpts::default()
+    };
+    run_with_opts(source, false, &opts)
+}
+
+/// Compile and run with optional AST and LLVM optimizations
+pub fn run_with_opts(source: &str, optimize: bool, llvm: &LlvmOpts) -> Result<i64, String> {
+    let mut program = parse(source)?;
+    typecheck(&mut program)?;
+
+    if optimize {
+        program = optimize_program(program);
+    }
+
+    jit_run_with_opts(&program, llvm)
+}
+
+/// Compile and run source code that calls the functions registered in `host`
+pub fn run_with_host(source: &str, host: &HostFunctions) -> Result<i64, String> {
+    let mut program = parse(source)?;
+    host.declare(&mut program);
+    typecheck(&mut program)?;
+
+    jit_run_with_host(&program, &LlvmOpts::default(), host)
 }
 
-/// Compile and run with optional optimizations
-pub fn run_with_opts(source: &str, optimize: bool) -> Result<i64, String> {
+/// JIT compile source code, keeping the compiled functions to call from Rust
+pub fn compile_module<'ctx>(
+    context: &'ctx Context,
+    source: &str,
+) -> Result<CompiledModule<'ctx>, String> {
+    let mut program = parse(source)?;
+    typecheck(&mut program)?;
+
+    CompiledModule::new(
+        context,
+        &program,
+        &LlvmOpts::default(),
+        &HostFunctions::new(),
+    )
+}
+
+/// Compile source code ahead of time, writing `emit` output to `output`
+pub fn build(
+    source: &str,
+    output: &Path,
+    emit: Emit,
+    optimize: bool,
+    llvm: &LlvmOpts,
+) -> Result<(), String> {
     let mut program = parse(source)?;
     typecheck(&mut program)?;
 
@@ -58,7 +127,7 @@ pub fn run_with_opts(source: &str, optimize: bool) -> Result<i64, String> {
         program = optimize_program(program);
     }
 
-    jit_run(&program)
+    build_program(&program, output, emit, llvm)
 }
 
 /// Apply optimization passes to the program
@@ -78,13 +147,15 @@ pub fn print_ast(source: &str) -> Result<String, String> {
 
 /// Compile source code and return LLVM IR as a string
 pub fn compile_to_ir(source: &str) -> Result<String, String> {
-    compile_to_ir_with_opts(source, false)
+    compile_to_ir_with_opts(source, false, &LlvmOpts::default())
 }
 
-/// Compile to IR with optional optimizations
-pub fn compile_to_ir_with_opts(source: &str, optimize: bool) -> Result<String, String> {
-    use inkwell::context::Context;
-
+/// Compile to IR with optional AST optimizations and LLVM passes
+pub fn compile_to_ir_with_opts(
+    source: &str,
+    optimize: bool,
+    llvm: &LlvmOpts,
+) -> Result<String, String> {
     let mut program = parse(source)?;
     typecheck(&mut program)?;
 
@@ -95,6 +166,7 @@ pub fn compile_to_ir_with_opts(source: &str, optimize: bool) -> Result<String, S
     let context = Context::create();
     let mut codegen = CodeGen::new(&context, "secondlang");
     codegen.compile(&program)?;
+    codegen.optimize(llvm)?;
 
     Ok(codegen.print_ir())
 }
@@ -5,12 +5,19 @@
 //!   secondlang --ir <file.sl>         Print LLVM IR
 //!   secondlang --ast <file.sl>        Print AST
 //!   secondlang --check <file.sl>      Type check only
-//!   secondlang -O <file.sl>           Run with optimizations
+//!   secondlang -O <file.sl>           Run with AST optimizations
+//!   secondlang -O2 <file.sl>          Run with LLVM's -O2 pipeline
+//!   secondlang --passes <passes> <file.sl>  Run with custom LLVM passes
+//!   secondlang build <file.sl> -o out  Compile to a native executable
 
 use std::env;
 use std::fs;
+use std::path::Path;
 
-use secondlang::{compile_to_ir_with_opts, parse, print_ast, run_with_opts, typecheck};
+use inkwell::OptimizationLevel;
+use secondlang::{
+    build, compile_to_ir_with_opts, parse, print_ast, run_with_opts, typecheck, Emit, LlvmOpts,
+};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -20,11 +27,18 @@ fn main() {
         return;
     }
 
+    if args[1] == "build" {
+        build_command(&args[2..]);
+        return;
+    }
+
     let mut filename = None;
     let mut show_ir = false;
     let mut show_ast = false;
     let mut check_only = false;
     let mut optimize = false;
+    let mut level = None;
+    let mut custom_passes = None;
 
     let mut i = 1;
     while i < args.len() {
@@ -33,6 +47,16 @@ fn main() {
             "--ast" => show_ast = true,
             "--check" => check_only = true,
             "-O" | "--optimize" => optimize = true,
+            arg @ ("-O0" | "-O1" | "-O2" | "-O3") => level = Some(opt_level(arg)),
+            "--passes" => {
+                i += 1;
+                if i < args.len() {
+                    custom_passes = Some(args[i].clone());
+                } else {
+                    eprintln!("Error: --passes requires a pass pipeline argument");
+                    std::process::exit(1);
+                }
+            }
             "--help" | "-h" => {
                 print_usage();
                 return;
@@ -65,6 +89,8 @@ fn main() {
         }
     };
 
+    let llvm = llvm_opts(level, custom_passes);
+
     if check_only {
         // Type check only
         match parse(&source) {
@@ -91,7 +117,7 @@ fn main() {
         }
     } else if show_ir {
         // Print LLVM IR
-        match compile_to_ir_with_opts(&source, optimize) {
+        match compile_to_ir_with_opts(&source, optimize, &llvm) {
             Ok(ir) => println!("{}", ir),
             Err(e) => {
                 eprintln!("Compilation error: {}", e);
@@ -100,7 +126,7 @@ fn main() {
         }
     } else {
         // JIT compile and run
-        match run_with_opts(&source, optimize) {
+        match run_with_opts(&source, optimize, &llvm) {
             Ok(result) => println!("{}", result),
             Err(e) => {
                 eprintln!("Error: {}", e);
@@ -110,22 +136,144 @@ fn main() {
     }
 }
 
+/// The optimization level of `-O0` to `-O3`
+fn opt_level(arg: &str) -> OptimizationLevel {
+    match arg {
+        "-O0" => OptimizationLevel::None,
+        "-O1" => OptimizationLevel::Less,
+        "-O2" => OptimizationLevel::Default,
+        _ => OptimizationLevel::Aggressive,
+    }
+}
+
+/// LLVM settings for an `-O<n>` level, with `--passes` replacing its pipeline
+fn llvm_opts(level: Option<OptimizationLevel>, passes: Option<String>) -> LlvmOpts {
+    let mut opts = level.map(LlvmOpts::level).unwrap_or_default();
+    if passes.is_some() {
+        opts.passes = passes;
+    }
+    opts
+}
+
+/// `secondlang build <file.sl> [-o <output>] [--emit exe|obj|asm]` and optimization flags
+fn build_command(args: &[String]) {
+    let mut filename = None;
+    let mut output = None;
+    let mut emit = Emit::Exe;
+    let mut optimize = false;
+    let mut level = None;
+    let mut custom_passes = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-o" | "--emit" | "--passes" if i + 1 == args.len() => {
+                eprintln!("Error: {} requires an argument", args[i]);
+                std::process::exit(1);
+            }
+            "-o" => {
+                i += 1;
+                output = Some(args[i].clone());
+            }
+            "--emit" => {
+                i += 1;
+                emit = match args[i].parse() {
+                    Ok(emit) => emit,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "-O" | "--optimize" => optimize = true,
+            arg @ ("-O0" | "-O1" | "-O2" | "-O3") => level = Some(opt_level(arg)),
+            "--passes" => {
+                i += 1;
+                custom_passes = Some(args[i].clone());
+            }
+            arg if !arg.starts_with('-') => {
+                filename = Some(arg.to_string());
+            }
+            _ => {
+                eprintln!("Unknown option: {}", args[i]);
+                std::process::exit(1);
+            }
+        }
+        i += 1;
+    }
+
+    let filename = match filename {
+        Some(f) => f,
+        None => {
+            eprintln!("Error: No input file specified");
+            print_usage();
+            std::process::exit(1);
+        }
+    };
+
+    // By default, write next to the source: fib.sl builds fib, fib.o or fib.s
+    let output = output.unwrap_or_else(|| {
+        let path = Path::new(&filename);
+        match emit {
+            Emit::Exe => path.with_extension(""),
+            Emit::Obj => path.with_extension("o"),
+            Emit::Asm => path.with_extension("s"),
+        }
+        .to_string_lossy()
+        .into_owned()
+    });
+    if Path::new(&output) == Path::new(&filename) {
+        eprintln!(
+            "Error: the output would overwrite '{}', choose one with -o",
+            filename
+        );
+        std::process::exit(1);
+    }
+
+    let source = match fs::read_to_string(&filename) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error reading file '{}': {}", filename, e);
+            std::process::exit(1);
+        }
+    };
+
+    let llvm = llvm_opts(level, custom_passes);
+    if let Err(e) = build(&source, Path::new(&output), emit, optimize, &llvm) {
+        eprintln!("Compilation error: {}", e);
+        std::process::exit(1);
+    }
+}
+
 fn print_usage() {
     println!("Secondlang Compiler v0.1.0");
     println!();
     println!("Usage:");
     println!("  secondlang <file.sl>              JIT compile and run");
-    println!("  secondlang -O <file.sl>           Run with optimizations");
+    println!("  secondlang -O <file.sl>           Run with AST optimizations");
+    println!("  secondlang -O0..-O3 <file.sl>     Run with LLVM's standard pipeline at that level");
+    println!("  secondlang --passes <p> <file.sl> Run with custom LLVM passes");
     println!("  secondlang --ir <file.sl>         Print LLVM IR");
     println!("  secondlang --ir -O <file.sl>      Print optimized LLVM IR");
     println!("  secondlang --ast <file.sl>        Print AST");
     println!("  secondlang --check <file.sl>      Type check only");
+    println!("  secondlang build <file.sl> -o out Compile to a native executable");
     println!("  secondlang --help                 Show this help");
     println!();
+    println!("Build options:");
+    println!("  -o <file>                Output file (default: the source file without .sl)");
+    println!("  --emit exe|obj|asm       Write an executable, object file or assembly");
+    println!("  -O, -O0..-O3, --passes   Optimize as when running");
+    println!();
     println!("Optimization passes (with -O):");
     println!("  - Constant folding:        1 + 2 * 3 -> 7");
     println!("  - Algebraic simplification: x + 0 -> x, x * 1 -> x");
     println!();
+    println!("LLVM passes (with --passes, e.g. \"mem2reg,instcombine\"):");
+    println!("  - mem2reg: promote stack variables to registers");
+    println!("  - instcombine, simplifycfg, gvn, dce");
+    println!("  - default<O0> to default<O3>: the pipelines of -O0 to -O3");
+    println!();
     println!("Example:");
     println!("  secondlang examples/fibonacci.sl");
 }
@@ -23,6 +23,9 @@ pub fn parse(source: &str) -> Result<Program, String> {
             Rule::Stmt => {
                 program.push(parse_stmt(pair)?);
             }
+            Rule::Extern => {
+                program.push(parse_extern(pair)?);
+            }
             Rule::EOI => {}
             _ => {}
         }
@@ -36,6 +39,7 @@ fn parse_stmt(pair: Pair<Rule>) -> Result<Stmt, String> {
         Rule::Function => parse_function(inner),
         Rule::Return => parse_return(inner),
         Rule::Assignment => parse_assignment(inner),
+        Rule::IndexAssignment => parse_index_assignment(inner),
         Rule::Expr => Ok(Stmt::Expr(parse_expr(inner)?)),
         Rule::Conditional | Rule::WhileLoop | Rule::Comparison => {
             Ok(Stmt::Expr(parse_expr(inner)?))
@@ -49,8 +53,11 @@ fn parse_function(pair: Pair<Rule>) -> Result<Stmt, String> {
 
     let name = inner.next().unwrap().as_str().to_string();
 
+    // Types left out are numbered type variables, which the type checker
+    // replaces by fresh ones and infers
+    let mut vars = 0..;
     let mut params: Vec<(String, Type)> = Vec::new();
-    let mut return_type = Type::Unknown;
+    let mut return_type = None;
     let mut body = Vec::new();
 
     for item in inner {
@@ -58,12 +65,15 @@ fn parse_function(pair: Pair<Rule>) -> Result<Stmt, String> {
             Rule::TypedParam => {
                 let mut param_inner = item.into_inner();
                 let param_name = param_inner.next().unwrap().as_str().to_string();
-                let param_type = parse_type(param_inner.next().unwrap())?;
+                let param_type = match param_inner.next() {
+                    Some(type_pair) => parse_type(type_pair)?,
+                    None => Type::Var(vars.next().unwrap()),
+                };
                 params.push((param_name, param_type));
             }
             Rule::ReturnType => {
                 let type_pair = item.into_inner().next().unwrap();
-                return_type = parse_type(type_pair)?;
+                return_type = Some(parse_type(type_pair)?);
             }
             Rule::Block => {
                 body = parse_block(item)?;
@@ -75,20 +85,74 @@ fn parse_function(pair: Pair<Rule>) -> Result<Stmt, String> {
     Ok(Stmt::Function {
         name,
         params,
-        return_type,
+        return_type: return_type.unwrap_or_else(|| Type::Var(vars.next().unwrap())),
         body,
     })
 }
 
+fn parse_extern(pair: Pair<Rule>) -> Result<Stmt, String> {
+    let mut inner = pair.into_inner();
+
+    let name = inner.next().unwrap().as_str().to_string();
+
+    // C functions cannot be inferred from a body: every parameter needs a
+    // type, and no return type means the function returns nothing
+    let mut params: Vec<(String, Type)> = Vec::new();
+    let mut return_type = Type::Unit;
+
+    for item in inner {
+        match item.as_rule() {
+            Rule::TypedParam => {
+                let mut param_inner = item.into_inner();
+                let param_name = param_inner.next().unwrap().as_str().to_string();
+                let Some(type_pair) = param_inner.next() else {
+                    return Err(format!(
+                        "Parameter {} of extern function {} needs a type",
+                        param_name, name
+                    ));
+                };
+                params.push((param_name, parse_type(type_pair)?));
+            }
+            Rule::ReturnType => {
+                let type_pair = item.into_inner().next().unwrap();
+                return_type = parse_type(type_pair)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Stmt::Extern {
+        name,
+        params,
+        return_type,
+    })
+}
+
 fn parse_type(pair: Pair<Rule>) -> Result<Type, String> {
     match pair.as_rule() {
         Rule::Type => parse_type(pair.into_inner().next().unwrap()),
         Rule::IntType => Ok(Type::Int),
+        Rule::FloatType => Ok(Type::Float),
         Rule::BoolType => Ok(Type::Bool),
+        Rule::ArrayType => {
+            let mut inner = pair.into_inner();
+            let element = parse_type(inner.next().unwrap())?;
+            let size = parse_size(inner.next().unwrap())?;
+            Ok(Type::Array {
+                element: Box::new(element),
+                size,
+            })
+        }
         r => Err(format!("Unexpected type rule: {:?}", r)),
     }
 }
 
+fn parse_size(pair: Pair<Rule>) -> Result<usize, String> {
+    pair.as_str()
+        .parse()
+        .map_err(|_| format!("Invalid array size: {}", pair.as_str()))
+}
+
 fn parse_block(pair: Pair<Rule>) -> Result<Vec<Stmt>, String> {
     let mut stmts = Vec::new();
     for item in pair.into_inner() {
@@ -130,6 +194,35 @@ fn parse_assignment(pair: Pair<Rule>) -> Result<Stmt, String> {
     })
 }
 
+fn parse_index_assignment(pair: Pair<Rule>) -> Result<Stmt, String> {
+    let mut inner = pair.into_inner();
+    let name = inner.next().unwrap().as_str().to_string();
+
+    let mut target = TypedExpr::unknown(Expr::Var(name));
+    let mut value = None;
+    for item in inner {
+        match item.as_rule() {
+            Rule::Index => target = parse_index(target, item)?,
+            _ => value = Some(parse_expr(item)?),
+        }
+    }
+
+    Ok(Stmt::IndexAssign {
+        target,
+        value: value.unwrap(),
+    })
+}
+
+/// Index `array` with the expression of an `Index` pair
+fn parse_index(array: TypedExpr, pair: Pair<Rule>) -> Result<TypedExpr, String> {
+    let index = parse_expr(pair.into_inner().next().unwrap())?;
+    Ok(TypedExpr::unknown(Expr::Index {
+        array: Box::new(array),
+        index: Box::new(index),
+        checked: true,
+    }))
+}
+
 fn parse_expr(pair: Pair<Rule>) -> Result<TypedExpr, String> {
     let expr = match pair.as_rule() {
         Rule::Expr => {
@@ -143,8 +236,21 @@ fn parse_expr(pair: Pair<Rule>) -> Result<TypedExpr, String> {
         Rule::Multiplicative => parse_binary(pair)?,
         Rule::Unary => return parse_unary(pair),
         Rule::Call => return parse_call(pair),
+        Rule::Conversion => parse_conversion(pair)?,
+        Rule::ArrayLiteral => Expr::Array(
+            pair.into_inner()
+                .map(parse_expr)
+                .collect::<Result<_, _>>()?,
+        ),
+        Rule::ArrayRepeat => {
+            let mut inner = pair.into_inner();
+            let value = Box::new(parse_expr(inner.next().unwrap())?);
+            let size = parse_size(inner.next().unwrap())?;
+            Expr::ArrayRepeat { value, size }
+        }
         Rule::Literal => parse_literal(pair)?,
         Rule::Int => Expr::Int(pair.as_str().parse().unwrap()),
+        Rule::Float => Expr::Float(pair.as_str().parse().unwrap()),
         Rule::Bool => Expr::Bool(pair.as_str() == "true"),
         Rule::Identifier => Expr::Var(pair.as_str().to_string()),
         Rule::Block => {
@@ -232,29 +338,41 @@ fn parse_call(pair: Pair<Rule>) -> Result<TypedExpr, String> {
 
     let mut expr = parse_expr(first)?;
 
-    // Check for CallArgs (function call)
-    for call_args in inner {
-        if call_args.as_rule() == Rule::CallArgs {
-            let args: Vec<TypedExpr> = call_args
-                .into_inner()
-                .map(|p| parse_expr(p))
-                .collect::<Result<_, _>>()?;
-
-            if let Expr::Var(name) = expr.expr {
-                expr = TypedExpr::unknown(Expr::Call { name, args });
-            } else {
-                return Err("Can only call named functions".to_string());
+    // Check for CallArgs (function call) and Index (array element)
+    for suffix in inner {
+        match suffix.as_rule() {
+            Rule::CallArgs => {
+                let args: Vec<TypedExpr> = suffix
+                    .into_inner()
+                    .map(|p| parse_expr(p))
+                    .collect::<Result<_, _>>()?;
+
+                if let Expr::Var(name) = expr.expr {
+                    expr = TypedExpr::unknown(Expr::Call { name, args });
+                } else {
+                    return Err("Can only call named functions".to_string());
+                }
             }
+            Rule::Index => expr = parse_index(expr, suffix)?,
+            _ => {}
         }
     }
 
     Ok(expr)
 }
 
+fn parse_conversion(pair: Pair<Rule>) -> Result<Expr, String> {
+    let mut inner = pair.into_inner();
+    let to = parse_type(inner.next().unwrap())?;
+    let expr = Box::new(parse_expr(inner.next().unwrap())?);
+    Ok(Expr::Cast { to, expr })
+}
+
 fn parse_literal(pair: Pair<Rule>) -> Result<Expr, String> {
     let inner = pair.into_inner().next().unwrap();
     match inner.as_rule() {
         Rule::Int => Ok(Expr::Int(inner.as_str().parse().unwrap())),
+        Rule::Float => Ok(Expr::Float(inner.as_str().parse().unwrap())),
         Rule::Bool => Ok(Expr::Bool(inner.as_str() == "true")),
         r => Err(format!("Unexpected literal rule: {:?}", r)),
     }
@@ -285,6 +403,70 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_unannotated_function() {
+        let source = "def add(a, b: int) { return a + b }";
+        let program = parse(source).unwrap();
+        if let Stmt::Function {
+            params,
+            return_type,
+            ..
+        } = &program[0]
+        {
+            assert_eq!(params[0], ("a".to_string(), Type::Var(0)));
+            assert_eq!(params[1], ("b".to_string(), Type::Int));
+            assert_eq!(*return_type, Type::Var(1));
+        } else {
+            panic!("Expected Function");
+        }
+    }
+
+    #[test]
+    fn test_parse_float_and_conversion() {
+        let source = "x: float = float(n) * 2.5";
+        let program = parse(source).unwrap();
+        if let Stmt::Assignment {
+            type_ann, value, ..
+        } = &program[0]
+        {
+            assert_eq!(*type_ann, Some(Type::Float));
+            assert_eq!(value.expr.to_string(), "(float(n) * 2.5)");
+        } else {
+            panic!("Expected Assignment");
+        }
+    }
+
+    #[test]
+    fn test_parse_arrays() {
+        let source = "a: [int; 3] = [1, 2, 3]\nb = [0.5; 4]\na[b[0]] = len(a)";
+        let program = parse(source).unwrap();
+        let Stmt::Assignment { type_ann, .. } = &program[0] else {
+            panic!("Expected Assignment");
+        };
+        assert_eq!(
+            *type_ann,
+            Some(Type::Array {
+                element: Box::new(Type::Int),
+                size: 3,
+            })
+        );
+        let Stmt::IndexAssign { target, value } = &program[2] else {
+            panic!("Expected IndexAssign");
+        };
+        assert_eq!(target.expr.to_string(), "a[b[0]]");
+        assert_eq!(value.expr.to_string(), "len(a)");
+    }
+
+    #[test]
+    fn test_parse_extern() {
+        let program = parse("extern def labs(x: int) -> int\nextern def srand(seed: int)").unwrap();
+        assert_eq!(program[0].to_string(), "extern def labs(x: int) -> int");
+        assert_eq!(program[1].to_string(), "extern def srand(seed: int) -> ()");
+
+        let err = parse("extern def labs(x) -> int").unwrap_err();
+        assert_eq!(err, "Parameter x of extern function labs needs a type");
+    }
+
     #[test]
     fn test_parse_typed_assignment() {
         let source = "x: int = 42";
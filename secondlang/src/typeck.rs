@@ -2,183 +2,464 @@
 //!
 //! This module performs:
 //! - Type checking: verifying type consistency
-//! - Type inference: deducing types for expressions without explicit annotations
+//! - Type inference: deducing types for expressions, and for parameters and
+//!   return types written without annotations
+//!
+//! A parameter or return type left out of a `def` is a type variable, solved
+//! from how the function body uses it and from the arguments of its calls.
+//! Functions are monomorphic: every call of a function must agree on its
+//! types.
+//!
+//! Arithmetic works on `int` and `float` alike, with both operands of the
+//! same type. Operands whose type nothing else pins down default to `int`.
+//!
+//! Arrays have a fixed size that is part of their type: `[int; 3]` and
+//! `[int; 4]` are different types. The built-in `len` returns the size.
+//!
+//! The built-in `print` prints an `int`, `float` or `bool` and returns unit.
 
 use std::collections::HashMap;
 
 use crate::ast::{BinaryOp, Expr, Program, Stmt, TypedExpr, UnaryOp};
-use crate::types::Type;
+use crate::types::{Substitution, Type, TypeVar};
 
 /// Type environment - maps variable names to their types
 pub type TypeEnv = HashMap<String, Type>;
 
+/// Name of the built-in function returning the length of an array
+pub const LEN: &str = "len";
+
+/// Name of the built-in function printing a value
+pub const PRINT: &str = "print";
+
+/// Functions the language provides, which programs cannot define
+const BUILTINS: [&str; 2] = [LEN, PRINT];
+
 // ANCHOR: typecheck
 /// Type check and infer types for a program
 pub fn typecheck(program: &mut Program) -> Result<(), String> {
+    let mut checker = TypeChecker::new();
     let mut env = TypeEnv::new();
 
-    // First pass: collect function signatures
-    for stmt in program.iter() {
-        if let Stmt::Function {
-            name,
-            params,
-            return_type,
-            ..
-        } = stmt
-        {
-            let param_types: Vec<Type> = params.iter().map(|(_, t)| t.clone()).collect();
-            let func_type = Type::Function {
-                params: param_types,
-                ret: Box::new(return_type.clone()),
-            };
-            env.insert(name.clone(), func_type);
+    // First pass: collect function signatures, with a fresh type variable
+    // for every type left out, and the signatures of extern functions
+    for stmt in program.iter_mut() {
+        let (name, params, return_type) = match stmt {
+            Stmt::Function {
+                name,
+                params,
+                return_type,
+                ..
+            } => {
+                let mut vars = HashMap::new();
+                for (_, param_type) in params.iter_mut() {
+                    *param_type = checker.instantiate(param_type, &mut vars);
+                }
+                *return_type = checker.instantiate(return_type, &mut vars);
+                (name, params, return_type)
+            }
+            Stmt::Extern {
+                name,
+                params,
+                return_type,
+            } => {
+                check_extern(name, params, return_type)?;
+                (name, params, return_type)
+            }
+            _ => continue,
+        };
+
+        if BUILTINS.contains(&name.as_str()) {
+            return Err(format!(
+                "{} is a built-in function and cannot be redefined",
+                name
+            ));
+        }
+
+        if env.contains_key(name) {
+            return Err(format!("Function {} is defined more than once", name));
         }
+
+        let param_types: Vec<Type> = params.iter().map(|(_, t)| t.clone()).collect();
+        let func_type = Type::Function {
+            params: param_types,
+            ret: Box::new(return_type.clone()),
+        };
+        env.insert(name.clone(), func_type);
+    }
+
+    // Second pass: type check each statement, collecting constraints
+    for stmt in program.iter_mut() {
+        checker.check_stmt(stmt, &mut env)?;
     }
 
-    // Second pass: type check each statement
+    // Arrays indexed before their type was known, then numeric operands
+    // with no type inferred yet, which are ints
+    checker.resolve_arrays()?;
+    checker.default_numeric()?;
+
+    // Third pass: replace type variables by the types inferred for them
     for stmt in program.iter_mut() {
-        typecheck_stmt(stmt, &mut env)?;
+        checker.resolve_stmt(stmt)?;
     }
 
     Ok(())
 }
 // ANCHOR_END: typecheck
 
-fn typecheck_stmt(stmt: &mut Stmt, env: &mut TypeEnv) -> Result<Type, String> {
-    match stmt {
-        Stmt::Function {
-            name: _,
-            params,
-            return_type,
-            body,
-        } => {
-            // Create local environment with parameters
-            let mut local_env = env.clone();
-            for (param_name, param_type) in params.iter() {
-                local_env.insert(param_name.clone(), param_type.clone());
-            }
+/// Check an extern function only takes and returns values C has types for
+fn check_extern(name: &str, params: &[(String, Type)], return_type: &Type) -> Result<(), String> {
+    let is_scalar = |ty: &Type| matches!(ty, Type::Int | Type::Float | Type::Bool);
 
-            // Type check body
-            let mut body_type = Type::Unit;
-            for body_stmt in body.iter_mut() {
-                body_type = typecheck_stmt(body_stmt, &mut local_env)?;
-            }
+    if let Some((param, ty)) = params.iter().find(|(_, ty)| !is_scalar(ty)) {
+        return Err(format!(
+            "Parameter {} of extern function {} cannot be {}: extern functions take int, float or bool",
+            param, name, ty
+        ));
+    }
+    if !is_scalar(return_type) && *return_type != Type::Unit {
+        return Err(format!(
+            "Extern function {} cannot return {}: extern functions return int, float, bool or nothing",
+            name, return_type
+        ));
+    }
+    Ok(())
+}
 
-            // Verify return type matches (if not Unknown)
-            if *return_type != Type::Unknown && body_type != Type::Unit {
-                let _ = return_type.unify(&body_type)?;
-            }
+/// Inference state while checking a program
+struct TypeChecker {
+    subst: Substitution,
+    /// Return type of the function being checked
+    return_type: Option<Type>,
+    /// Whether the function being checked has a `return`
+    returns: bool,
+    /// Types that must be `int` or `float`, with the code requiring it
+    numeric: Vec<(Type, String)>,
+    /// Types that must be arrays, with their element type and the code
+    /// requiring it
+    arrays: Vec<(Type, Type, String)>,
+}
 
-            Ok(Type::Unit)
+impl TypeChecker {
+    fn new() -> Self {
+        TypeChecker {
+            subst: Substitution::new(),
+            return_type: None,
+            returns: false,
+            numeric: Vec::new(),
+            arrays: Vec::new(),
         }
+    }
 
-        Stmt::Return(expr) => {
-            typecheck_expr(expr, env)?;
-            Ok(expr.ty.clone())
+    /// Require `ty` to be `int` or `float`
+    ///
+    /// A type variable is checked once inference knows more, by
+    /// `default_numeric`.
+    fn require_numeric(&mut self, ty: &Type, source: &str) -> Result<(), String> {
+        match self.subst.apply(ty) {
+            Type::Int | Type::Float => Ok(()),
+            Type::Var(_) => {
+                self.numeric.push((ty.clone(), source.to_string()));
+                Ok(())
+            }
+            other => Err(format!(
+                "Arithmetic in {} requires int or float operands, got {}",
+                source, other
+            )),
         }
+    }
 
-        Stmt::Assignment {
-            name,
-            type_ann,
-            value,
-        } => {
-            typecheck_expr(value, env)?;
-
-            let var_type = if let Some(ann) = type_ann {
-                // Explicit type annotation - check it matches
-                let _ = ann.unify(&value.ty)?;
-                ann.clone()
-            } else {
-                // Infer from value
-                value.ty.clone()
-            };
-
-            env.insert(name.clone(), var_type.clone());
-            Ok(var_type)
+    /// Require `ty` to be an array of `element`s
+    ///
+    /// A type variable is checked once inference knows more, by
+    /// `resolve_arrays`.
+    fn require_array(&mut self, ty: &Type, element: &Type, source: &str) -> Result<(), String> {
+        match self.subst.apply(ty) {
+            Type::Array {
+                element: actual, ..
+            } => self.subst.unify(&actual, element, source),
+            Type::Var(_) => {
+                self.arrays
+                    .push((ty.clone(), element.clone(), source.to_string()));
+                Ok(())
+            }
+            other => Err(format!("Expected an array in {}, got {}", source, other)),
         }
+    }
+
+    /// Check the deferred array constraints
+    ///
+    /// Solving one can make the type of another array known, so this goes
+    /// on until all are solved or none can be.
+    fn resolve_arrays(&mut self) -> Result<(), String> {
+        loop {
+            let pending = std::mem::take(&mut self.arrays);
+            let count = pending.len();
+            for (ty, element, source) in pending {
+                self.require_array(&ty, &element, &source)?;
+            }
 
-        Stmt::Expr(expr) => {
-            typecheck_expr(expr, env)?;
-            Ok(expr.ty.clone())
+            match self.arrays.first() {
+                None => return Ok(()),
+                Some((_, _, source)) if self.arrays.len() == count => {
+                    return Err(format!("Cannot infer the array type in {}", source));
+                }
+                Some(_) => {}
+            }
         }
     }
-}
 
-// ANCHOR: typecheck_expr
-fn typecheck_expr(expr: &mut TypedExpr, env: &TypeEnv) -> Result<(), String> {
-    match &mut expr.expr {
-        Expr::Int(_) => {
-            expr.ty = Type::Int;
+    /// Check the deferred numeric constraints, making the types still
+    /// unknown `int`
+    fn default_numeric(&mut self) -> Result<(), String> {
+        for (ty, source) in std::mem::take(&mut self.numeric) {
+            if let Type::Var(_) = self.subst.apply(&ty) {
+                self.subst.unify(&Type::Int, &ty, &source)?;
+            }
+            self.require_numeric(&ty, &source)?;
         }
+        Ok(())
+    }
 
-        Expr::Bool(_) => {
-            expr.ty = Type::Bool;
+    /// Replace the parser's type variables by fresh ones
+    ///
+    /// The parser numbers the types left out of each function from 0, so
+    /// every function gets its own variables here.
+    fn instantiate(&mut self, ty: &Type, vars: &mut HashMap<TypeVar, Type>) -> Type {
+        match ty {
+            Type::Var(v) => vars.entry(*v).or_insert_with(|| self.subst.fresh()).clone(),
+            _ => ty.clone(),
         }
+    }
+
+    fn check_stmt(&mut self, stmt: &mut Stmt, env: &mut TypeEnv) -> Result<Type, String> {
+        match stmt {
+            Stmt::Function {
+                name,
+                params,
+                return_type,
+                body,
+            } => {
+                // Create local environment with the functions and parameters
+                let mut local_env: TypeEnv = env
+                    .iter()
+                    .filter(|(_, t)| matches!(t, Type::Function { .. }))
+                    .map(|(n, t)| (n.clone(), t.clone()))
+                    .collect();
+                for (param_name, param_type) in params.iter() {
+                    local_env.insert(param_name.clone(), param_type.clone());
+                }
+
+                let outer_return_type = self.return_type.replace(return_type.clone());
+                let outer_returns = std::mem::replace(&mut self.returns, false);
+
+                // Type check body
+                let mut body_type = Type::Unit;
+                for body_stmt in body.iter_mut() {
+                    body_type = self.check_stmt(body_stmt, &mut local_env)?;
+                }
 
-        Expr::Var(name) => {
-            if let Some(ty) = env.get(name) {
-                expr.ty = ty.clone();
-            } else {
-                return Err(format!("Undefined variable: {}", name));
+                // The value of the last statement is returned too. A body
+                // with no value and no `return` returns unit.
+                let source = format!("the body of function {}", name);
+                if self.subst.apply(&body_type) != Type::Unit {
+                    self.subst.unify(return_type, &body_type, &source)?;
+                } else if !self.returns {
+                    self.subst.unify(return_type, &Type::Unit, &source)?;
+                }
+
+                self.return_type = outer_return_type;
+                self.returns = outer_returns;
+                Ok(Type::Unit)
             }
-        }
 
-        Expr::Unary { op, expr: inner } => {
-            typecheck_expr(inner, env)?;
-            match op {
-                UnaryOp::Neg => {
-                    if inner.ty != Type::Int {
-                        return Err(format!("Cannot negate non-integer type: {}", inner.ty));
-                    }
-                    expr.ty = Type::Int;
+            // Registered in the first pass of `typecheck`
+            Stmt::Extern { .. } => Ok(Type::Unit),
+
+            Stmt::Return(expr) => {
+                self.check_expr(expr, env)?;
+                if let Some(return_type) = self.return_type.clone() {
+                    let source = format!("`return {}`", expr.expr);
+                    self.subst.unify(&return_type, &expr.ty, &source)?;
+                    self.returns = true;
                 }
-                UnaryOp::Not => {
-                    if inner.ty != Type::Bool {
-                        return Err(format!("Cannot negate non-boolean type: {}", inner.ty));
+                Ok(expr.ty.clone())
+            }
+
+            Stmt::Assignment {
+                name,
+                type_ann,
+                value,
+            } => {
+                self.check_expr(value, env)?;
+
+                let source = format!("`{} = {}`", name, value.expr);
+                if let Some(ann) = type_ann {
+                    // Explicit type annotation - check it matches
+                    self.subst.unify(ann, &value.ty, &source)?;
+                }
+
+                match env.get(name) {
+                    // A variable keeps the type of its first assignment
+                    Some(var_type) => {
+                        let var_type = var_type.clone();
+                        self.subst.unify(&var_type, &value.ty, &source)?;
+                    }
+                    // Infer from value
+                    None => {
+                        env.insert(name.clone(), value.ty.clone());
                     }
-                    expr.ty = Type::Bool;
                 }
+
+                Ok(value.ty.clone())
+            }
+
+            Stmt::IndexAssign { target, value } => {
+                self.check_expr(target, env)?;
+                self.check_expr(value, env)?;
+
+                let source = format!("`{} = {}`", target.expr, value.expr);
+                self.subst.unify(&target.ty, &value.ty, &source)?;
+                Ok(value.ty.clone())
+            }
+
+            Stmt::Expr(expr) => {
+                self.check_expr(expr, env)?;
+                Ok(expr.ty.clone())
             }
         }
+    }
 
-        Expr::Binary { op, left, right } => {
-            typecheck_expr(left, env)?;
-            typecheck_expr(right, env)?;
+    // ANCHOR: typecheck_expr
+    fn check_expr(&mut self, expr: &mut TypedExpr, env: &TypeEnv) -> Result<(), String> {
+        let source = format!("`{}`", expr.expr);
 
-            match op {
-                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
-                    if left.ty != Type::Int || right.ty != Type::Int {
-                        return Err(format!(
-                            "Arithmetic operation requires int operands, got {} and {}",
-                            left.ty, right.ty
-                        ));
-                    }
-                    expr.ty = Type::Int;
+        match &mut expr.expr {
+            Expr::Int(_) => {
+                expr.ty = Type::Int;
+            }
+
+            Expr::Float(_) => {
+                expr.ty = Type::Float;
+            }
+
+            Expr::Bool(_) => {
+                expr.ty = Type::Bool;
+            }
+
+            Expr::Var(name) => {
+                if let Some(ty) = env.get(name) {
+                    expr.ty = ty.clone();
+                } else {
+                    return Err(format!("Undefined variable: {}", name));
                 }
-                BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge => {
-                    if left.ty != Type::Int || right.ty != Type::Int {
-                        return Err(format!(
-                            "Comparison requires int operands, got {} and {}",
-                            left.ty, right.ty
-                        ));
+            }
+
+            Expr::Unary { op, expr: inner } => {
+                self.check_expr(inner, env)?;
+                match op {
+                    UnaryOp::Neg => self.require_numeric(&inner.ty, &source)?,
+                    UnaryOp::Not => self.subst.unify(&Type::Bool, &inner.ty, &source)?,
+                }
+                expr.ty = inner.ty.clone();
+            }
+
+            Expr::Binary { op, left, right } => {
+                self.check_expr(left, env)?;
+                self.check_expr(right, env)?;
+
+                match op {
+                    BinaryOp::Add
+                    | BinaryOp::Sub
+                    | BinaryOp::Mul
+                    | BinaryOp::Div
+                    | BinaryOp::Mod => {
+                        self.require_numeric(&left.ty, &source)?;
+                        self.subst.unify(&left.ty, &right.ty, &source)?;
+                        expr.ty = left.ty.clone();
+                    }
+                    BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge => {
+                        self.require_numeric(&left.ty, &source)?;
+                        self.subst.unify(&left.ty, &right.ty, &source)?;
+                        expr.ty = Type::Bool;
+                    }
+                    BinaryOp::Eq | BinaryOp::Ne => {
+                        self.subst.unify(&left.ty, &right.ty, &source)?;
+                        expr.ty = Type::Bool;
                     }
-                    expr.ty = Type::Bool;
                 }
-                BinaryOp::Eq | BinaryOp::Ne => {
-                    let _ = left.ty.unify(&right.ty)?;
-                    expr.ty = Type::Bool;
+            }
+
+            Expr::Cast { to, expr: inner } => {
+                self.check_expr(inner, env)?;
+                self.require_numeric(&inner.ty, &source)?;
+                expr.ty = to.clone();
+            }
+
+            Expr::Array(elements) => {
+                let element = self.subst.fresh();
+                let elements_source = format!("the elements of {}", source);
+                for e in elements.iter_mut() {
+                    self.check_expr(e, env)?;
+                    self.subst.unify(&element, &e.ty, &elements_source)?;
                 }
+                expr.ty = Type::Array {
+                    element: Box::new(element),
+                    size: elements.len(),
+                };
+            }
+
+            Expr::ArrayRepeat { value, size } => {
+                self.check_expr(value, env)?;
+                expr.ty = Type::Array {
+                    element: Box::new(value.ty.clone()),
+                    size: *size,
+                };
+            }
+
+            Expr::Index { array, index, .. } => {
+                self.check_expr(array, env)?;
+                self.check_expr(index, env)?;
+                let index_source = format!("the index of {}", source);
+                self.subst.unify(&Type::Int, &index.ty, &index_source)?;
+
+                let element = self.subst.fresh();
+                self.require_array(&array.ty, &element, &source)?;
+                expr.ty = element;
+            }
+
+            Expr::Call { name, args } if name.as_str() == LEN => {
+                if args.len() != 1 {
+                    return Err(format!("{} expects 1 argument, got {}", LEN, args.len()));
+                }
+                let array = &mut args[0];
+                self.check_expr(array, env)?;
+                let element = self.subst.fresh();
+                self.require_array(&array.ty, &element, &source)?;
+                expr.ty = Type::Int;
+            }
+
+            // What can be printed is checked in `resolve_expr`, once the
+            // type of the argument is known
+            Expr::Call { name, args } if name.as_str() == PRINT => {
+                if args.len() != 1 {
+                    return Err(format!("{} expects 1 argument, got {}", PRINT, args.len()));
+                }
+                self.check_expr(&mut args[0], env)?;
+                expr.ty = Type::Unit;
             }
-        }
 
-        Expr::Call { name, args } => {
-            // Look up function type
-            let func_type = env
-                .get(name)
-                .ok_or_else(|| format!("Undefined function: {}", name))?
-                .clone();
+            Expr::Call { name, args } => {
+                // Look up function type
+                let func_type = env
+                    .get(name)
+                    .ok_or_else(|| format!("Undefined function: {}", name))?
+                    .clone();
+
+                let Type::Function { params, ret } = func_type else {
+                    return Err(format!("{} is not a function", name));
+                };
 
-            if let Type::Function { params, ret } = func_type {
                 // Check argument count
                 if args.len() != params.len() {
                     return Err(format!(
@@ -190,71 +471,164 @@ fn typecheck_expr(expr: &mut TypedExpr, env: &TypeEnv) -> Result<(), String> {
                 }
 
                 // Type check each argument
-                for (arg, param_type) in args.iter_mut().zip(params.iter()) {
-                    typecheck_expr(arg, env)?;
-                    let _ = arg.ty.unify(param_type)?;
+                for (i, (arg, param_type)) in args.iter_mut().zip(params.iter()).enumerate() {
+                    self.check_expr(arg, env)?;
+                    let arg_source = format!("argument {} of {}", i + 1, source);
+                    self.subst.unify(param_type, &arg.ty, &arg_source)?;
                 }
 
                 expr.ty = *ret;
-            } else {
-                return Err(format!("{} is not a function", name));
             }
-        }
 
-        Expr::If {
-            cond,
-            then_branch,
-            else_branch,
-        } => {
-            typecheck_expr(cond, env)?;
-            if cond.ty != Type::Bool {
-                return Err(format!("If condition must be bool, got {}", cond.ty));
-            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.check_expr(cond, env)?;
+                let cond_source = format!("the condition of {}", source);
+                self.subst.unify(&Type::Bool, &cond.ty, &cond_source)?;
+
+                // Type check branches
+                let mut then_env = env.clone();
+                let mut then_type = Type::Unit;
+                for stmt in then_branch.iter_mut() {
+                    then_type = self.check_stmt(stmt, &mut then_env)?;
+                }
+
+                let mut else_env = env.clone();
+                let mut else_type = Type::Unit;
+                for stmt in else_branch.iter_mut() {
+                    else_type = self.check_stmt(stmt, &mut else_env)?;
+                }
 
-            // Type check branches
-            let mut then_env = env.clone();
-            let mut then_type = Type::Unit;
-            for stmt in then_branch.iter_mut() {
-                then_type = typecheck_stmt(stmt, &mut then_env)?;
+                // Branches must have same type
+                let branches_source = format!("the branches of {}", source);
+                self.subst.unify(&then_type, &else_type, &branches_source)?;
+                expr.ty = then_type;
             }
 
-            let mut else_env = env.clone();
-            let mut else_type = Type::Unit;
-            for stmt in else_branch.iter_mut() {
-                else_type = typecheck_stmt(stmt, &mut else_env)?;
+            Expr::While { cond, body } => {
+                self.check_expr(cond, env)?;
+                let cond_source = format!("the condition of {}", source);
+                self.subst.unify(&Type::Bool, &cond.ty, &cond_source)?;
+
+                let mut body_env = env.clone();
+                for stmt in body.iter_mut() {
+                    self.check_stmt(stmt, &mut body_env)?;
+                }
+
+                expr.ty = Type::Unit;
             }
 
-            // Branches must have same type
-            expr.ty = then_type.unify(&else_type)?;
+            Expr::Block(stmts) => {
+                let mut block_env = env.clone();
+                let mut last_type = Type::Unit;
+                for stmt in stmts.iter_mut() {
+                    last_type = self.check_stmt(stmt, &mut block_env)?;
+                }
+                expr.ty = last_type;
+            }
         }
 
-        Expr::While { cond, body } => {
-            typecheck_expr(cond, env)?;
-            if cond.ty != Type::Bool {
-                return Err(format!("While condition must be bool, got {}", cond.ty));
+        Ok(())
+    }
+    // ANCHOR_END: typecheck_expr
+
+    /// Write the inferred types into a statement
+    fn resolve_stmt(&self, stmt: &mut Stmt) -> Result<(), String> {
+        match stmt {
+            Stmt::Function {
+                name,
+                params,
+                return_type,
+                body,
+            } => {
+                for (param_name, param_type) in params.iter_mut() {
+                    *param_type = self.subst.apply(param_type);
+                    if !param_type.is_resolved() {
+                        return Err(format!(
+                            "Cannot infer the type of parameter {} of function {}",
+                            param_name, name
+                        ));
+                    }
+                }
+                *return_type = self.subst.apply(return_type);
+                if !return_type.is_resolved() {
+                    return Err(format!("Cannot infer the return type of function {}", name));
+                }
+                body.iter_mut().try_for_each(|s| self.resolve_stmt(s))
             }
-
-            let mut body_env = env.clone();
-            for stmt in body.iter_mut() {
-                typecheck_stmt(stmt, &mut body_env)?;
+            Stmt::Extern { .. } => Ok(()),
+            Stmt::Return(expr) | Stmt::Expr(expr) => self.resolve_expr(expr),
+            Stmt::Assignment { value, .. } => self.resolve_expr(value),
+            Stmt::IndexAssign { target, value } => {
+                self.resolve_expr(target)?;
+                self.resolve_expr(value)
             }
+        }
+    }
 
-            expr.ty = Type::Unit;
+    /// Write the inferred types into an expression and its subexpressions
+    fn resolve_expr(&self, expr: &mut TypedExpr) -> Result<(), String> {
+        expr.ty = self.subst.apply(&expr.ty);
+        if !expr.ty.is_resolved() {
+            return Err(format!("Cannot infer the type of `{}`", expr.expr));
         }
 
-        Expr::Block(stmts) => {
-            let mut block_env = env.clone();
-            let mut last_type = Type::Unit;
-            for stmt in stmts.iter_mut() {
-                last_type = typecheck_stmt(stmt, &mut block_env)?;
+        match &mut expr.expr {
+            Expr::Int(_) | Expr::Float(_) | Expr::Bool(_) | Expr::Var(_) => Ok(()),
+            Expr::Unary { expr: inner, .. } | Expr::Cast { expr: inner, .. } => {
+                self.resolve_expr(inner)
+            }
+            Expr::Binary { op, left, right } => {
+                self.resolve_expr(left)?;
+                self.resolve_expr(right)?;
+                match (*op, &left.ty) {
+                    (BinaryOp::Eq | BinaryOp::Ne, Type::Array { .. }) => {
+                        Err(format!("Arrays cannot be compared with {}", op))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            Expr::Array(elements) => elements.iter_mut().try_for_each(|e| self.resolve_expr(e)),
+            Expr::ArrayRepeat { value, .. } => self.resolve_expr(value),
+            Expr::Index { array, index, .. } => {
+                self.resolve_expr(array)?;
+                self.resolve_expr(index)
+            }
+            Expr::Call { name, args } => {
+                args.iter_mut().try_for_each(|a| self.resolve_expr(a))?;
+                match &args[..] {
+                    [arg] if name.as_str() == PRINT => match arg.ty {
+                        Type::Int | Type::Float | Type::Bool => Ok(()),
+                        _ => Err(format!(
+                            "Cannot print {}: {} takes an int, float or bool",
+                            arg.ty, PRINT
+                        )),
+                    },
+                    _ => Ok(()),
+                }
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr(cond)?;
+                then_branch
+                    .iter_mut()
+                    .chain(else_branch.iter_mut())
+                    .try_for_each(|s| self.resolve_stmt(s))
             }
-            expr.ty = last_type;
+            Expr::While { cond, body } => {
+                self.resolve_expr(cond)?;
+                body.iter_mut().try_for_each(|s| self.resolve_stmt(s))
+            }
+            Expr::Block(stmts) => stmts.iter_mut().try_for_each(|s| self.resolve_stmt(s)),
         }
     }
-
-    Ok(())
 }
-// ANCHOR_END: typecheck_expr
 
 #[cfg(test)]
 mod tests {
@@ -314,6 +688,232 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_infer_unannotated_function() {
+        let source = r#"
+            def inc(x) {
+                return x + 1
+            }
+            inc(41)
+        "#;
+        let program = typecheck_source(source).unwrap();
+        if let Stmt::Function {
+            params,
+            return_type,
+            ..
+        } = &program[0]
+        {
+            assert_eq!(params[0].1, Type::Int);
+            assert_eq!(*return_type, Type::Int);
+        } else {
+            panic!("Expected Function");
+        }
+    }
+
+    #[test]
+    fn test_infer_from_call_site() {
+        let source = r#"
+            def same(a, b) {
+                return a == b
+            }
+            same(true, false)
+        "#;
+        let program = typecheck_source(source).unwrap();
+        if let Stmt::Function {
+            params,
+            return_type,
+            ..
+        } = &program[0]
+        {
+            assert_eq!(params[0].1, Type::Bool);
+            assert_eq!(params[1].1, Type::Bool);
+            assert_eq!(*return_type, Type::Bool);
+        } else {
+            panic!("Expected Function");
+        }
+    }
+
+    #[test]
+    fn test_infer_return_type_mismatch() {
+        // The return type is inferred as bool, so the call cannot be added
+        let source = r#"
+            def positive(n: int) {
+                return n > 0
+            }
+            positive(1) + 1
+        "#;
+        assert!(typecheck_source(source).is_err());
+    }
+
+    #[test]
+    fn test_infer_error_names_both_sources() {
+        let source = r#"
+            def inc(x) {
+                return x + 1
+            }
+            inc(true)
+        "#;
+        let err = typecheck_source(source).unwrap_err();
+        assert!(err.contains("argument 1 of `inc(true)`"), "{}", err);
+        assert!(err.contains("inferred from `(x + 1)`"), "{}", err);
+    }
+
+    #[test]
+    fn test_infer_unused_parameter_fails() {
+        let source = r#"
+            def first(a, b) {
+                return a
+            }
+            first(1, 2)
+            def ignore(x) {
+                return 0
+            }
+        "#;
+        let err = typecheck_source(source).unwrap_err();
+        assert_eq!(
+            err,
+            "Cannot infer the type of parameter x of function ignore"
+        );
+    }
+
+    #[test]
+    fn test_typecheck_float_arithmetic() {
+        let program = typecheck_source("x = 1.5 * 2.0\nx < 3.0\nint(x) + 1").unwrap();
+        let types: Vec<_> = program
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Assignment { value: e, .. } | Stmt::Expr(e) => e.ty.clone(),
+                _ => panic!("Expected expression"),
+            })
+            .collect();
+        assert_eq!(types, vec![Type::Float, Type::Bool, Type::Int]);
+    }
+
+    #[test]
+    fn test_typecheck_mixed_numeric_error() {
+        let err = typecheck_source("1 + 2.5").unwrap_err();
+        assert_eq!(err, "Type mismatch in `(1 + 2.5)`: expected int, got float");
+        let err = typecheck_source("int(true)").unwrap_err();
+        assert!(err.contains("requires int or float"), "{}", err);
+    }
+
+    #[test]
+    fn test_infer_numeric_parameters() {
+        // `half` gets its float from the call, `twice` defaults to int
+        let source = r#"
+            def half(x) {
+                return x / 2.0
+            }
+            def twice(n) {
+                return n + n
+            }
+            half(3.0)
+        "#;
+        let program = typecheck_source(source).unwrap();
+        let params: Vec<_> = program
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Function { params, .. } => Some(params[0].1.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(params, vec![Type::Float, Type::Int]);
+    }
+
+    #[test]
+    fn test_typecheck_arrays() {
+        let source = "a = [1, 2, 3]\na[0] = len(a)\nb = [a; 2]\nb[1][2] > 0";
+        let program = typecheck_source(source).unwrap();
+        let Stmt::Assignment { value, .. } = &program[2] else {
+            panic!("Expected Assignment");
+        };
+        assert_eq!(value.ty.to_string(), "[[int; 3]; 2]");
+        if let Stmt::Expr(expr) = &program[3] {
+            assert_eq!(expr.ty, Type::Bool);
+        }
+    }
+
+    #[test]
+    fn test_infer_array_parameter() {
+        let source = r#"
+            def first(xs) {
+                return xs[0]
+            }
+            first([true, false])
+        "#;
+        let program = typecheck_source(source).unwrap();
+        if let Stmt::Function { params, .. } = &program[0] {
+            assert_eq!(params[0].1.to_string(), "[bool; 2]");
+        }
+    }
+
+    #[test]
+    fn test_typecheck_array_errors() {
+        let err = typecheck_source("a = [1, 2]\na = [1, 2, 3]").unwrap_err();
+        assert_eq!(
+            err,
+            "Type mismatch in `a = [1, 2, 3]`: expected [int; 2], got [int; 3]"
+        );
+        let err = typecheck_source("[1, true]").unwrap_err();
+        assert!(err.contains("the elements of `[1, true]`"), "{}", err);
+        let err = typecheck_source("x = 5\nx[0]").unwrap_err();
+        assert_eq!(err, "Expected an array in `x[0]`, got int");
+        let err = typecheck_source("def first(xs) { return xs[0] }").unwrap_err();
+        assert_eq!(err, "Cannot infer the array type in `xs[0]`");
+        assert!(typecheck_source("[1] == [1]").is_err());
+        assert!(typecheck_source("def len(x: int) -> int { return x }").is_err());
+    }
+
+    #[test]
+    fn test_typecheck_print() {
+        let program = typecheck_source("x = 1.5\nprint(x)").unwrap();
+        if let Stmt::Expr(expr) = &program[1] {
+            assert_eq!(expr.ty, Type::Unit);
+        }
+
+        let err = typecheck_source("print([1, 2])").unwrap_err();
+        assert_eq!(
+            err,
+            "Cannot print [int; 2]: print takes an int, float or bool"
+        );
+        assert!(typecheck_source("print(1, 2)").is_err());
+        assert!(typecheck_source("def print(x: int) -> int { return x }").is_err());
+    }
+
+    #[test]
+    fn test_typecheck_extern() {
+        let program = typecheck_source("extern def labs(x: int) -> int\nlabs(0 - 3) + 1").unwrap();
+        if let Stmt::Expr(expr) = &program[1] {
+            assert_eq!(expr.ty, Type::Int);
+        }
+
+        assert!(typecheck_source("extern def labs(x: int) -> int\nlabs(1.5)").is_err());
+        let err = typecheck_source("extern def sum(xs: [int; 2]) -> int").unwrap_err();
+        assert!(
+            err.starts_with("Parameter xs of extern function sum cannot be [int; 2]"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_typecheck_duplicate_function() {
+        let source = r#"
+            def f(x: int) -> int {
+                return x
+            }
+            def f(x: float) -> float {
+                return x
+            }
+        "#;
+        let err = typecheck_source(source).unwrap_err();
+        assert_eq!(err, "Function f is defined more than once");
+
+        let err = typecheck_source("extern def labs(x: int) -> int\ndef labs(x) { return x }")
+            .unwrap_err();
+        assert_eq!(err, "Function labs is defined more than once");
+    }
+
     #[test]
     fn test_typecheck_fibonacci() {
         let source = r#"
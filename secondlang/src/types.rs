@@ -2,50 +2,161 @@
 //!
 //! This module defines the type system and type inference for Secondlang.
 //! Types enable LLVM code generation by providing static type information.
+//!
+//! Inference works Hindley–Milner style: a type not written in the source is
+//! a type variable, constraints between types are solved by unification, and
+//! the solutions are collected in a `Substitution`.
 
+use std::collections::HashMap;
 use std::fmt;
 
+/// Identifier of a type variable
+pub type TypeVar = u32;
+
 // ANCHOR: type_enum
 /// Types in our language
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Type {
     /// Integer type (64-bit signed)
     Int,
+    /// Floating-point type (64-bit IEEE 754)
+    Float,
     /// Boolean type
     Bool,
+    /// Fixed-size array: `[element; size]`
+    Array { element: Box<Type>, size: usize },
     /// Function type: (param_types) -> return_type
     Function { params: Vec<Type>, ret: Box<Type> },
     /// Unit type (for statements with no value)
     Unit,
-    /// Unknown type (for type inference)
-    Unknown,
+    /// Type variable, standing for a type inference has not worked out yet
+    Var(TypeVar),
 }
 // ANCHOR_END: type_enum
 
 impl Type {
-    /// Check if this type is fully resolved (no Unknown types)
+    /// Check if this type is fully resolved (no type variables)
     pub fn is_resolved(&self) -> bool {
         match self {
-            Type::Int | Type::Bool | Type::Unit => true,
-            Type::Unknown => false,
+            Type::Int | Type::Float | Type::Bool | Type::Unit => true,
+            Type::Var(_) => false,
+            Type::Array { element, .. } => element.is_resolved(),
             Type::Function { params, ret } => {
                 params.iter().all(|t| t.is_resolved()) && ret.is_resolved()
             }
         }
     }
 
+    /// Whether the type variable `var` appears in this type
+    fn contains(&self, var: TypeVar) -> bool {
+        match self {
+            Type::Var(v) => *v == var,
+            Type::Array { element, .. } => element.contains(var),
+            Type::Function { params, ret } => {
+                params.iter().any(|t| t.contains(var)) || ret.contains(var)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Solutions found for type variables so far
+#[derive(Debug, Clone, Default)]
+pub struct Substitution {
+    types: HashMap<TypeVar, Type>,
+    /// Where each type variable got its solution, for error messages
+    sources: HashMap<TypeVar, String>,
+    next: TypeVar,
+}
+
+impl Substitution {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A type variable not used before
+    pub fn fresh(&mut self) -> Type {
+        let var = self.next;
+        self.next += 1;
+        Type::Var(var)
+    }
+
+    /// Replace every solved type variable in `ty` by its solution
+    pub fn apply(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(v) => match self.types.get(v) {
+                Some(t) => self.apply(t),
+                None => ty.clone(),
+            },
+            Type::Array { element, size } => Type::Array {
+                element: Box::new(self.apply(element)),
+                size: *size,
+            },
+            Type::Function { params, ret } => Type::Function {
+                params: params.iter().map(|t| self.apply(t)).collect(),
+                ret: Box::new(self.apply(ret)),
+            },
+            _ => ty.clone(),
+        }
+    }
+
     // ANCHOR: unify
-    /// Try to unify this type with another type
-    /// Returns the unified type if successful, or an error message
-    pub fn unify(&self, other: &Type) -> Result<Type, String> {
-        match (self, other) {
+    /// Constrain two types to be equal, solving type variables as needed
+    ///
+    /// `source` describes the code that requires the types to be equal. When
+    /// they cannot be, the error names it along with the code that gave the
+    /// conflicting type variables their solutions.
+    pub fn unify(&mut self, expected: &Type, actual: &Type, source: &str) -> Result<(), String> {
+        if self.unify_types(expected, actual, source) {
+            return Ok(());
+        }
+
+        let mut message = format!(
+            "Type mismatch in {}: expected {}, got {}",
+            source,
+            self.apply(expected),
+            self.apply(actual)
+        );
+        for ty in [expected, actual] {
+            if let Some(origin) = self.source(ty) {
+                message.push_str(&format!(" ({} inferred from {})", self.apply(ty), origin));
+            }
+        }
+        Err(message)
+    }
+
+    fn unify_types(&mut self, a: &Type, b: &Type, source: &str) -> bool {
+        match (self.resolve(a), self.resolve(b)) {
             // Same types unify
-            (Type::Int, Type::Int) => Ok(Type::Int),
-            (Type::Bool, Type::Bool) => Ok(Type::Bool),
-            (Type::Unit, Type::Unit) => Ok(Type::Unit),
+            (Type::Int, Type::Int)
+            | (Type::Float, Type::Float)
+            | (Type::Bool, Type::Bool)
+            | (Type::Unit, Type::Unit) => true,
 
-            // Unknown can unify with anything
-            (Type::Unknown, t) | (t, Type::Unknown) => Ok(t.clone()),
+            (Type::Var(x), Type::Var(y)) if x == y => true,
+
+            // A type variable is solved by the other type, unless that
+            // would make an infinite type, even through solved variables
+            (Type::Var(v), t) | (t, Type::Var(v)) => {
+                if self.apply(&t).contains(v) {
+                    return false;
+                }
+                self.types.insert(v, t);
+                self.sources.insert(v, source.to_string());
+                true
+            }
+
+            // Arrays must have the same size and element type
+            (
+                Type::Array {
+                    element: e1,
+                    size: s1,
+                },
+                Type::Array {
+                    element: e2,
+                    size: s2,
+                },
+            ) if s1 == s2 => self.unify_types(&e1, &e2, source),
 
             // Function types must have compatible signatures
             (
@@ -58,32 +169,53 @@ impl Type {
                     ret: r2,
                 },
             ) if p1.len() == p2.len() => {
-                let params: Result<Vec<_>, _> =
-                    p1.iter().zip(p2.iter()).map(|(a, b)| a.unify(b)).collect();
-                let ret = r1.unify(r2)?;
-                Ok(Type::Function {
-                    params: params?,
-                    ret: Box::new(ret),
-                })
+                p1.iter()
+                    .zip(p2.iter())
+                    .all(|(a, b)| self.unify_types(a, b, source))
+                    && self.unify_types(&r1, &r2, source)
             }
 
             // Type mismatch
-            _ => Err(format!(
-                "Type mismatch: expected {:?}, got {:?}",
-                self, other
-            )),
+            _ => false,
         }
     }
     // ANCHOR_END: unify
+
+    /// Follow solved type variables until reaching a type that is not one
+    fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(v) => match self.types.get(v) {
+                Some(t) => self.resolve(t),
+                None => ty.clone(),
+            },
+            _ => ty.clone(),
+        }
+    }
+
+    /// The code that solved the last type variable `ty` leads to
+    fn source(&self, ty: &Type) -> Option<&str> {
+        let mut source = None;
+        let mut ty = ty;
+        while let Type::Var(v) = ty {
+            let Some(next) = self.types.get(v) else {
+                break;
+            };
+            source = self.sources.get(v).map(String::as_str);
+            ty = next;
+        }
+        source
+    }
 }
 
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
             Type::Bool => write!(f, "bool"),
             Type::Unit => write!(f, "()"),
-            Type::Unknown => write!(f, "?"),
+            Type::Var(v) => write!(f, "'t{}", v),
+            Type::Array { element, size } => write!(f, "[{}; {}]", element, size),
             Type::Function { params, ret } => {
                 let params_str: Vec<_> = params.iter().map(|t| t.to_string()).collect();
                 write!(f, "({}) -> {}", params_str.join(", "), ret)
@@ -98,18 +230,104 @@ mod tests {
 
     #[test]
     fn test_unify_same_types() {
-        assert_eq!(Type::Int.unify(&Type::Int).unwrap(), Type::Int);
-        assert_eq!(Type::Bool.unify(&Type::Bool).unwrap(), Type::Bool);
+        let mut subst = Substitution::new();
+        subst.unify(&Type::Int, &Type::Int, "test").unwrap();
+        subst.unify(&Type::Bool, &Type::Bool, "test").unwrap();
+    }
+
+    #[test]
+    fn test_unify_var() {
+        let mut subst = Substitution::new();
+        let a = subst.fresh();
+        let b = subst.fresh();
+        subst.unify(&a, &b, "test").unwrap();
+        assert_eq!(subst.apply(&a), b);
+
+        subst.unify(&Type::Int, &b, "test").unwrap();
+        assert_eq!(subst.apply(&a), Type::Int);
+        assert!(subst.apply(&b).is_resolved());
     }
 
     #[test]
-    fn test_unify_unknown() {
-        assert_eq!(Type::Unknown.unify(&Type::Int).unwrap(), Type::Int);
-        assert_eq!(Type::Int.unify(&Type::Unknown).unwrap(), Type::Int);
+    fn test_unify_function() {
+        let mut subst = Substitution::new();
+        let a = subst.fresh();
+        let f = Type::Function {
+            params: vec![a.clone()],
+            ret: Box::new(Type::Bool),
+        };
+        let g = Type::Function {
+            params: vec![Type::Int],
+            ret: Box::new(subst.fresh()),
+        };
+        subst.unify(&f, &g, "test").unwrap();
+        assert_eq!(
+            subst.apply(&g),
+            Type::Function {
+                params: vec![Type::Int],
+                ret: Box::new(Type::Bool),
+            }
+        );
+    }
+
+    #[test]
+    fn test_unify_arrays() {
+        let mut subst = Substitution::new();
+        let a = subst.fresh();
+        let array = |element, size| Type::Array {
+            element: Box::new(element),
+            size,
+        };
+        assert!(subst
+            .unify(&array(a.clone(), 3), &array(Type::Int, 3), "test")
+            .is_ok());
+        assert_eq!(subst.apply(&a), Type::Int);
+        assert!(subst
+            .unify(&array(Type::Int, 3), &array(Type::Int, 4), "test")
+            .is_err());
     }
 
     #[test]
     fn test_unify_mismatch() {
-        assert!(Type::Int.unify(&Type::Bool).is_err());
+        let mut subst = Substitution::new();
+        assert!(subst.unify(&Type::Int, &Type::Bool, "test").is_err());
+    }
+
+    #[test]
+    fn test_unify_infinite_type() {
+        let mut subst = Substitution::new();
+        let a = subst.fresh();
+        let f = Type::Function {
+            params: vec![a.clone()],
+            ret: Box::new(Type::Int),
+        };
+        assert!(subst.unify(&a, &f, "test").is_err());
+    }
+
+    #[test]
+    fn test_unify_infinite_type_through_solved_var() {
+        let mut subst = Substitution::new();
+        let a = subst.fresh();
+        let b = subst.fresh();
+        let array = |element: &Type| Type::Array {
+            element: Box::new(element.clone()),
+            size: 2,
+        };
+        subst.unify(&a, &array(&b), "test").unwrap();
+        // b := [a; 2] would make b = [[b; 2]; 2]
+        assert!(subst.unify(&b, &array(&a), "test").is_err());
+        assert_eq!(subst.apply(&a), array(&b));
+    }
+
+    #[test]
+    fn test_mismatch_names_both_sources() {
+        let mut subst = Substitution::new();
+        let a = subst.fresh();
+        subst.unify(&a, &Type::Int, "`(x + 1)`").unwrap();
+        let err = subst.unify(&a, &Type::Bool, "`f(true)`").unwrap_err();
+        assert_eq!(
+            err,
+            "Type mismatch in `f(true)`: expected int, got bool (int inferred from `(x + 1)`)"
+        );
     }
 }
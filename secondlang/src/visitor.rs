@@ -10,6 +10,7 @@
 //! - `AlgebraicSimplifier`: Apply algebraic identities (x + 0 = x, etc.)
 
 use crate::ast::{BinaryOp, Expr, Stmt, TypedExpr, UnaryOp};
+use crate::types::Type;
 
 // ANCHOR: expr_visitor
 /// Visitor trait for traversing typed expressions
@@ -21,10 +22,19 @@ pub trait ExprVisitor {
     fn visit_expr(&mut self, expr: &TypedExpr) -> TypedExpr {
         let new_expr = match &expr.expr {
             Expr::Int(n) => self.visit_int(*n),
+            Expr::Float(x) => self.visit_float(*x),
             Expr::Bool(b) => self.visit_bool(*b),
             Expr::Var(name) => self.visit_var(name),
             Expr::Unary { op, expr: inner } => self.visit_unary(*op, inner),
             Expr::Binary { op, left, right } => self.visit_binary(*op, left, right),
+            Expr::Cast { to, expr: inner } => self.visit_cast(to, inner),
+            Expr::Array(elements) => self.visit_array(elements),
+            Expr::ArrayRepeat { value, size } => self.visit_array_repeat(value, *size),
+            Expr::Index {
+                array,
+                index,
+                checked,
+            } => self.visit_index(array, index, *checked),
             Expr::Call { name, args } => self.visit_call(name, args),
             Expr::If {
                 cond,
@@ -44,6 +54,10 @@ pub trait ExprVisitor {
         Expr::Int(n)
     }
 
+    fn visit_float(&mut self, x: f64) -> Expr {
+        Expr::Float(x)
+    }
+
     fn visit_bool(&mut self, b: bool) -> Expr {
         Expr::Bool(b)
     }
@@ -70,6 +84,36 @@ pub trait ExprVisitor {
         }
     }
 
+    fn visit_cast(&mut self, to: &Type, expr: &TypedExpr) -> Expr {
+        let visited = self.visit_expr(expr);
+        Expr::Cast {
+            to: to.clone(),
+            expr: Box::new(visited),
+        }
+    }
+
+    fn visit_array(&mut self, elements: &[TypedExpr]) -> Expr {
+        Expr::Array(elements.iter().map(|e| self.visit_expr(e)).collect())
+    }
+
+    fn visit_array_repeat(&mut self, value: &TypedExpr, size: usize) -> Expr {
+        let visited = self.visit_expr(value);
+        Expr::ArrayRepeat {
+            value: Box::new(visited),
+            size,
+        }
+    }
+
+    fn visit_index(&mut self, array: &TypedExpr, index: &TypedExpr, checked: bool) -> Expr {
+        let a = self.visit_expr(array);
+        let i = self.visit_expr(index);
+        Expr::Index {
+            array: Box::new(a),
+            index: Box::new(i),
+            checked,
+        }
+    }
+
     fn visit_call(&mut self, name: &str, args: &[TypedExpr]) -> Expr {
         let visited_args: Vec<TypedExpr> = args.iter().map(|a| self.visit_expr(a)).collect();
         Expr::Call {
@@ -120,6 +164,7 @@ pub trait ExprVisitor {
                     body: visited_body,
                 }
             }
+            Stmt::Extern { .. } => stmt.clone(),
             Stmt::Return(expr) => Stmt::Return(self.visit_expr(expr)),
             Stmt::Assignment {
                 name,
@@ -130,6 +175,10 @@ pub trait ExprVisitor {
                 type_ann: type_ann.clone(),
                 value: self.visit_expr(value),
             },
+            Stmt::IndexAssign { target, value } => Stmt::IndexAssign {
+                target: self.visit_expr(target),
+                value: self.visit_expr(value),
+            },
             Stmt::Expr(expr) => Stmt::Expr(self.visit_expr(expr)),
         }
     }
@@ -194,6 +243,10 @@ impl PrettyPrinter {
                 self.indent -= 1;
                 self.output.push_str(&format!("{}}}", self.indent_str()));
             }
+            Stmt::Extern { .. } => {
+                self.output
+                    .push_str(&format!("{}{}", self.indent_str(), stmt));
+            }
             Stmt::Return(expr) => {
                 self.output.push_str(&format!(
                     "{}return {}",
@@ -218,6 +271,14 @@ impl PrettyPrinter {
                     self.format_expr(value)
                 ));
             }
+            Stmt::IndexAssign { target, value } => {
+                self.output.push_str(&format!(
+                    "{}{} = {}",
+                    self.indent_str(),
+                    self.format_expr(target),
+                    self.format_expr(value)
+                ));
+            }
             Stmt::Expr(expr) => {
                 self.output
                     .push_str(&format!("{}{}", self.indent_str(), self.format_expr(expr)));
@@ -228,6 +289,7 @@ impl PrettyPrinter {
     fn format_expr(&self, expr: &TypedExpr) -> String {
         match &expr.expr {
             Expr::Int(n) => n.to_string(),
+            Expr::Float(x) => format!("{:?}", x),
             Expr::Bool(b) => b.to_string(),
             Expr::Var(name) => name.clone(),
             Expr::Unary { op, expr } => format!("{}{}", op, self.format_expr(expr)),
@@ -239,6 +301,16 @@ impl PrettyPrinter {
                     self.format_expr(right)
                 )
             }
+            Expr::Cast { to, expr } => format!("{}({})", to, self.format_expr(expr)),
+            Expr::Array(elements) => {
+                let elements_str: Vec<String> =
+                    elements.iter().map(|e| self.format_expr(e)).collect();
+                format!("[{}]", elements_str.join(", "))
+            }
+            Expr::ArrayRepeat { value, size } => format!("[{}; {}]", self.format_expr(value), size),
+            Expr::Index { array, index, .. } => {
+                format!("{}[{}]", self.format_expr(array), self.format_expr(index))
+            }
             Expr::Call { name, args } => {
                 let args_str: Vec<String> = args.iter().map(|a| self.format_expr(a)).collect();
                 format!("{}({})", name, args_str.join(", "))
@@ -323,6 +395,24 @@ impl ExprVisitor for ConstantFolder {
             }
         }
 
+        // The same for floats
+        if let (Expr::Float(lv), Expr::Float(rv)) = (&l.expr, &r.expr) {
+            let (lv, rv) = (*lv, *rv);
+            match op {
+                BinaryOp::Add => return Expr::Float(lv + rv),
+                BinaryOp::Sub => return Expr::Float(lv - rv),
+                BinaryOp::Mul => return Expr::Float(lv * rv),
+                BinaryOp::Div => return Expr::Float(lv / rv),
+                BinaryOp::Mod => return Expr::Float(lv % rv),
+                BinaryOp::Lt => return Expr::Bool(lv < rv),
+                BinaryOp::Gt => return Expr::Bool(lv > rv),
+                BinaryOp::Le => return Expr::Bool(lv <= rv),
+                BinaryOp::Ge => return Expr::Bool(lv >= rv),
+                BinaryOp::Eq => return Expr::Bool(lv == rv),
+                BinaryOp::Ne => return Expr::Bool(lv != rv),
+            }
+        }
+
         // Can't fold, return as-is
         Expr::Binary {
             op,
@@ -336,6 +426,7 @@ impl ExprVisitor for ConstantFolder {
 
         match (&op, &e.expr) {
             (UnaryOp::Neg, Expr::Int(n)) => Expr::Int(-n),
+            (UnaryOp::Neg, Expr::Float(x)) => Expr::Float(-x),
             (UnaryOp::Not, Expr::Bool(b)) => Expr::Bool(!b),
             _ => Expr::Unary {
                 op,
@@ -343,6 +434,38 @@ impl ExprVisitor for ConstantFolder {
             },
         }
     }
+
+    fn visit_cast(&mut self, to: &Type, expr: &TypedExpr) -> Expr {
+        let e = self.visit_expr(expr);
+
+        // Converts like the generated code: floats round toward zero
+        match (to, &e.expr) {
+            (Type::Int, Expr::Float(x)) => Expr::Int(*x as i64),
+            (Type::Float, Expr::Int(n)) => Expr::Float(*n as f64),
+            _ => Expr::Cast {
+                to: to.clone(),
+                expr: Box::new(e),
+            },
+        }
+    }
+
+    fn visit_index(&mut self, array: &TypedExpr, index: &TypedExpr, checked: bool) -> Expr {
+        let a = self.visit_expr(array);
+        let i = self.visit_expr(index);
+
+        // A constant index within the array needs no bounds check
+        let in_bounds = match (&a.ty, &i.expr) {
+            (Type::Array { size, .. }, Expr::Int(n)) => {
+                usize::try_from(*n).is_ok_and(|n| n < *size)
+            }
+            _ => false,
+        };
+        Expr::Index {
+            array: Box::new(a),
+            index: Box::new(i),
+            checked: checked && !in_bounds,
+        }
+    }
 }
 // ANCHOR_END: constant_folder
 
@@ -464,6 +587,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_constant_folding_floats() {
+        let program = parse_and_check("int(float(3) * 2.5) + 1\n0.5 > -1.5");
+        let folded = ConstantFolder::fold_program(&program);
+
+        if let [Stmt::Expr(sum), Stmt::Expr(cmp)] = &folded[..] {
+            assert_eq!(sum.expr, Expr::Int(8));
+            assert_eq!(cmp.expr, Expr::Bool(true));
+        } else {
+            panic!("Expected two expressions");
+        }
+    }
+
+    #[test]
+    fn test_constant_folding_elides_bounds_checks() {
+        let program = parse_and_check("a = [1, 2, 3]\na[1 + 1]\na[3]\nn = 0\na[n]");
+        let folded = ConstantFolder::fold_program(&program);
+
+        let checked: Vec<bool> = folded
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Expr(TypedExpr {
+                    expr: Expr::Index { checked, .. },
+                    ..
+                }) => Some(*checked),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(checked, vec![false, true, true]);
+    }
+
     #[test]
     fn test_algebraic_simplification_add_zero() {
         let program = parse_and_check("def test(x: int) -> int { return x + 0 }");
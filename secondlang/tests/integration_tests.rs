@@ -2,8 +2,20 @@
 //!
 //! These tests verify the full compilation pipeline:
 //! parsing -> type checking -> LLVM codegen -> JIT execution
+//! and ahead-of-time compilation to native executables
 
-use secondlang::{compile_to_ir, parse, run, typecheck};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use inkwell::context::Context;
+use inkwell::OptimizationLevel;
+use secondlang::{
+    build, compile_module, compile_to_ir, compile_to_ir_with_opts, parse, run, run_optimized,
+    run_with_host, run_with_opts, typecheck, Emit, HostFunctions, LlvmOpts,
+};
 
 // =============================================================================
 // Type Checking Tests
@@ -81,6 +93,34 @@ fn test_typecheck_wrong_argument_type() {
     assert!(result.is_err());
 }
 
+#[test]
+fn test_typecheck_inferred_function() {
+    let source = r#"
+        def add(a, b) {
+            return a + b
+        }
+        add(1, 2)
+    "#;
+    typecheck_source(source).unwrap();
+}
+
+#[test]
+fn test_typecheck_inferred_argument_mismatch() {
+    let source = r#"
+        def add(a, b) {
+            return a + b
+        }
+        add(1, true)
+    "#;
+    let err = typecheck_source(source).unwrap_err();
+    assert!(err.contains("argument 2 of `add(1, true)`"), "{}", err);
+    assert!(
+        err.contains("inferred from argument 1 of `add(1, true)`"),
+        "{}",
+        err
+    );
+}
+
 // =============================================================================
 // LLVM IR Generation Tests
 // =============================================================================
@@ -111,6 +151,30 @@ fn test_compile_add() {
     assert!(ir.contains("add"));
 }
 
+#[test]
+fn test_compile_float_function() {
+    let source = r#"
+        def half(x: float) -> float {
+            return x / 2.0
+        }
+        int(half(3.0))
+    "#;
+    let ir = compile_to_ir(source).unwrap();
+    assert!(ir.contains("define double @half(double"), "{}", ir);
+    assert!(ir.contains("fdiv double"), "{}", ir);
+    assert!(ir.contains("fptosi double"), "{}", ir);
+}
+
+#[test]
+fn test_compile_bounds_checks() {
+    // Folding proves the constant index is in bounds
+    let source = "a = [1, 2, 3]\na[2]";
+    let checked = compile_to_ir(source).unwrap();
+    assert!(checked.contains("out_of_bounds"), "{}", checked);
+    let folded = compile_to_ir_with_opts(source, true, &LlvmOpts::default()).unwrap();
+    assert!(!folded.contains("out_of_bounds"), "{}", folded);
+}
+
 #[test]
 fn test_compile_fibonacci() {
     let source = r#"
@@ -128,6 +192,93 @@ fn test_compile_fibonacci() {
     assert!(ir.contains("call i64 @fib")); // Recursive call
 }
 
+// =============================================================================
+// LLVM Optimization Tests
+// =============================================================================
+
+fn passes(pipeline: &str) -> LlvmOpts {
+    LlvmOpts {
+        passes: Some(pipeline.to_string()),
+        ..LlvmOpts::default()
+    }
+}
+
+#[test]
+fn test_mem2reg_promotes_allocas() {
+    let source = r#"
+        def sum_to(n: int) -> int {
+            total = 0
+            i = 1
+            while (i <= n) {
+                total = total + i
+                i = i + 1
+            }
+            return total
+        }
+        sum_to(10)
+    "#;
+
+    // Every parameter and variable gets a stack slot from create_entry_block_alloca
+    let unopt_ir = compile_to_ir(source).unwrap();
+    assert!(unopt_ir.contains("alloca"), "{}", unopt_ir);
+
+    // mem2reg turns them into SSA registers, with phis for the loop variables
+    let opt_ir = compile_to_ir_with_opts(source, false, &passes("mem2reg")).unwrap();
+    assert!(!opt_ir.contains("alloca"), "{}", opt_ir);
+    assert!(opt_ir.contains("phi i64"), "{}", opt_ir);
+
+    assert_eq!(run_optimized(source, "mem2reg,instcombine").unwrap(), 55);
+}
+
+#[test]
+fn test_default_o2_pipeline() {
+    let source = r#"
+        def square(n: int) -> int {
+            return n * n
+        }
+        square(3) + square(4)
+    "#;
+
+    let opts = LlvmOpts::level(OptimizationLevel::Default);
+    assert_eq!(opts.passes.as_deref(), Some("default<O2>"));
+
+    // Inlining and constant folding leave just the result
+    let ir = compile_to_ir_with_opts(source, false, &opts).unwrap();
+    assert!(ir.contains("ret i64 25"), "{}", ir);
+    assert_eq!(run_with_opts(source, false, &opts).unwrap(), 25);
+}
+
+#[test]
+fn test_optimization_levels_match_unoptimized() {
+    let examples = concat!(env!("CARGO_MANIFEST_DIR"), "/examples");
+    for entry in fs::read_dir(examples).unwrap() {
+        let path = entry.unwrap().path();
+        let source = fs::read_to_string(&path).unwrap();
+        let expected = run(&source).unwrap();
+        for level in [
+            OptimizationLevel::None,
+            OptimizationLevel::Less,
+            OptimizationLevel::Default,
+            OptimizationLevel::Aggressive,
+        ] {
+            let opts = LlvmOpts::level(level);
+            assert_eq!(
+                run_with_opts(&source, false, &opts).unwrap(),
+                expected,
+                "{} with {:?}",
+                path.display(),
+                level
+            );
+        }
+    }
+}
+
+#[test]
+fn test_invalid_pass_pipeline() {
+    let err = compile_to_ir_with_opts("1 + 2", false, &passes("not-a-pass")).unwrap_err();
+    assert!(err.contains("Failed to run passes"), "{}", err);
+}
+
 // =============================================================================
 // JIT Execution Tests
 // =============================================================================
@@ -256,6 +407,21 @@ fn test_jit_multiple_functions() {
     assert_eq!(run(source).unwrap(), 20);
 }
 
+#[test]
+fn test_jit_inferred_fibonacci() {
+    let source = r#"
+        def fib(n) {
+            if (n < 2) {
+                return n
+            } else {
+                return fib(n - 1) + fib(n - 2)
+            }
+        }
+        fib(10)
+    "#;
+    assert_eq!(run(source).unwrap(), 55);
+}
+
 #[test]
 fn test_jit_gcd() {
     let source = r#"
@@ -286,3 +452,571 @@ fn test_jit_power() {
     "#;
     assert_eq!(run(source).unwrap(), 1024);
 }
+
+// =============================================================================
+// Floating-point Tests
+// =============================================================================
+
+#[test]
+fn test_jit_float_arithmetic() {
+    assert_eq!(run("int(2.5 * 4.0)").unwrap(), 10);
+    assert_eq!(run("int(7.0 / 2.0 - 0.25 + 10.5 % 4.0)").unwrap(), 5);
+    assert_eq!(run("int(-7.9)").unwrap(), -7);
+}
+
+#[test]
+fn test_jit_float_comparisons() {
+    assert_eq!(run("x = 0.1 + 0.2\nx > 0.29").unwrap(), 1);
+    assert_eq!(run("x = 0.1 + 0.2\nx == 0.3").unwrap(), 0);
+    assert_eq!(run("1.5 != 1.5").unwrap(), 0);
+}
+
+#[test]
+fn test_jit_inferred_float_function() {
+    let source = r#"
+        def average(a, b) {
+            return (a + b) / 2.0
+        }
+        int(average(float(3), 8.0) * 10.0)
+    "#;
+    assert_eq!(run(source).unwrap(), 55);
+}
+
+#[test]
+fn test_jit_floats_example() {
+    let source = include_str!("../examples/floats.sl");
+    assert_eq!(run(source).unwrap(), 1414213);
+}
+
+#[test]
+fn test_jit_float_result_needs_conversion() {
+    let err = run("1.5 * 2.0").unwrap_err();
+    assert!(err.contains("convert it with int(...)"), "{}", err);
+}
+
+#[test]
+fn test_jit_branch_variables_of_different_types() {
+    let source = r#"
+        def g(c: bool) -> int {
+            if (c) {
+                x = 1
+                x
+            } else {
+                x = 2.5
+                int(x + 1.0)
+            }
+        }
+        g(true) * 10 + g(false)
+    "#;
+    assert_eq!(run(source).unwrap(), 13);
+}
+
+#[test]
+fn test_jit_float_function_falling_off_loop() {
+    let source = r#"
+        def first_positive(x: float) -> float {
+            while (x > 0.0) {
+                return x
+            }
+        }
+        int(first_positive(2.5)) + int(first_positive(0.0 - 1.0))
+    "#;
+    assert_eq!(run(source).unwrap(), 2);
+}
+
+// =============================================================================
+// Array Tests
+// =============================================================================
+
+#[test]
+fn test_jit_array_sum() {
+    let source = r#"
+        def sum(xs: [int; 5]) -> int {
+            total = 0
+            i = 0
+            while (i < len(xs)) {
+                total = total + xs[i]
+                i = i + 1
+            }
+            return total
+        }
+        sum([1, 2, 3, 4, 5])
+    "#;
+    assert_eq!(run(source).unwrap(), 15);
+}
+
+#[test]
+fn test_jit_nested_arrays() {
+    let source = r#"
+        grid = [[0; 3]; 2]
+        grid[1][2] = 7
+        grid[0][1] = grid[1][2] * 2
+        row = grid[0]
+        row[1] + grid[1][2] + len(grid) * 100
+    "#;
+    assert_eq!(run(source).unwrap(), 221);
+}
+
+#[test]
+fn test_jit_arrays_are_values() {
+    // Assignments and calls copy arrays
+    let source = r#"
+        def set_first(xs: [int; 3]) -> int {
+            xs[0] = 100
+            return xs[0]
+        }
+        a = [1, 2, 3]
+        b = a
+        b[0] = 10
+        set_first(a) + a[0] + b[0]
+    "#;
+    assert_eq!(run(source).unwrap(), 111);
+}
+
+#[test]
+fn test_jit_float_array() {
+    assert_eq!(
+        run("xs = [0.5; 4]\nxs[3] = 2.0\nint(xs[0] + xs[3])").unwrap(),
+        2
+    );
+}
+
+#[test]
+fn test_jit_arrays_example() {
+    let source = include_str!("../examples/arrays.sl");
+    assert_eq!(run(source).unwrap(), 25);
+}
+
+// =============================================================================
+// Top-level Statement Tests
+// =============================================================================
+
+#[test]
+fn test_jit_top_level_globals() {
+    let source = r#"
+        def square(n: int) -> int {
+            return n * n
+        }
+        x = 6
+        y = square(x) + 1
+        y - x
+    "#;
+    assert_eq!(run(source).unwrap(), 31);
+}
+
+#[test]
+fn test_jit_ends_with_assignment() {
+    let source = r#"
+        x = 42
+        y = x * 2
+    "#;
+    assert_eq!(run(source).unwrap(), 84);
+}
+
+#[test]
+fn test_jit_top_level_loop() {
+    let source = r#"
+        total = 0
+        i = 1
+        while (i <= 10) {
+            total = total + i
+            i = i + 1
+        }
+        total
+    "#;
+    assert_eq!(run(source).unwrap(), 55);
+}
+
+#[test]
+fn test_jit_inference_example() {
+    let source = include_str!("../examples/inference.sl");
+    assert_eq!(run(source).unwrap(), 111);
+}
+
+#[test]
+fn test_jit_nothing_to_run() {
+    let source = r#"
+        def answer() -> int {
+            return 42
+        }
+    "#;
+    let err = run(source).unwrap_err();
+    assert!(err.contains("no top-level statements"), "{}", err);
+}
+
+// =============================================================================
+// Ahead-of-time Compilation Tests
+// =============================================================================
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("secondlang-test-{}-{}", std::process::id(), name))
+}
+
+/// Build an executable and return what it prints
+fn build_and_run(source: &str, name: &str) -> String {
+    let exe = temp_path(name);
+    build(source, &exe, Emit::Exe, false, &LlvmOpts::default()).unwrap();
+    let output = Command::new(&exe).output().unwrap();
+    let _ = fs::remove_file(&exe);
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_aot_matches_jit_for_examples() {
+    let examples = concat!(env!("CARGO_MANIFEST_DIR"), "/examples");
+    for entry in fs::read_dir(examples).unwrap() {
+        let path = entry.unwrap().path();
+        let source = fs::read_to_string(&path).unwrap();
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        assert_eq!(
+            build_and_run(&source, name),
+            format!("{}\n", run(&source).unwrap()),
+            "{}",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn test_aot_out_of_bounds_aborts() {
+    let exe = temp_path("bounds");
+    let source = "a = [1, 2, 3]\ni = 3\na[i]";
+    build(source, &exe, Emit::Exe, false, &LlvmOpts::default()).unwrap();
+    let output = Command::new(&exe).output().unwrap();
+    let _ = fs::remove_file(&exe);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Index out of bounds in `a[i]`: the index is 3 but the length is 3\n"
+    );
+}
+
+#[test]
+fn test_aot_negative_result() {
+    assert_eq!(build_and_run("x = 5\nx - 47", "negative"), "-42\n");
+}
+
+#[test]
+fn test_aot_emit_object_and_assembly() {
+    let source = r#"
+        def square(n: int) -> int {
+            return n * n
+        }
+        square(7)
+    "#;
+
+    let object = temp_path("square.o");
+    build(source, &object, Emit::Obj, false, &LlvmOpts::default()).unwrap();
+    assert!(fs::metadata(&object).unwrap().len() > 0);
+    let _ = fs::remove_file(&object);
+
+    let assembly = temp_path("square.s");
+    build(source, &assembly, Emit::Asm, false, &LlvmOpts::default()).unwrap();
+    let listing = fs::read_to_string(&assembly).unwrap();
+    let _ = fs::remove_file(&assembly);
+    assert!(listing.contains("square"));
+    assert!(listing.contains("__main"));
+}
+
+#[test]
+fn test_aot_requires_top_level_statements() {
+    let source = r#"
+        def answer() -> int {
+            return 42
+        }
+    "#;
+    let err = build(
+        source,
+        &temp_path("nothing"),
+        Emit::Exe,
+        false,
+        &LlvmOpts::default(),
+    )
+    .unwrap_err();
+    assert!(err.contains("no top-level statements"), "{}", err);
+}
+
+// =============================================================================
+// Print Tests
+// =============================================================================
+
+/// Run a program with the `secondlang` command, which JIT compiles it, and
+/// return what it prints: the output of the program, then its result
+fn run_and_capture(source: &str, name: &str) -> String {
+    let path = temp_path(&format!("{}.sl", name));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_secondlang"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    let _ = fs::remove_file(&path);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_print_values() {
+    let source = r#"
+        print(42)
+        print(0 - 7)
+        print(1 < 2)
+        print(false)
+        print(2.5)
+        7
+    "#;
+    assert_eq!(
+        run_and_capture(source, "print-values"),
+        "42\n-7\ntrue\nfalse\n2.5\n7\n"
+    );
+}
+
+#[test]
+fn test_print_in_loop() {
+    let source = r#"
+        def countdown(n: int) -> int {
+            while (n > 0) {
+                print(n)
+                n = n - 1
+            }
+            return n
+        }
+        countdown(3)
+    "#;
+    assert_eq!(run_and_capture(source, "print-loop"), "3\n2\n1\n0\n");
+}
+
+#[test]
+fn test_print_aot_matches_jit() {
+    let source = "x = 6\nprint(x * 7)\nprint(x > 10)\nx";
+    assert_eq!(build_and_run(source, "print-aot"), "42\nfalse\n6\n");
+    assert_eq!(run_and_capture(source, "print-jit"), "42\nfalse\n6\n");
+}
+
+#[test]
+fn test_print_returns_unit() {
+    // A program ending with print returns 0, like one with no value
+    assert_eq!(run("print(1)").unwrap(), 0);
+}
+
+// =============================================================================
+// Extern Function Tests
+// =============================================================================
+
+#[test]
+fn test_extern_jit() {
+    let source = r#"
+        extern def labs(x: int) -> int
+        labs(0 - 42)
+    "#;
+    assert_eq!(run(source).unwrap(), 42);
+}
+
+#[test]
+fn test_extern_without_result() {
+    let source = r#"
+        extern def srand(seed: int)
+        srand(7)
+        1
+    "#;
+    assert_eq!(run(source).unwrap(), 1);
+}
+
+#[test]
+fn test_extern_ir_is_declaration() {
+    let ir = compile_to_ir("extern def labs(x: int) -> int\nlabs(1)").unwrap();
+    assert!(ir.contains("declare i64 @labs(i64)"), "{}", ir);
+}
+
+#[test]
+fn test_extern_aot() {
+    let source = r#"
+        extern def labs(x: int) -> int
+        def distance(a: int, b: int) -> int {
+            return labs(a - b)
+        }
+        distance(3, 45)
+    "#;
+    assert_eq!(build_and_run(source, "extern"), "42\n");
+}
+
+#[test]
+fn test_extern_call_type_mismatch() {
+    let mut program = parse("extern def labs(x: int) -> int\nlabs(true)").unwrap();
+    let err = typecheck(&mut program).unwrap_err();
+    assert!(err.contains("labs"), "{}", err);
+}
+
+// =============================================================================
+// Host Function Tests
+// =============================================================================
+
+extern "C" fn host_twice(n: i64) -> i64 {
+    n * 2
+}
+
+extern "C" fn host_hypot(x: f64, y: f64) -> f64 {
+    x.hypot(y)
+}
+
+extern "C" fn host_count_true(a: bool, b: bool) -> i64 {
+    a as i64 + b as i64
+}
+
+static RECORDED: AtomicI64 = AtomicI64::new(0);
+
+extern "C" fn host_record(n: i64) {
+    RECORDED.fetch_add(n, Ordering::SeqCst);
+}
+
+#[test]
+fn test_host_function_call() {
+    let mut host = HostFunctions::new();
+    host.register("twice", host_twice as extern "C" fn(i64) -> i64);
+    assert_eq!(run_with_host("twice(21)", &host).unwrap(), 42);
+}
+
+#[test]
+fn test_host_function_float() {
+    let mut host = HostFunctions::new();
+    host.register("hypot", host_hypot as extern "C" fn(f64, f64) -> f64);
+    assert_eq!(run_with_host("int(hypot(3.0, 4.0))", &host).unwrap(), 5);
+}
+
+#[test]
+fn test_host_function_bool_arguments() {
+    let mut host = HostFunctions::new();
+    host.register(
+        "count_true",
+        host_count_true as extern "C" fn(bool, bool) -> i64,
+    );
+    let source = "count_true(1 < 2, false) * 10 + count_true(true, 3 > 2)";
+    assert_eq!(run_with_host(source, &host).unwrap(), 12);
+}
+
+#[test]
+fn test_host_function_reads_host_data() {
+    let source = r#"
+        i = 1
+        while (i <= 4) {
+            record(i)
+            i = i + 1
+        }
+        i
+    "#;
+    let mut host = HostFunctions::new();
+    host.register("record", host_record as extern "C" fn(i64));
+    assert_eq!(run_with_host(source, &host).unwrap(), 5);
+    assert_eq!(RECORDED.load(Ordering::SeqCst), 10);
+}
+
+#[test]
+fn test_host_function_type_error() {
+    let mut host = HostFunctions::new();
+    host.register("twice", host_twice as extern "C" fn(i64) -> i64);
+    let err = run_with_host("twice(1.5)", &host).unwrap_err();
+    assert!(err.contains("twice"), "{}", err);
+    // Programs run without the host do not know the function
+    assert!(run("twice(21)").is_err());
+}
+
+#[test]
+fn test_host_function_cannot_be_redefined() {
+    let mut host = HostFunctions::new();
+    host.register("twice", host_twice as extern "C" fn(i64) -> i64);
+    let source = "def twice(n: float) -> float { return n + n }\n1";
+    let err = run_with_host(source, &host).unwrap_err();
+    assert_eq!(err, "Function twice is defined more than once");
+}
+
+// =============================================================================
+// Compiled Module Tests
+// =============================================================================
+
+#[test]
+fn test_module_call_function() {
+    let source = r#"
+        def add(a: int, b: int) -> int {
+            return a + b
+        }
+    "#;
+    let context = Context::create();
+    let module = compile_module(&context, source).unwrap();
+    let add = module.get::<fn(i64, i64) -> i64>("add").unwrap();
+    assert_eq!(add.call(2, 3), 5);
+    assert_eq!(add.call(-10, 4), -6);
+}
+
+#[test]
+fn test_module_call_repeatedly() {
+    let source = r#"
+        def fib(n) {
+            if (n < 2) {
+                return n
+            } else {
+                return fib(n - 1) + fib(n - 2)
+            }
+        }
+    "#;
+    let context = Context::create();
+    let module = compile_module(&context, source).unwrap();
+    let fib = module.get::<fn(i64) -> i64>("fib").unwrap();
+    let values: Vec<i64> = (0..10).map(|n| fib.call(n)).collect();
+    assert_eq!(values, vec![0, 1, 1, 2, 3, 5, 8, 13, 21, 34]);
+}
+
+#[test]
+fn test_module_float_and_bool_functions() {
+    let source = r#"
+        def average(a: float, b: float) -> float {
+            return (a + b) / 2.0
+        }
+        def is_even(n: int) {
+            return n % 2 == 0
+        }
+    "#;
+    let context = Context::create();
+    let module = compile_module(&context, source).unwrap();
+    let average = module.get::<fn(f64, f64) -> f64>("average").unwrap();
+    assert_eq!(average.call(1.0, 4.0), 2.5);
+    let is_even = module.get::<fn(i64) -> bool>("is_even").unwrap();
+    assert!(is_even.call(4));
+    assert!(!is_even.call(7));
+}
+
+#[test]
+fn test_module_signature_mismatch() {
+    let context = Context::create();
+    let module = compile_module(&context, "def neg(n: int) -> int { return 0 - n }").unwrap();
+    let err = module.get::<fn(i64, i64) -> i64>("neg").err().unwrap();
+    assert_eq!(
+        err,
+        "Function neg has type (int) -> int, not (int, int) -> int"
+    );
+    let err = module.get::<fn(i64) -> f64>("neg").err().unwrap();
+    assert!(err.contains("not (int) -> float"), "{}", err);
+    let err = module.get::<fn(i64) -> i64>("abs").err().unwrap();
+    assert_eq!(err, "Undefined function: abs");
+}
+
+#[test]
+fn test_module_run_main() {
+    let source = r#"
+        def square(n: int) -> int {
+            return n * n
+        }
+        square(6)
+    "#;
+    let context = Context::create();
+    let module = compile_module(&context, source).unwrap();
+    assert_eq!(module.run_main().unwrap(), 36);
+    assert_eq!(module.get::<fn(i64) -> i64>("square").unwrap().call(7), 49);
+
+    let module = compile_module(&context, "def one() -> int { return 1 }").unwrap();
+    let err = module.run_main().unwrap_err();
+    assert!(err.contains("no top-level statements"), "{}", err);
+}